@@ -0,0 +1,360 @@
+//! Optional HTTP listener serving a read-only JSON view of what a running
+//! Aquila is doing: connected actions, tracked runtimes, the flow store,
+//! in-flight executions (all of them, or one by id), Sagittarius stream states and the effective
+//! configuration. Dead-lettered executions can also be re-driven from here,
+//! but only with `admin.redrive_token` set and sent as a bearer token.
+//! Runs on its own port next to the gRPC server and stops with it once the
+//! drain coordinator is closed.
+
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+
+use async_nats::jetstream::kv::Store;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
+    routing::{get, post},
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    configuration::config::Config,
+    flow::{
+        dead_letters::{
+            DeadLetterDetail, DeadLetterError, DeadLetterPage, DeadLetterSummary, DeadLetters,
+        },
+        executions::ExecutionSnapshot,
+    },
+    sagittarius::stream_state::{SagittariusStreamStates, StreamStateSnapshot},
+    server::{ActionConnectionSnapshot, Drain, ServerDiagnostics, TrackedRuntimeSnapshot},
+    telemetry::errors,
+};
+
+/// Everything the admin API reports on, collected by the run mode that
+/// started it.
+#[derive(Clone)]
+pub struct AdminState {
+    pub server: ServerDiagnostics,
+    pub kv: Store,
+    /// `None` in static mode, where there are no Sagittarius streams.
+    pub sagittarius_streams: Option<SagittariusStreamStates>,
+    pub dead_letters: DeadLetters,
+    /// The effective configuration, rendered once at startup via its `Display` impl.
+    pub config: String,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+struct FlowStoreSummary {
+    total: usize,
+    /// Flow count per project id, taken from the flow's KV key.
+    projects: BTreeMap<String, usize>,
+}
+
+#[derive(Serialize)]
+struct RenderedConfig {
+    rendered: String,
+}
+
+#[derive(Serialize)]
+struct AdminError {
+    error: String,
+}
+
+/// Spawns the admin listener if `admin.enabled` is set. A listener that
+/// fails to bind is logged and otherwise ignored; diagnostics are not worth
+/// taking the gateway down for.
+pub fn spawn(config: &Config, state: AdminState, drain: Drain) {
+    if !config.admin.enabled {
+        return;
+    }
+
+    let address = match format!("{}:{}", config.admin.host, config.admin.port).parse() {
+        Ok(address) => address,
+        Err(err) => {
+            errors::record(
+                "configuration",
+                "admin.address",
+                &err,
+                format!("host={} port={}", config.admin.host, config.admin.port),
+            );
+            return;
+        }
+    };
+
+    let redrive_token = Some(config.admin.redrive_token.as_str())
+        .filter(|token| !token.is_empty())
+        .map(Arc::from);
+    tokio::spawn(async move {
+        if let Err(err) = serve(address, state, redrive_token, drain).await {
+            errors::record("server", "admin.serve", &err, format!("address={address}"));
+        }
+    });
+}
+
+async fn serve(
+    address: SocketAddr,
+    state: AdminState,
+    redrive_token: Option<Arc<str>>,
+    drain: Drain,
+) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    log::info!(
+        "Admin API listening address={} redrive_enabled={}",
+        address,
+        redrive_token.is_some()
+    );
+
+    axum::serve(listener, router(state, redrive_token))
+        .with_graceful_shutdown(async move { drain.closed().await })
+        .await?;
+
+    log::info!("Admin API stopped");
+    Ok(())
+}
+
+/// Every read-only route, plus re-driving dead letters if a `redrive_token`
+/// is configured.
+fn router(state: AdminState, redrive_token: Option<Arc<str>>) -> Router {
+    let router = Router::new()
+        .route("/actions", get(actions))
+        .route("/runtimes", get(runtimes))
+        .route("/flows", get(flows))
+        .route("/executions", get(executions))
+        .route("/executions/{execution_id}", get(execution))
+        .route("/sagittarius", get(sagittarius))
+        .route("/config", get(config))
+        .route("/dead-letters", get(dead_letters))
+        .route("/dead-letters/{sequence}", get(dead_letter));
+    let router = match redrive_token {
+        Some(token) => router.route(
+            "/dead-letters/{sequence}/redrive",
+            post(
+                move |state: State<AdminState>, headers: HeaderMap, sequence: Path<u64>| {
+                    redrive_dead_letter(state, headers, sequence, token)
+                },
+            ),
+        ),
+        None => router,
+    };
+    router.with_state(state)
+}
+
+async fn actions(State(state): State<AdminState>) -> Json<Vec<ActionConnectionSnapshot>> {
+    Json(state.server.actions().await)
+}
+
+async fn runtimes(State(state): State<AdminState>) -> Json<Option<Vec<TrackedRuntimeSnapshot>>> {
+    Json(state.server.runtimes().await)
+}
+
+async fn flows(
+    State(state): State<AdminState>,
+) -> Result<Json<FlowStoreSummary>, (StatusCode, Json<AdminError>)> {
+    let mut keys = match state.kv.keys().await {
+        Ok(keys) => keys.boxed(),
+        Err(err) => {
+            errors::record("flow_storage", "admin.flows", &err, "");
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(AdminError {
+                    error: "flow store is not available".into(),
+                }),
+            ));
+        }
+    };
+
+    let mut summary = FlowStoreSummary::default();
+    while let Some(key) = keys.next().await {
+        match key {
+            Ok(key) => summary.count(&key),
+            Err(err) => {
+                errors::record("flow_storage", "admin.flows", &err, "");
+                break;
+            }
+        }
+    }
+
+    Ok(Json(summary))
+}
+
+async fn executions(State(state): State<AdminState>) -> Json<Vec<ExecutionSnapshot>> {
+    Json(state.server.executions().await)
+}
+
+async fn execution(
+    State(state): State<AdminState>,
+    Path(execution_id): Path<String>,
+) -> Result<Json<ExecutionSnapshot>, (StatusCode, Json<AdminError>)> {
+    state
+        .server
+        .execution(&execution_id)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(AdminError {
+                    error: format!("execution {execution_id} is not in flight"),
+                }),
+            )
+        })
+}
+
+async fn sagittarius(State(state): State<AdminState>) -> Json<Option<Vec<StreamStateSnapshot>>> {
+    Json(
+        state
+            .sagittarius_streams
+            .as_ref()
+            .map(SagittariusStreamStates::snapshot),
+    )
+}
+
+async fn config(State(state): State<AdminState>) -> Json<RenderedConfig> {
+    Json(RenderedConfig {
+        rendered: state.config.clone(),
+    })
+}
+
+/// Where `GET /dead-letters` starts listing: after sequence `after`, or at
+/// the oldest entry.
+#[derive(Debug, Default, Deserialize)]
+struct DeadLetterCursor {
+    #[serde(default)]
+    after: u64,
+}
+
+async fn dead_letters(
+    State(state): State<AdminState>,
+    Query(cursor): Query<DeadLetterCursor>,
+) -> Result<Json<DeadLetterPage>, (StatusCode, Json<AdminError>)> {
+    state
+        .dead_letters
+        .list(cursor.after)
+        .await
+        .map(Json)
+        .map_err(dead_letter_error)
+}
+
+async fn dead_letter(
+    State(state): State<AdminState>,
+    Path(sequence): Path<u64>,
+) -> Result<Json<DeadLetterDetail>, (StatusCode, Json<AdminError>)> {
+    state
+        .dead_letters
+        .inspect(sequence)
+        .await
+        .map(Json)
+        .map_err(dead_letter_error)
+}
+
+async fn redrive_dead_letter(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+    Path(sequence): Path<u64>,
+    token: Arc<str>,
+) -> Result<Json<DeadLetterSummary>, (StatusCode, Json<AdminError>)> {
+    if !is_authorized(&headers, &token) {
+        log::warn!(
+            "Rejected unauthorized dead letter redrive sequence={}",
+            sequence
+        );
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(AdminError {
+                error: "a valid bearer token is required".into(),
+            }),
+        ));
+    }
+
+    state
+        .dead_letters
+        .redrive(sequence)
+        .await
+        .map(Json)
+        .map_err(dead_letter_error)
+}
+
+/// Whether `headers` carry `token` as a bearer token. Compared without
+/// stopping at the first difference, so timing doesn't reveal the token.
+fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+    let Some(presented) = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+
+    presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (left, right)| difference | (left ^ right))
+            == 0
+}
+
+fn dead_letter_error(err: DeadLetterError) -> (StatusCode, Json<AdminError>) {
+    let status = match &err {
+        DeadLetterError::Unavailable | DeadLetterError::Failed(_) => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        DeadLetterError::NotFound => StatusCode::NOT_FOUND,
+        DeadLetterError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+    };
+    (
+        status,
+        Json(AdminError {
+            error: err.to_string(),
+        }),
+    )
+}
+
+impl FlowStoreSummary {
+    /// Counts a flow key of the form `<type>.<slug>.<project_id>.<flow_id>`.
+    /// The slug may itself contain dots, so the project id is read from the end.
+    fn count(&mut self, key: &str) {
+        self.total += 1;
+
+        let mut segments = key.rsplitn(3, '.');
+        let project_id = match (segments.next(), segments.next(), segments.next()) {
+            (Some(_flow_id), Some(project_id), Some(_)) => project_id,
+            _ => "unknown",
+        };
+        *self.projects.entry(project_id.to_string()).or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flow_store_summary_counts_per_project() {
+        let mut summary = FlowStoreSummary::default();
+        summary.count("REST.my-project.1.10");
+        summary.count("REST.my.dotted.project.1.11");
+        summary.count("CRON.other.2.12");
+        summary.count("malformed");
+
+        assert_eq!(summary.total, 4);
+        assert_eq!(summary.projects.get("1"), Some(&2));
+        assert_eq!(summary.projects.get("2"), Some(&1));
+        assert_eq!(summary.projects.get("unknown"), Some(&1));
+    }
+
+    #[test]
+    fn redrive_requires_the_configured_bearer_token() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, value.parse().expect("header value"));
+            headers
+        };
+
+        assert!(is_authorized(&headers("Bearer secret"), "secret"));
+        assert!(!is_authorized(&headers("Bearer secrets"), "secret"));
+        assert!(!is_authorized(&headers("Bearer other"), "secret"));
+        assert!(!is_authorized(&headers("secret"), "secret"));
+        assert!(!is_authorized(&HeaderMap::new(), "secret"));
+    }
+}
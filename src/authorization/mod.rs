@@ -3,6 +3,7 @@
 //! outgoing request, [`authorization::extract_token`] to read one back off
 //! an incoming request.
 
+#[allow(clippy::module_inception)]
 pub mod authorization {
     use std::str::FromStr;
     use tonic::{
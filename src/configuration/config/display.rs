@@ -6,7 +6,7 @@
 
 use std::fmt;
 
-use super::Config;
+use super::{Config, DispatchMode};
 
 impl fmt::Display for Config {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -39,6 +39,26 @@ impl fmt::Display for Config {
         writeln!(formatter, "  NATS")?;
         writeln!(formatter, "    URL:       {}", self.nats.url)?;
         writeln!(formatter, "    Bucket:    {}", self.nats.bucket)?;
+        writeln!(formatter, "    Module bucket: {}", self.nats.module_bucket)?;
+        writeln!(
+            formatter,
+            "    Configuration bucket: {}",
+            self.nats.configuration_bucket
+        )?;
+        writeln!(
+            formatter,
+            "    Event key bucket: {}",
+            self.nats.event_key_bucket
+        )?;
+        if self.nats.dead_letter_stream.is_empty() {
+            writeln!(formatter, "    Dead-letter stream: <disabled>")?;
+        } else {
+            writeln!(
+                formatter,
+                "    Dead-letter stream: {} (max age {}s)",
+                self.nats.dead_letter_stream, self.nats.dead_letter_max_age_secs
+            )?;
+        }
         writeln!(formatter, "  gRPC")?;
         writeln!(
             formatter,
@@ -50,6 +70,44 @@ impl fmt::Display for Config {
             "    Health service: {}",
             self.grpc.health_service
         )?;
+        if self.grpc.keepalive_interval_secs > 0 {
+            writeln!(
+                formatter,
+                "    Keepalive: every {}s, timeout {}s",
+                self.grpc.keepalive_interval_secs, self.grpc.keepalive_timeout_secs
+            )?;
+        } else {
+            writeln!(formatter, "    Keepalive: <disabled>")?;
+        }
+        writeln!(formatter, "  Admin API")?;
+        if self.admin.enabled {
+            writeln!(
+                formatter,
+                "    Address:   {}:{}",
+                self.admin.host, self.admin.port
+            )?;
+            writeln!(
+                formatter,
+                "    Redrive:   {}",
+                if self.admin.redrive_token.is_empty() {
+                    "<disabled>"
+                } else {
+                    "[FILTERED]"
+                }
+            )?;
+        } else {
+            writeln!(formatter, "    Address:   <disabled>")?;
+        }
+        writeln!(formatter, "  Prometheus")?;
+        if self.prometheus.enabled {
+            writeln!(
+                formatter,
+                "    Address:   {}:{}/metrics",
+                self.prometheus.host, self.prometheus.port
+            )?;
+        } else {
+            writeln!(formatter, "    Address:   <disabled>")?;
+        }
         writeln!(formatter, "  Static mode")?;
         writeln!(formatter, "    Flow path: {}", self.static_config.flow_path)?;
         writeln!(formatter, "  Dynamic mode")?;
@@ -64,6 +122,91 @@ impl fmt::Display for Config {
             "    Request timeout: {}s",
             self.dynamic_config.backend_unary_timeout_secs
         )?;
+        writeln!(formatter, "  Execution dispatch")?;
+        writeln!(formatter, "    Mode:      {}", self.execution_dispatch.mode)?;
+        if self.execution_dispatch.mode == DispatchMode::JetStream {
+            writeln!(
+                formatter,
+                "    Stream:    {} (subjects {}.*)",
+                self.execution_dispatch.stream, self.execution_dispatch.subject_prefix
+            )?;
+            writeln!(
+                formatter,
+                "    Attempts:  {} ({}ms backoff)",
+                self.execution_dispatch.max_attempts, self.execution_dispatch.retry_backoff_ms
+            )?;
+        }
+        writeln!(formatter, "  Output validation")?;
+        writeln!(formatter, "    Mode:      {}", self.output_validation.mode)?;
+        writeln!(formatter, "  Action execution")?;
+        writeln!(
+            formatter,
+            "    Timeout:   {}s",
+            self.action_execution.timeout_secs
+        )?;
+        writeln!(
+            formatter,
+            "    Flow timeout: {}s",
+            self.action_execution.flow_timeout_secs
+        )?;
+        writeln!(formatter, "  Action sessions")?;
+        if self.action_session.resume_grace_period_secs > 0 {
+            writeln!(
+                formatter,
+                "    Resume grace period: {}s",
+                self.action_session.resume_grace_period_secs
+            )?;
+        } else {
+            writeln!(formatter, "    Resume grace period: <disabled>")?;
+        }
+        writeln!(formatter, "  Action events")?;
+        writeln!(
+            formatter,
+            "    Result timeout: {}s",
+            self.action_events.result_timeout_secs
+        )?;
+        writeln!(
+            formatter,
+            "    Fan-out limit:  {}",
+            self.action_events.fan_out()
+        )?;
+        writeln!(
+            formatter,
+            "    In flight:      {} (queued: {})",
+            self.action_events.in_flight(),
+            self.action_events.max_queued
+        )?;
+        if self.action_events.dedupe_window_secs > 0 {
+            writeln!(
+                formatter,
+                "    Dedupe window:  {}s",
+                self.action_events.dedupe_window_secs
+            )?;
+        } else {
+            writeln!(formatter, "    Dedupe window:  <disabled>")?;
+        }
+        writeln!(formatter, "  Action updates")?;
+        writeln!(
+            formatter,
+            "    Broadcast capacity: {}",
+            self.action_updates.channel_capacity()
+        )?;
+        writeln!(
+            formatter,
+            "    Configuration acks: {}s timeout, {} attempts",
+            self.action_updates.configuration_ack_timeout_secs.max(1),
+            self.action_updates.configuration_max_attempts.max(1)
+        )?;
+        writeln!(formatter, "  Module updates")?;
+        if self.module_update.force_refresh_interval_secs > 0 {
+            writeln!(
+                formatter,
+                "    Forced refresh: every {}s",
+                self.module_update.force_refresh_interval_secs
+            )?;
+        } else {
+            writeln!(formatter, "    Forced refresh: <disabled>")?;
+        }
         writeln!(formatter, "  Runtime status")?;
         writeln!(
             formatter,
@@ -80,10 +223,16 @@ impl fmt::Display for Config {
             "    Monitor interval:     {}s",
             self.runtime_status.monitor_interval_secs
         )?;
-        write!(
+        writeln!(
             formatter,
             "    Heartbeat interval:   {}m",
             self.runtime_status.heartbeat_interval_minutes
+        )?;
+        writeln!(formatter, "  Shutdown")?;
+        write!(
+            formatter,
+            "    Drain timeout: {}s",
+            self.shutdown.drain_timeout_secs
         )
     }
 }
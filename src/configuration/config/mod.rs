@@ -16,6 +16,15 @@ use super::{env::Environment, mode::Mode};
 
 const CONFIG_FILE: &str = "aquila";
 const BACKEND_TOKEN_ENV: &str = "AQUILA_BACKEND_TOKEN";
+const ADMIN_REDRIVE_TOKEN_ENV: &str = "AQUILA_ADMIN_REDRIVE_TOKEN";
+/// First tokens of the subjects Aquila uses with core NATS. A work-queue
+/// stream on `<prefix>.*` with one of these as prefix would capture them.
+const CORE_SUBJECT_PREFIXES: [&str; 4] = [
+    "execution",
+    "execution_cancel",
+    "sub_flow_execution",
+    "action",
+];
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default)]
@@ -30,7 +39,17 @@ pub struct Config {
     pub static_config: StaticConfig,
     pub dynamic_config: DynamicConfig,
     pub grpc: Grpc,
+    pub admin: Admin,
+    pub prometheus: Prometheus,
+    pub execution_dispatch: ExecutionDispatch,
+    pub output_validation: OutputValidation,
+    pub action_execution: ActionExecution,
+    pub action_session: ActionSession,
+    pub action_events: ActionEvents,
+    pub action_updates: ActionUpdates,
+    pub module_update: ModuleUpdate,
     pub runtime_status: RuntimeStatus,
+    pub shutdown: Shutdown,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -38,6 +57,20 @@ pub struct Config {
 pub struct Nats {
     pub url: String,
     pub bucket: String,
+    /// Bucket remembering the module each action last had accepted by
+    /// Sagittarius. Only used in dynamic mode.
+    pub module_bucket: String,
+    /// Bucket keeping the latest configuration pushed per module. Only used
+    /// in dynamic mode.
+    pub configuration_bucket: String,
+    /// Bucket keeping the idempotency keys of recent action events.
+    pub event_key_bucket: String,
+    /// JetStream stream keeping executions that couldn't be dispatched. An
+    /// empty name disables it.
+    pub dead_letter_stream: String,
+    /// How long a dead-lettered execution is kept; `0` keeps it until it's
+    /// re-driven.
+    pub dead_letter_max_age_secs: u64,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -74,6 +107,194 @@ pub struct Grpc {
     pub host: String,
     pub port: u16,
     pub health_service: bool,
+    /// How often an idle connection is pinged over HTTP/2. `0` disables pings.
+    pub keepalive_interval_secs: u64,
+    /// How long a ping may go unanswered before the connection is closed.
+    pub keepalive_timeout_secs: u64,
+}
+
+/// The optional HTTP listener serving the JSON admin/diagnostics API.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Admin {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    /// Bearer token re-driving dead letters requires. Empty leaves the
+    /// API read-only.
+    pub redrive_token: String,
+}
+
+impl std::fmt::Debug for Admin {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter
+            .debug_struct("Admin")
+            .field("enabled", &self.enabled)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("redrive_token", &"[FILTERED]")
+            .finish()
+    }
+}
+
+/// The optional Prometheus scrape listener. Independent of the OTLP
+/// `opentelemetry.metrics_endpoint`; both can be enabled at once.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Prometheus {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+}
+
+/// How executions are handed to runtimes, see [`crate::flow::dispatch`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ExecutionDispatch {
+    pub mode: DispatchMode,
+    /// The work-queue stream executions are queued in. Only used in
+    /// `jetstream` mode.
+    pub stream: String,
+    /// Executions are queued on `<subject_prefix>.<execution_id>`.
+    pub subject_prefix: String,
+    /// How often a publish the stream didn't confirm is tried in total.
+    pub max_attempts: u32,
+    /// Backoff between attempts, growing linearly with each one.
+    pub retry_backoff_ms: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DispatchMode {
+    /// At-most-once publish on core NATS.
+    #[default]
+    Core,
+    /// Durable publish into a JetStream work-queue stream.
+    JetStream,
+}
+
+impl std::fmt::Display for DispatchMode {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Core => "core",
+            Self::JetStream => "jetstream",
+        })
+    }
+}
+
+/// Checking a successful execution result against its flow's
+/// `output_schema` before it's delivered.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OutputValidation {
+    pub mode: OutputValidationMode,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputValidationMode {
+    /// Results are delivered unchecked.
+    #[default]
+    Off,
+    /// Results that don't match are logged and counted, but delivered as they are.
+    Warn,
+    /// Results that don't match are delivered as an error instead.
+    Enforce,
+}
+
+impl std::fmt::Display for OutputValidationMode {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Off => "off",
+            Self::Warn => "warn",
+            Self::Enforce => "enforce",
+        })
+    }
+}
+
+/// Defaults for executions forwarded to connected actions. Individual
+/// actions can override them in the service configuration file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ActionExecution {
+    /// How long an action may take to answer an execution request before
+    /// Aquila replies with a timeout error in its place.
+    pub timeout_secs: u64,
+    /// How long a flow execution an action asked for may take before the
+    /// action receives a timeout in place of its result.
+    pub flow_timeout_secs: u64,
+}
+
+/// Resumption of an action's session when it reconnects.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ActionSession {
+    /// How long a disconnected action's session stays resumable, keeping its
+    /// executions pending. `0` disables resumption.
+    pub resume_grace_period_secs: u64,
+}
+
+/// Flows triggered by events actions send.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ActionEvents {
+    /// The longest an event that asked for its flows' results waits for
+    /// them, and how long it waits if it didn't say.
+    pub result_timeout_secs: u64,
+    /// How many flow executions an action's events may have requested at
+    /// once, per connection.
+    pub fan_out_limit: usize,
+    /// How many of an action's events are handled at once, per connection.
+    pub max_in_flight: usize,
+    /// How many further events of a connection wait for one of those to
+    /// finish before more are turned down.
+    pub max_queued: usize,
+    /// How long an event's idempotency key is remembered; `0` disables
+    /// dropping duplicate events.
+    pub dedupe_window_secs: u64,
+}
+
+impl ActionEvents {
+    /// [`Self::fan_out_limit`], at least 1.
+    pub fn fan_out(&self) -> usize {
+        self.fan_out_limit.max(1)
+    }
+
+    /// [`Self::max_in_flight`], at least 1.
+    pub fn in_flight(&self) -> usize {
+        self.max_in_flight.max(1)
+    }
+}
+
+/// The broadcasts fanning flow and configuration changes out to every
+/// connected action.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ActionUpdates {
+    /// How many changes each broadcast buffers for its slowest action before
+    /// that action falls behind and gets re-synced with a full snapshot.
+    pub broadcast_capacity: usize,
+    /// How long an action that acknowledges configuration pushes has to do
+    /// so before the push is sent again.
+    pub configuration_ack_timeout_secs: u64,
+    /// How often an unacknowledged configuration push is sent in total.
+    pub configuration_max_attempts: u32,
+}
+
+impl ActionUpdates {
+    /// [`Self::broadcast_capacity`], at least 1.
+    pub fn channel_capacity(&self) -> usize {
+        self.broadcast_capacity.max(1)
+    }
+}
+
+/// When an action's module is sent to Sagittarius on logon.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ModuleUpdate {
+    /// How long an unchanged module may go without being sent again. `0`
+    /// only sends modules that changed.
+    pub force_refresh_interval_secs: u64,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -85,6 +306,12 @@ pub struct RuntimeStatus {
     pub heartbeat_interval_minutes: u64,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Shutdown {
+    pub drain_timeout_secs: u64,
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -96,7 +323,17 @@ impl Default for Config {
             static_config: StaticConfig::default(),
             dynamic_config: DynamicConfig::default(),
             grpc: Grpc::default(),
+            admin: Admin::default(),
+            prometheus: Prometheus::default(),
+            execution_dispatch: ExecutionDispatch::default(),
+            output_validation: OutputValidation::default(),
+            action_execution: ActionExecution::default(),
+            action_session: ActionSession::default(),
+            action_events: ActionEvents::default(),
+            action_updates: ActionUpdates::default(),
+            module_update: ModuleUpdate::default(),
             runtime_status: RuntimeStatus::default(),
+            shutdown: Shutdown::default(),
         }
     }
 }
@@ -113,6 +350,11 @@ impl Default for Nats {
         Self {
             url: "nats://localhost:4222".into(),
             bucket: "flow_store".into(),
+            module_bucket: "action_modules".into(),
+            configuration_bucket: "action_configurations".into(),
+            event_key_bucket: "action_event_keys".into(),
+            dead_letter_stream: "AQUILA_DEAD_LETTERS".into(),
+            dead_letter_max_age_secs: 7 * 24 * 60 * 60,
         }
     }
 }
@@ -141,6 +383,88 @@ impl Default for Grpc {
             host: "127.0.0.1".into(),
             port: 8081,
             health_service: false,
+            keepalive_interval_secs: 30,
+            keepalive_timeout_secs: 20,
+        }
+    }
+}
+
+impl Default for Admin {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "127.0.0.1".into(),
+            port: 8082,
+            redrive_token: String::new(),
+        }
+    }
+}
+
+impl Default for Prometheus {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "127.0.0.1".into(),
+            port: 9464,
+        }
+    }
+}
+
+impl Default for ActionExecution {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 60,
+            flow_timeout_secs: 300,
+        }
+    }
+}
+
+impl Default for ActionSession {
+    fn default() -> Self {
+        Self {
+            resume_grace_period_secs: 30,
+        }
+    }
+}
+
+impl Default for ExecutionDispatch {
+    fn default() -> Self {
+        Self {
+            mode: DispatchMode::Core,
+            stream: "AQUILA_EXECUTIONS".into(),
+            subject_prefix: "queued_execution".into(),
+            max_attempts: 5,
+            retry_backoff_ms: 200,
+        }
+    }
+}
+
+impl Default for ActionEvents {
+    fn default() -> Self {
+        Self {
+            result_timeout_secs: 30,
+            fan_out_limit: 16,
+            max_in_flight: 16,
+            max_queued: 256,
+            dedupe_window_secs: 600,
+        }
+    }
+}
+
+impl Default for ActionUpdates {
+    fn default() -> Self {
+        Self {
+            broadcast_capacity: 64,
+            configuration_ack_timeout_secs: 10,
+            configuration_max_attempts: 5,
+        }
+    }
+}
+
+impl Default for ModuleUpdate {
+    fn default() -> Self {
+        Self {
+            force_refresh_interval_secs: 3600,
         }
     }
 }
@@ -156,6 +480,14 @@ impl Default for RuntimeStatus {
     }
 }
 
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            drain_timeout_secs: 30,
+        }
+    }
+}
+
 impl Config {
     pub fn new() -> Self {
         Self::try_new()
@@ -182,8 +514,40 @@ impl Config {
         if let Ok(token) = std::env::var(BACKEND_TOKEN_ENV) {
             builder = builder.set_override("dynamic_config.backend_token", token)?;
         }
+        if let Ok(token) = std::env::var(ADMIN_REDRIVE_TOKEN_ENV) {
+            builder = builder.set_override("admin.redrive_token", token)?;
+        }
+
+        let config: Self = builder.build()?.try_deserialize()?;
+        config.validate()?;
+        Ok(config)
+    }
 
-        builder.build()?.try_deserialize()
+    /// Rejects values that deserialize fine but can't work, such as a
+    /// timeout that would fail every execution before it could be answered.
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.action_execution.timeout_secs == 0 {
+            return Err(ConfigError::Message(
+                "action_execution.timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.execution_dispatch.mode == DispatchMode::JetStream {
+            let prefix = self.execution_dispatch.subject_prefix.as_str();
+            if prefix
+                .split('.')
+                .any(|token| token.is_empty() || token == "*" || token == ">")
+            {
+                return Err(ConfigError::Message(format!(
+                    "execution_dispatch.subject_prefix must be a subject without wildcards, got `{prefix}`"
+                )));
+            }
+            if CORE_SUBJECT_PREFIXES.contains(&prefix) {
+                return Err(ConfigError::Message(format!(
+                    "execution_dispatch.subject_prefix `{prefix}` would capture the core NATS subjects Aquila uses"
+                )));
+            }
+        }
+        Ok(())
     }
 
     pub fn is_static(&self) -> bool {
@@ -199,7 +563,10 @@ mod tests {
 
     use code0_flow::flow_telemetry::OpenTelemetry;
 
-    use super::{Config, default_opentelemetry};
+    use super::{
+        Config, DispatchMode, ExecutionDispatch, OutputValidation, OutputValidationMode,
+        default_opentelemetry,
+    };
 
     static ENV_LOCK: Mutex<()> = Mutex::new(());
 
@@ -233,6 +600,16 @@ mod tests {
         assert!(!output.contains("super-secret"));
     }
 
+    #[test]
+    fn debug_output_filters_admin_redrive_token() {
+        let mut config = Config::default();
+        config.admin.redrive_token = "redrive-secret".into();
+
+        let output = format!("{config:#?}");
+
+        assert!(!output.contains("redrive-secret"));
+    }
+
     #[test]
     fn opentelemetry_endpoints_are_enabled_by_presence() {
         let config: OpenTelemetry = ConfigLoader::builder()
@@ -263,6 +640,67 @@ mod tests {
         assert!(config.has_enabled_exporter());
     }
 
+    #[test]
+    fn execution_dispatch_mode_is_read_in_lowercase() {
+        let config: ExecutionDispatch = ConfigLoader::builder()
+            .add_source(
+                ConfigLoader::try_from(&ExecutionDispatch::default())
+                    .expect("default dispatch config should serialize"),
+            )
+            .set_override("mode", "jetstream")
+            .expect("mode override should apply")
+            .build()
+            .expect("dispatch config should build")
+            .try_deserialize()
+            .expect("dispatch config should deserialize");
+
+        assert_eq!(ExecutionDispatch::default().mode, DispatchMode::Core);
+        assert_eq!(config.mode, DispatchMode::JetStream);
+        assert_eq!(config.stream, "AQUILA_EXECUTIONS");
+    }
+
+    #[test]
+    fn zero_action_execution_timeout_is_rejected() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+
+        config.action_execution.timeout_secs = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn dispatch_subject_prefix_overlapping_core_subjects_is_rejected() {
+        let mut config = Config::default();
+        config.execution_dispatch.mode = DispatchMode::JetStream;
+        assert!(config.validate().is_ok());
+
+        for prefix in ["execution", "execution_cancel", "queued.*", ""] {
+            config.execution_dispatch.subject_prefix = prefix.to_string();
+            assert!(
+                config.validate().is_err(),
+                "prefix `{prefix}` should be rejected"
+            );
+        }
+
+        // Core mode doesn't create the stream, so the prefix is never used.
+        config.execution_dispatch.mode = DispatchMode::Core;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn output_validation_is_off_unless_configured() {
+        let config: OutputValidation = ConfigLoader::builder()
+            .set_override("mode", "enforce")
+            .expect("mode override should apply")
+            .build()
+            .expect("output validation config should build")
+            .try_deserialize()
+            .expect("output validation config should deserialize");
+
+        assert_eq!(OutputValidation::default().mode, OutputValidationMode::Off);
+        assert_eq!(config.mode, OutputValidationMode::Enforce);
+    }
+
     #[test]
     fn opentelemetry_default_service_name_is_aquila() {
         assert_eq!(Config::default().opentelemetry.service_name, "aquila");
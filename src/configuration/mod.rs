@@ -1,10 +1,12 @@
 //! Everything Aquila loads before it can start serving: the process-wide
 //! [`config::Config`] (file + env), the static [`service::ServiceConfiguration`]
 //! allowlist, and small shared value types ([`env::Environment`], [`mode::Mode`],
-//! [`state::AppReadiness`]) used across both.
+//! [`state::AppReadiness`]) used across both. [`module_configurations`] keeps
+//! the latest per-module configuration pushed at runtime.
 
 pub mod config;
 pub mod env;
 pub mod mode;
+pub mod module_configurations;
 pub mod service;
 pub mod state;
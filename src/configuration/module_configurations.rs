@@ -0,0 +1,144 @@
+//! The latest [`ModuleConfigurations`] pushed for each module. The
+//! configuration broadcast only carries changes, so an action that logs on
+//! after a push, or whose forwarder fell behind, is sent its configuration
+//! from here instead of waiting for the next push.
+//!
+//! With a KV bucket (`nats.configuration_bucket`), the latest push per
+//! module also survives restarts and is shared across Aquila instances;
+//! the in-memory copy only saves the round-trip.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use prost::Message;
+use tucana::shared::ModuleConfigurations;
+
+use crate::telemetry::errors;
+
+#[derive(Clone, Default)]
+pub struct LatestModuleConfigurations {
+    kv: Option<async_nats::jetstream::kv::Store>,
+    inner: Arc<RwLock<HashMap<String, ModuleConfigurations>>>,
+}
+
+impl LatestModuleConfigurations {
+    /// Without a bucket, only pushes received since startup are kept.
+    pub fn new(kv: Option<async_nats::jetstream::kv::Store>) -> Self {
+        Self {
+            kv,
+            inner: Arc::default(),
+        }
+    }
+
+    /// Replaces whatever was stored for the module `configurations` belongs
+    /// to. A failed KV write only loses the push across restarts.
+    pub async fn update(&self, configurations: &ModuleConfigurations) {
+        self.remember(configurations.clone());
+
+        let Some(kv) = &self.kv else {
+            return;
+        };
+        let identifier = &configurations.module_identifier;
+        if let Err(err) = kv
+            .put(
+                module_key(identifier),
+                configurations.encode_to_vec().into(),
+            )
+            .await
+        {
+            errors::record(
+                "flow_storage",
+                "module_configurations.put",
+                &err,
+                format!("module.identifier={identifier}"),
+            );
+        }
+    }
+
+    pub async fn get(&self, module_identifier: &str) -> Option<ModuleConfigurations> {
+        {
+            let latest = self.inner.read().unwrap_or_else(|err| err.into_inner());
+            if let Some(configurations) = latest.get(module_identifier) {
+                return Some(configurations.clone());
+            }
+        }
+
+        let kv = self.kv.as_ref()?;
+        let entry = match kv.get(module_key(module_identifier)).await {
+            Ok(entry) => entry?,
+            Err(err) => {
+                errors::record(
+                    "flow_storage",
+                    "module_configurations.get",
+                    &err,
+                    format!("module.identifier={module_identifier}"),
+                );
+                return None;
+            }
+        };
+
+        match ModuleConfigurations::decode(entry) {
+            Ok(configurations) => {
+                self.remember(configurations.clone());
+                Some(configurations)
+            }
+            Err(err) => {
+                errors::record(
+                    "flow_storage",
+                    "module_configurations.decode",
+                    &err,
+                    format!("module.identifier={module_identifier}"),
+                );
+                None
+            }
+        }
+    }
+
+    fn remember(&self, configurations: ModuleConfigurations) {
+        let mut latest = self.inner.write().unwrap_or_else(|err| err.into_inner());
+        latest.insert(configurations.module_identifier.clone(), configurations);
+    }
+}
+
+/// The KV key a module's entries are stored under. KV keys only allow a
+/// limited character set; anything else in the identifier becomes `_`.
+pub fn module_key(identifier: &str) -> String {
+    identifier
+        .chars()
+        .map(|character| match character {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '=' | '.' => character,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_push_replaces_earlier_one_without_bucket() {
+        futures::executor::block_on(async {
+            let latest = LatestModuleConfigurations::new(None);
+            assert!(latest.get("send-email").await.is_none());
+
+            let mut configurations = ModuleConfigurations {
+                module_identifier: "send-email".to_string(),
+                module_configurations: vec![],
+            };
+            latest.update(&configurations).await;
+            configurations.module_configurations.push(
+                tucana::shared::ModuleProjectConfigurations {
+                    project_id: 1,
+                    module_configurations: vec![],
+                },
+            );
+            latest.update(&configurations).await;
+
+            assert_eq!(latest.get("send-email").await, Some(configurations));
+            assert!(latest.get("send-sms").await.is_none());
+        });
+    }
+}
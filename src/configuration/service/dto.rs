@@ -8,6 +8,11 @@
 //! [`RuntimeServiceConfiguration`] doubles as both the wire format and the
 //! domain type.
 
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
 use serde::{Deserialize, Serialize};
 use tucana::shared::{ModuleConfigurations, helper::value::from_json_value};
 
@@ -32,6 +37,12 @@ pub(super) struct SerializableActionServiceConfiguration {
     pub(super) identifier: String,
     #[serde(default)]
     pub(super) configs: Vec<SerializableModuleProjectConfiguration>,
+    /// Overrides `action_execution.timeout_secs` for this action.
+    #[serde(default)]
+    pub(super) execution_timeout_secs: Option<u64>,
+    /// Most execution requests this action works on at once, per connection.
+    #[serde(default)]
+    pub(super) max_in_flight: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default)]
@@ -42,6 +53,24 @@ pub(super) struct SerializableServiceConfiguration {
     pub(super) runtimes: Vec<RuntimeServiceConfiguration>,
 }
 
+impl SerializableServiceConfiguration {
+    /// Rejects an action whose execution timeout would fail every execution
+    /// before it could be answered.
+    pub(super) fn validate(&self) -> Result<(), String> {
+        match self
+            .actions
+            .iter()
+            .find(|action| action.execution_timeout_secs == Some(0))
+        {
+            Some(action) => Err(format!(
+                "execution_timeout_secs of action {} must be greater than 0",
+                action.identifier
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
 /// A pre-provisioned runtime's token and identity. Used directly as both the
 /// file format and the domain type since, unlike actions, no expansion is needed.
 #[derive(Serialize, Deserialize, Clone)]
@@ -77,10 +106,12 @@ impl From<SerializableActionServiceConfiguration> for ActionServiceConfiguration
         Self {
             token: value.token,
             service_name: value.identifier,
-            config: vec![ModuleConfigurations {
+            config: Arc::new(RwLock::new(vec![ModuleConfigurations {
                 module_identifier,
                 module_configurations: value.configs.into_iter().map(Into::into).collect(),
-            }],
+            }])),
+            execution_timeout: value.execution_timeout_secs.map(Duration::from_secs),
+            max_in_flight: value.max_in_flight.filter(|max| *max > 0),
         }
     }
 }
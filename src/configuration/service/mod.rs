@@ -10,7 +10,13 @@ mod dto;
 
 pub use dto::RuntimeServiceConfiguration;
 
-use std::{fs::File, io::Read, path::Path};
+use std::{
+    fs::File,
+    io::Read,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use serde_json::from_str;
 use tucana::shared::ModuleConfigurations;
@@ -21,7 +27,11 @@ use dto::SerializableServiceConfiguration;
 pub struct ActionServiceConfiguration {
     token: String,
     service_name: String,
-    config: Vec<ModuleConfigurations>,
+    /// Shared by every clone, so a reload reaches all of them. See
+    /// [`ServiceConfiguration::update_action_configurations`].
+    config: Arc<RwLock<Vec<ModuleConfigurations>>>,
+    execution_timeout: Option<Duration>,
+    max_in_flight: Option<usize>,
 }
 
 #[derive(Clone, Default)]
@@ -35,6 +45,7 @@ impl ServiceConfiguration {
     /// since individual `taurus-*` runtime instances all share one
     /// provisioned token under the `taurus` identifier while `draco-*`
     /// runtimes are provisioned individually.
+    #[allow(clippy::ptr_arg)]
     pub fn extract_service_name(name: &String) -> Option<String> {
         if name.starts_with("draco") {
             return Some(name.clone());
@@ -80,11 +91,64 @@ impl ServiceConfiguration {
             .iter()
             .find(|x| &x.token == token && &x.service_name == action_identifier)
         {
-            Some(a) => a.config.clone(),
+            Some(a) => a.configurations(),
             None => vec![],
         }
     }
 
+    /// Takes over the action configurations of `reloaded` for every action
+    /// both know, and returns those that changed. Everything else, such as
+    /// tokens, timeouts and which actions exist, stays as first loaded.
+    pub fn update_action_configurations(
+        &self,
+        reloaded: &ServiceConfiguration,
+    ) -> Vec<ModuleConfigurations> {
+        let mut changed = Vec::new();
+        for action in &self.actions {
+            let Some(reloaded) = reloaded.find_action(&action.token, &action.service_name) else {
+                continue;
+            };
+            let configurations = reloaded.configurations();
+            let mut current = action
+                .config
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if *current != configurations {
+                changed.extend(configurations.iter().cloned());
+                *current = configurations;
+            }
+        }
+        changed
+    }
+
+    /// The execution timeout configured for this action, if it overrides
+    /// the global `action_execution.timeout_secs`.
+    pub fn action_execution_timeout(
+        &self,
+        token: &str,
+        action_identifier: &str,
+    ) -> Option<Duration> {
+        self.find_action(token, action_identifier)
+            .and_then(|x| x.execution_timeout)
+    }
+
+    /// How many executions this action may have outstanding per connection,
+    /// or `None` if it isn't limited.
+    pub fn action_max_in_flight(&self, token: &str, action_identifier: &str) -> Option<usize> {
+        self.find_action(token, action_identifier)
+            .and_then(|x| x.max_in_flight)
+    }
+
+    fn find_action(
+        &self,
+        token: &str,
+        action_identifier: &str,
+    ) -> Option<&ActionServiceConfiguration> {
+        self.actions
+            .iter()
+            .find(|x| x.token == token && x.service_name == action_identifier)
+    }
+
     /// Every module identifier Aquila should advertise as available,
     /// combining each action's own identifier with each runtime's resolved
     /// module list (or its own identifier, if it hasn't resolved any yet).
@@ -103,6 +167,7 @@ impl ServiceConfiguration {
             })
             .collect();
 
+        #[allow(clippy::useless_vec)]
         vec![actions, runtime].concat()
     }
 
@@ -132,9 +197,26 @@ impl ServiceConfiguration {
 
         log::debug!("Successfully loaded action configuration file");
 
-        from_str::<SerializableServiceConfiguration>(&data)
-            .map(Into::into)
-            .map_err(|error| format!("Couldn't parse service configuration file: {}", error))
+        Self::from_json(&data)
+    }
+
+    /// Parses the contents of a service configuration file.
+    pub fn from_json(data: &str) -> Result<Self, String> {
+        let configuration = from_str::<SerializableServiceConfiguration>(data)
+            .map_err(|error| format!("Couldn't parse service configuration file: {}", error))?;
+        configuration
+            .validate()
+            .map_err(|error| format!("Invalid service configuration file: {}", error))?;
+        Ok(configuration.into())
+    }
+}
+
+impl ActionServiceConfiguration {
+    fn configurations(&self) -> Vec<ModuleConfigurations> {
+        self.config
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
     }
 }
 
@@ -154,6 +236,8 @@ mod tests {
                 token: String::from("action-token"),
                 identifier: String::from("action-identifier"),
                 configs: vec![],
+                execution_timeout_secs: Some(120),
+                max_in_flight: Some(4),
             }],
             runtimes: vec![
                 RuntimeServiceConfiguration {
@@ -270,6 +354,8 @@ mod tests {
                             value: serde_json::json!("old.example"),
                         }],
                     }],
+                    execution_timeout_secs: None,
+                    max_in_flight: None,
                 },
                 SerializableActionServiceConfiguration {
                     token: String::from("new-token"),
@@ -281,6 +367,8 @@ mod tests {
                             value: serde_json::json!("new.example"),
                         }],
                     }],
+                    execution_timeout_secs: None,
+                    max_in_flight: None,
                 },
             ],
             runtimes: vec![],
@@ -308,4 +396,96 @@ mod tests {
                 .is_empty()
         );
     }
+
+    #[test]
+    fn action_execution_timeout_requires_matching_token() {
+        let config = fixture();
+
+        assert_eq!(
+            config.action_execution_timeout("action-token", "action-identifier"),
+            Some(std::time::Duration::from_secs(120))
+        );
+        assert_eq!(
+            config.action_execution_timeout("wrong-token", "action-identifier"),
+            None
+        );
+    }
+
+    #[test]
+    fn action_max_in_flight_requires_matching_token() {
+        let config = fixture();
+
+        assert_eq!(
+            config.action_max_in_flight("action-token", "action-identifier"),
+            Some(4)
+        );
+        assert_eq!(
+            config.action_max_in_flight("wrong-token", "action-identifier"),
+            None
+        );
+    }
+
+    #[test]
+    fn update_action_configurations_returns_only_changed_configurations() {
+        let config = fixture();
+        let shared = config.clone();
+        let reloaded = |value: &str| -> ServiceConfiguration {
+            SerializableServiceConfiguration {
+                actions: vec![SerializableActionServiceConfiguration {
+                    token: String::from("action-token"),
+                    identifier: String::from("action-identifier"),
+                    configs: vec![SerializableModuleProjectConfiguration {
+                        project_id: 1,
+                        configs: vec![SerializableModuleConfiguration {
+                            identifier: String::from("endpoint"),
+                            value: serde_json::json!(value),
+                        }],
+                    }],
+                    execution_timeout_secs: None,
+                    max_in_flight: None,
+                }],
+                runtimes: vec![],
+            }
+            .into()
+        };
+
+        let changed = config.update_action_configurations(&reloaded("new.example"));
+
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].module_identifier, "action-identifier");
+        assert_eq!(
+            shared.get_action_configuration(
+                &String::from("action-token"),
+                &String::from("action-identifier")
+            ),
+            changed
+        );
+        assert!(
+            config
+                .update_action_configurations(&reloaded("new.example"))
+                .is_empty()
+        );
+        // Only the configuration is taken over.
+        assert_eq!(
+            shared.action_max_in_flight("action-token", "action-identifier"),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn zero_execution_timeout_is_rejected() {
+        let configuration = SerializableServiceConfiguration {
+            actions: vec![SerializableActionServiceConfiguration {
+                token: String::from("action-token"),
+                identifier: String::from("action-identifier"),
+                configs: vec![],
+                execution_timeout_secs: Some(0),
+                max_in_flight: None,
+            }],
+            runtimes: vec![],
+        };
+
+        let error = configuration.validate().unwrap_err();
+        assert!(error.contains("action-identifier"));
+    }
 }
@@ -1,6 +1,7 @@
 //! Shared readiness flags consulted by [`crate::server::create_readiness_interceptor`]
 //! to reject gRPC requests before Aquila's upstream dependencies are usable,
-//! rather than accepting them and failing partway through a handler.
+//! rather than accepting them and failing partway through a handler. The
+//! gRPC health service derives its per-service statuses from the same flags.
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -10,6 +11,10 @@ use std::sync::atomic::{AtomicBool, Ordering};
 pub struct AppReadiness {
     /// Whether the Sagittarius gRPC channel has completed its initial connect.
     pub sagittarius_ready: Arc<AtomicBool>,
+    /// Whether the NATS connection is currently established.
+    pub nats_ready: Arc<AtomicBool>,
+    /// Whether the flow KV bucket answered its most recent probe.
+    pub kv_ready: Arc<AtomicBool>,
 }
 
 impl Default for AppReadiness {
@@ -22,10 +27,18 @@ impl AppReadiness {
     pub fn new() -> Self {
         Self {
             sagittarius_ready: Arc::new(AtomicBool::new(false)),
+            nats_ready: Arc::new(AtomicBool::new(false)),
+            kv_ready: Arc::new(AtomicBool::new(false)),
         }
     }
 
     pub fn is_ready(&self) -> bool {
         self.sagittarius_ready.load(Ordering::SeqCst)
     }
+
+    /// Whether both NATS and the flow KV bucket are usable, i.e. everything
+    /// action transfer depends on.
+    pub fn is_messaging_ready(&self) -> bool {
+        self.nats_ready.load(Ordering::SeqCst) && self.kv_ready.load(Ordering::SeqCst)
+    }
 }
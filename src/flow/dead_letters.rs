@@ -0,0 +1,480 @@
+//! Executions Aquila failed to hand to a runtime, kept in a JetStream
+//! stream (`nats.dead_letter_stream`) instead of only being logged. Each
+//! entry is the `ExecutionFlow` exactly as it would have been published,
+//! with where it came from and why it failed in the message headers. The
+//! admin API lists and inspects entries, and re-drives one by publishing it
+//! onto the execution bus again.
+//!
+//! A re-driven execution runs under its original execution id, but whoever
+//! asked for it originally has long been told it failed, so its result
+//! isn't delivered to them.
+
+use std::time::Duration;
+
+use async_nats::{
+    HeaderMap,
+    jetstream::{self, stream::LastRawMessageErrorKind},
+};
+use prost::Message;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tucana::shared::{ExecutionFlow, helper::value::to_json_value};
+
+use super::{dispatch::ExecutionDispatcher, executions::ExecutionOrigin};
+use crate::{
+    configuration::config::Nats,
+    telemetry::{errors, metrics},
+    validation,
+};
+
+const ORIGIN_HEADER: &str = "Aquila-Origin";
+const EXECUTION_ID_HEADER: &str = "Aquila-Execution-Id";
+const ACTION_HEADER: &str = "Aquila-Action-Identifier";
+const ERROR_HEADER: &str = "Aquila-Error";
+const FAILED_AT_HEADER: &str = "Aquila-Failed-At";
+
+/// The most entries [`DeadLetters::list`] returns, oldest first.
+pub const LIST_LIMIT: usize = 100;
+
+/// An execution that couldn't be dispatched, as it's recorded.
+pub struct DeadLetter<'a> {
+    pub origin: ExecutionOrigin,
+    pub execution_id: &'a str,
+    pub execution_flow: &'a ExecutionFlow,
+    /// The action the execution was dispatched for, if any.
+    pub action_identifier: Option<&'a str>,
+    pub error: String,
+}
+
+/// A recorded entry, as the admin API lists it.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DeadLetterSummary {
+    pub sequence: u64,
+    pub origin: String,
+    pub execution_id: String,
+    pub flow_id: i64,
+    pub project_id: i64,
+    pub action_identifier: Option<String>,
+    pub error: String,
+    pub failed_at: i64,
+}
+
+/// A page of [`DeadLetters::list`].
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DeadLetterPage {
+    pub dead_letters: Vec<DeadLetterSummary>,
+    /// The `after` the next page is listed with, or `None` once there are no
+    /// later entries.
+    pub next_after: Option<u64>,
+}
+
+/// A recorded entry with the parts of its execution worth looking at.
+#[derive(Debug, Serialize)]
+pub struct DeadLetterDetail {
+    #[serde(flatten)]
+    pub summary: DeadLetterSummary,
+    pub starting_node_id: i64,
+    pub node_count: usize,
+    pub input_value: Option<JsonValue>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeadLetterError {
+    /// The stream couldn't be opened at startup.
+    Unavailable,
+    NotFound,
+    /// The entry isn't one Aquila recorded, or its execution can't be decoded.
+    Invalid(String),
+    /// Reading, deleting or re-driving the entry failed.
+    Failed(String),
+}
+
+impl std::fmt::Display for DeadLetterError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeadLetterError::Unavailable => {
+                write!(formatter, "dead-letter stream is not available")
+            }
+            DeadLetterError::NotFound => write!(formatter, "dead letter was not found"),
+            DeadLetterError::Invalid(message) | DeadLetterError::Failed(message) => {
+                write!(formatter, "{message}")
+            }
+        }
+    }
+}
+
+/// The dead-letter stream. Without one, undeliverable executions are only
+/// logged, as they were before.
+#[derive(Clone)]
+pub struct DeadLetters {
+    client: async_nats::Client,
+    dispatcher: ExecutionDispatcher,
+    stream: Option<jetstream::stream::Stream>,
+}
+
+impl DeadLetters {
+    /// Opens the stream named by `nats.dead_letter_stream`, creating it if
+    /// needed. An empty name leaves it disabled. Re-driven entries go
+    /// through `dispatcher`.
+    pub async fn open(
+        client: &async_nats::Client,
+        config: &Nats,
+        dispatcher: ExecutionDispatcher,
+    ) -> Self {
+        let name = &config.dead_letter_stream;
+        if name.is_empty() {
+            return Self {
+                client: client.clone(),
+                dispatcher,
+                stream: None,
+            };
+        }
+
+        let jet_stream = jetstream::new(client.clone());
+        let stream = match jet_stream
+            .get_or_create_stream(jetstream::stream::Config {
+                name: name.clone(),
+                subjects: vec![format!("{name}.>")],
+                max_age: Duration::from_secs(config.dead_letter_max_age_secs),
+                ..Default::default()
+            })
+            .await
+        {
+            Ok(stream) => {
+                log::debug!("NATS dead-letter stream is available stream={}", name);
+                Some(stream)
+            }
+            Err(err) => {
+                errors::record(
+                    "messaging",
+                    "dead_letter.open",
+                    &err,
+                    format!("stream={name}"),
+                );
+                None
+            }
+        };
+
+        Self {
+            client: client.clone(),
+            dispatcher,
+            stream,
+        }
+    }
+
+    /// Records an execution that couldn't be dispatched. If even that
+    /// fails, the failure is recorded and the execution is lost.
+    pub async fn record(&self, letter: DeadLetter<'_>) {
+        let Some(stream) = &self.stream else {
+            return;
+        };
+
+        let origin = letter.origin.as_str();
+        let context = format!(
+            "origin={} execution_id={} flow_id={}",
+            origin, letter.execution_id, letter.execution_flow.flow_id
+        );
+        let subject = format!("{}.{}", stream.cached_info().config.name, origin);
+        let published = match jetstream::new(self.client.clone())
+            .publish_with_headers(
+                subject,
+                headers(&letter),
+                letter.execution_flow.encode_to_vec().into(),
+            )
+            .await
+        {
+            Ok(ack) => ack.await.map(|_| ()).map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        match published {
+            Ok(()) => {
+                log::warn!(
+                    "Recorded undeliverable execution as dead letter {}",
+                    context
+                );
+                metrics::dead_letter(origin, "recorded");
+            }
+            Err(err) => {
+                errors::record_message("messaging", "dead_letter.record", &err, context);
+                metrics::dead_letter(origin, "record_failed");
+            }
+        }
+    }
+
+    /// Up to [`LIST_LIMIT`] entries after sequence `after`, oldest first.
+    /// Each read asks for the next entry at or after a sequence, so the gaps
+    /// deleted or re-driven entries leave cost nothing.
+    pub async fn list(&self, after: u64) -> Result<DeadLetterPage, DeadLetterError> {
+        let stream = self.stream.as_ref().ok_or(DeadLetterError::Unavailable)?;
+        let subjects = format!("{}.>", stream.cached_info().config.name);
+
+        list_page(after, LIST_LIMIT, |sequence| {
+            let subjects = subjects.clone();
+            async move {
+                let message = match stream
+                    .raw_message_builder()
+                    .sequence(sequence)
+                    .next_by_subject(subjects)
+                    .send()
+                    .await
+                {
+                    Ok(message) => message,
+                    Err(err) if err.kind() == LastRawMessageErrorKind::NoMessageFound => {
+                        return Ok(None);
+                    }
+                    Err(err) => return Err(DeadLetterError::Failed(err.to_string())),
+                };
+
+                match read_letter(message.sequence, &message.headers, message.payload) {
+                    Ok((summary, _)) => Ok(Some((message.sequence, Some(summary)))),
+                    Err(DeadLetterError::Invalid(error)) => {
+                        log::warn!(
+                            "Skipped unreadable dead letter sequence={} error={}",
+                            message.sequence,
+                            error
+                        );
+                        Ok(Some((message.sequence, None)))
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+        })
+        .await
+    }
+
+    pub async fn inspect(&self, sequence: u64) -> Result<DeadLetterDetail, DeadLetterError> {
+        let (summary, execution_flow) = self.read(sequence).await?;
+        Ok(DeadLetterDetail {
+            summary,
+            starting_node_id: execution_flow.starting_node_id,
+            node_count: execution_flow.node_functions.len(),
+            input_value: execution_flow.input_value.map(to_json_value),
+        })
+    }
+
+    /// Publishes the entry's execution onto the execution bus again and
+    /// removes it. An entry that fails to publish again is kept.
+    pub async fn redrive(&self, sequence: u64) -> Result<DeadLetterSummary, DeadLetterError> {
+        let stream = self.stream.as_ref().ok_or(DeadLetterError::Unavailable)?;
+        let (summary, execution_flow) = self.read(sequence).await?;
+
+        self.dispatcher
+            .dispatch(&summary.execution_id, &execution_flow)
+            .await
+            .map_err(|err| DeadLetterError::Failed(err.to_string()))?;
+        log::info!(
+            "Re-drove dead letter sequence={} execution_id={} flow_id={}",
+            sequence,
+            summary.execution_id,
+            summary.flow_id
+        );
+        metrics::dead_letter(&summary.origin, "redriven");
+
+        if let Err(err) = stream.delete_message(sequence).await {
+            errors::record(
+                "messaging",
+                "dead_letter.delete",
+                &err,
+                format!("sequence={sequence}"),
+            );
+        }
+        Ok(summary)
+    }
+
+    async fn read(
+        &self,
+        sequence: u64,
+    ) -> Result<(DeadLetterSummary, ExecutionFlow), DeadLetterError> {
+        let stream = self.stream.as_ref().ok_or(DeadLetterError::Unavailable)?;
+        let message = match stream.get_raw_message(sequence).await {
+            Ok(message) => message,
+            Err(err) if err.kind() == LastRawMessageErrorKind::NoMessageFound => {
+                return Err(DeadLetterError::NotFound);
+            }
+            Err(err) => return Err(DeadLetterError::Failed(err.to_string())),
+        };
+        read_letter(sequence, &message.headers, message.payload)
+    }
+}
+
+fn headers(letter: &DeadLetter<'_>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(ORIGIN_HEADER, letter.origin.as_str());
+    headers.insert(EXECUTION_ID_HEADER, letter.execution_id);
+    if let Some(action_identifier) = letter.action_identifier {
+        headers.insert(ACTION_HEADER, action_identifier);
+    }
+    // Header values can't span lines.
+    headers.insert(ERROR_HEADER, letter.error.replace(['\r', '\n'], " "));
+    headers.insert(FAILED_AT_HEADER, validation::epoch_millis_now().to_string());
+    headers
+}
+
+/// Collects up to `limit` entries after sequence `after`. `next` reads the
+/// first entry at or after a sequence: its sequence and summary, no summary
+/// if it's unreadable, or `None` if there is no such entry.
+async fn list_page<F, Fut>(
+    after: u64,
+    limit: usize,
+    mut next: F,
+) -> Result<DeadLetterPage, DeadLetterError>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = Result<Option<(u64, Option<DeadLetterSummary>)>, DeadLetterError>>,
+{
+    let mut dead_letters = Vec::new();
+    let mut last = after;
+    while dead_letters.len() < limit {
+        let Some((sequence, summary)) = next(last + 1).await? else {
+            return Ok(DeadLetterPage {
+                dead_letters,
+                next_after: None,
+            });
+        };
+        last = sequence;
+        dead_letters.extend(summary);
+    }
+    Ok(DeadLetterPage {
+        dead_letters,
+        next_after: Some(last),
+    })
+}
+
+fn read_letter(
+    sequence: u64,
+    headers: &HeaderMap,
+    payload: prost::bytes::Bytes,
+) -> Result<(DeadLetterSummary, ExecutionFlow), DeadLetterError> {
+    let header = |name: &str| headers.get(name).map(|value| value.as_str().to_string());
+    let (Some(origin), Some(execution_id)) = (header(ORIGIN_HEADER), header(EXECUTION_ID_HEADER))
+    else {
+        return Err(DeadLetterError::Invalid(format!(
+            "dead letter {sequence} wasn't recorded by Aquila"
+        )));
+    };
+    let execution_flow = ExecutionFlow::decode(payload).map_err(|err| {
+        DeadLetterError::Invalid(format!("dead letter {sequence} has no execution: {err}"))
+    })?;
+
+    let summary = DeadLetterSummary {
+        sequence,
+        origin,
+        execution_id,
+        flow_id: execution_flow.flow_id,
+        project_id: execution_flow.project_id,
+        action_identifier: header(ACTION_HEADER),
+        error: header(ERROR_HEADER).unwrap_or_default(),
+        failed_at: header(FAILED_AT_HEADER)
+            .and_then(|failed_at| failed_at.parse().ok())
+            .unwrap_or_default(),
+    };
+    Ok((summary, execution_flow))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(sequence: u64) -> DeadLetterSummary {
+        DeadLetterSummary {
+            sequence,
+            origin: "event".to_string(),
+            execution_id: format!("execution-{sequence}"),
+            flow_id: 1,
+            project_id: 1,
+            action_identifier: None,
+            error: "no responders".to_string(),
+            failed_at: 0,
+        }
+    }
+
+    /// Reads from entries at `sequences`, of which `unreadable` can't be read.
+    async fn page(
+        sequences: &[u64],
+        unreadable: &[u64],
+        after: u64,
+        limit: usize,
+    ) -> DeadLetterPage {
+        list_page(after, limit, |from| {
+            let found = sequences.iter().copied().find(|sequence| *sequence >= from);
+            async move {
+                Ok(found.map(|sequence| {
+                    (
+                        sequence,
+                        (!unreadable.contains(&sequence)).then(|| summary(sequence)),
+                    )
+                }))
+            }
+        })
+        .await
+        .expect("page")
+    }
+
+    #[test]
+    fn list_pages_continue_after_the_cursor() {
+        futures::executor::block_on(async {
+            let sequences = [2, 5, 6, 9, 12];
+
+            let first = page(&sequences, &[6], 0, 2).await;
+            assert_eq!(
+                first
+                    .dead_letters
+                    .iter()
+                    .map(|l| l.sequence)
+                    .collect::<Vec<_>>(),
+                vec![2, 5]
+            );
+            assert_eq!(first.next_after, Some(5));
+
+            // An unreadable entry is skipped but still moves the cursor on.
+            let second = page(&sequences, &[6], 5, 2).await;
+            assert_eq!(
+                second
+                    .dead_letters
+                    .iter()
+                    .map(|l| l.sequence)
+                    .collect::<Vec<_>>(),
+                vec![9, 12]
+            );
+            assert_eq!(second.next_after, Some(12));
+
+            let last = page(&sequences, &[6], 12, 2).await;
+            assert!(last.dead_letters.is_empty());
+            assert_eq!(last.next_after, None);
+        });
+    }
+
+    #[test]
+    fn recorded_headers_read_back_into_a_summary() {
+        let execution_flow = ExecutionFlow {
+            flow_id: 7,
+            project_id: 3,
+            ..Default::default()
+        };
+        let letter = DeadLetter {
+            origin: ExecutionOrigin::ActionFlow,
+            execution_id: "abc",
+            execution_flow: &execution_flow,
+            action_identifier: Some("send-email"),
+            error: "no responders\nat all".to_string(),
+        };
+
+        let (summary, read_flow) =
+            read_letter(12, &headers(&letter), execution_flow.encode_to_vec().into())
+                .expect("dead letter");
+        assert_eq!(read_flow, execution_flow);
+        assert_eq!(summary.sequence, 12);
+        assert_eq!(summary.origin, "action_flow");
+        assert_eq!(summary.execution_id, "abc");
+        assert_eq!(summary.flow_id, 7);
+        assert_eq!(summary.project_id, 3);
+        assert_eq!(summary.action_identifier.as_deref(), Some("send-email"));
+        assert_eq!(summary.error, "no responders at all");
+
+        assert!(matches!(
+            read_letter(13, &HeaderMap::new(), prost::bytes::Bytes::new()),
+            Err(DeadLetterError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+//! How an execution is handed to a runtime, in one of two modes
+//! (`execution_dispatch.mode`):
+//!
+//! - `core` publishes it on `execution.<execution_id>` with core NATS. It's
+//!   at-most-once: if no runtime is subscribed right then, it's gone.
+//! - `jetstream` publishes it into a work-queue stream on
+//!   `<subject_prefix>.<execution_id>` and waits for the stream to confirm
+//!   it stored it, retrying until it does. It stays queued until a runtime's
+//!   consumer acknowledges it, even if no runtime is running yet. The
+//!   execution id doubles as the message id, so a retry of a publish that
+//!   did arrive isn't queued twice.
+//!
+//! Flows triggered by action events don't go through here: the event waits
+//! for their reply, so they're always requested with core NATS request-reply
+//! on `execution.<execution_id>`, at most once.
+//!
+//! Cancelling an execution publishes its id on
+//! `execution_cancel.<execution_id>` with core NATS in either mode, for a
+//! runtime already running it. In `jetstream` mode, the execution is also
+//! purged from the stream if no runtime took it yet.
+
+use std::time::Duration;
+
+use async_nats::{HeaderMap, header::NATS_MESSAGE_ID, jetstream};
+use prost::Message;
+use tucana::shared::ExecutionFlow;
+
+use crate::{
+    configuration::config::{DispatchMode, ExecutionDispatch},
+    telemetry::errors,
+};
+
+/// A dispatch that failed, with the cause of its last attempt.
+#[derive(Debug)]
+pub struct DispatchError {
+    attempts: u32,
+    source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl std::fmt::Display for DispatchError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            formatter,
+            "failed to dispatch execution after {} attempt(s): {}",
+            self.attempts, self.source
+        )
+    }
+}
+
+impl std::error::Error for DispatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+#[derive(Clone)]
+enum Target {
+    Core(async_nats::Client),
+    JetStream {
+        jet_stream: jetstream::Context,
+        stream: String,
+        subject_prefix: String,
+        max_attempts: u32,
+        retry_backoff: Duration,
+    },
+}
+
+/// Shared by every execution source (Sagittarius test executions,
+/// action-triggered executions, re-driven dead letters).
+#[derive(Clone)]
+pub struct ExecutionDispatcher {
+    target: Target,
+}
+
+impl ExecutionDispatcher {
+    pub fn core(client: &async_nats::Client) -> Self {
+        Self {
+            target: Target::Core(client.clone()),
+        }
+    }
+
+    /// The dispatcher `config` asks for. In `jetstream` mode the work-queue
+    /// stream is created if needed; if that fails, the failure is recorded
+    /// and every dispatch fails until the stream exists.
+    pub async fn open(client: &async_nats::Client, config: &ExecutionDispatch) -> Self {
+        if config.mode == DispatchMode::Core {
+            return Self::core(client);
+        }
+
+        let jet_stream = jetstream::new(client.clone());
+        match jet_stream
+            .get_or_create_stream(jetstream::stream::Config {
+                name: config.stream.clone(),
+                subjects: vec![format!("{}.*", config.subject_prefix)],
+                retention: jetstream::stream::RetentionPolicy::WorkQueue,
+                ..Default::default()
+            })
+            .await
+        {
+            Ok(_) => log::info!(
+                "Dispatching executions over JetStream stream={} subject_prefix={}",
+                config.stream,
+                config.subject_prefix
+            ),
+            Err(err) => errors::record(
+                "messaging",
+                "execution.dispatch.open",
+                &err,
+                format!("stream={}", config.stream),
+            ),
+        }
+
+        Self {
+            target: Target::JetStream {
+                jet_stream,
+                stream: config.stream.clone(),
+                subject_prefix: config.subject_prefix.clone(),
+                max_attempts: config.max_attempts.max(1),
+                retry_backoff: Duration::from_millis(config.retry_backoff_ms),
+            },
+        }
+    }
+
+    pub async fn dispatch(
+        &self,
+        execution_id: &str,
+        execution_flow: &ExecutionFlow,
+    ) -> Result<(), DispatchError> {
+        let bytes = execution_flow.encode_to_vec();
+        match &self.target {
+            Target::Core(client) => client
+                .publish(format!("execution.{execution_id}"), bytes.into())
+                .await
+                .map_err(|err| DispatchError {
+                    attempts: 1,
+                    source: Box::new(err),
+                }),
+            Target::JetStream {
+                jet_stream,
+                subject_prefix,
+                max_attempts,
+                retry_backoff,
+                ..
+            } => {
+                let subject = format!("{subject_prefix}.{execution_id}");
+                let mut attempt = 1;
+                loop {
+                    let mut headers = HeaderMap::new();
+                    headers.insert(NATS_MESSAGE_ID, execution_id);
+                    let published = match jet_stream
+                        .publish_with_headers(subject.clone(), headers, bytes.clone().into())
+                        .await
+                    {
+                        Ok(ack) => ack.await.map(|_| ()).map_err(async_nats::Error::from),
+                        Err(err) => Err(async_nats::Error::from(err)),
+                    };
+
+                    match published {
+                        Ok(()) => return Ok(()),
+                        Err(source) if attempt >= *max_attempts => {
+                            return Err(DispatchError {
+                                attempts: attempt,
+                                source,
+                            });
+                        }
+                        Err(err) => {
+                            log::warn!(
+                                "Retrying execution dispatch execution_id={} attempt={} error={}",
+                                execution_id,
+                                attempt,
+                                err
+                            );
+                            tokio::time::sleep(retry_backoff.saturating_mul(attempt)).await;
+                            attempt += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tells runtimes to stop `execution_id`, and removes it from the
+    /// work-queue stream if it's still queued there. A failure is recorded
+    /// and otherwise ignored; the caller has given up on the execution either way.
+    pub async fn cancel(&self, execution_id: &str) {
+        let client = match &self.target {
+            Target::Core(client) => client.clone(),
+            Target::JetStream {
+                jet_stream,
+                stream,
+                subject_prefix,
+                ..
+            } => {
+                let purged = match jet_stream.get_stream_no_info(stream).await {
+                    Ok(stream) => stream
+                        .purge()
+                        .filter(format!("{subject_prefix}.{execution_id}"))
+                        .await
+                        .map(|_| ())
+                        .map_err(async_nats::Error::from),
+                    Err(err) => Err(async_nats::Error::from(err)),
+                };
+                if let Err(err) = purged {
+                    errors::record(
+                        "messaging",
+                        "execution.cancel.purge",
+                        err.as_ref(),
+                        format!("execution_id={execution_id} stream={stream}"),
+                    );
+                }
+                jet_stream.client()
+            }
+        };
+
+        if let Err(err) = client
+            .publish(
+                format!("execution_cancel.{execution_id}"),
+                execution_id.to_string().into(),
+            )
+            .await
+        {
+            errors::record(
+                "messaging",
+                "execution.cancel.publish",
+                &err,
+                format!("execution_id={execution_id}"),
+            );
+        }
+    }
+}
@@ -0,0 +1,882 @@
+//! The one record of every execution Aquila is waiting on, whatever asked
+//! for it, so the admin API can show what's running and for how long.
+//!
+//! An entry also carries whatever routing its result needs (an
+//! [`ExecutionRoute`]): the NATS reply subject of an execution forwarded to
+//! an action, or the stream of an action waiting on a flow execution. The
+//! stores for those origins are views onto this registry rather than
+//! copies of it. A test execution's entry is also where its `flow_id` is
+//! recovered from when a runtime doesn't echo it, and an event's flow
+//! executions are where the action that sent the event cancels them from,
+//! see [`ExecutionRegistry::cancel`].
+//!
+//! Entries are filed by origin and execution id, so two origins sharing an
+//! id (a sub flow is requested under its parent execution's id) don't
+//! replace each other. They are bounded per origin and expire purely as a
+//! safety net against executions that never finish;
+//! [`ExecutionRegistry::finish`] removes an entry as soon as its execution
+//! is done.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_nats::Subject;
+use serde::Serialize;
+use tokio::sync::{Mutex, Notify};
+use tucana::aquila::ActionTransferResponse;
+
+/// How long an entry is kept if its execution never finishes.
+const EXECUTION_TTL: Duration = Duration::from_secs(30 * 60);
+/// Upper bound on tracked executions of one origin, enforced by evicting
+/// that origin's entry closest to expiry when a new one would go over it.
+/// Per origin, so a flood of one kind can't push out another's entries.
+const MAX_EXECUTIONS_PER_ORIGIN: usize = 10_000;
+
+pub type ResponseSender = tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>;
+
+/// Which kind of request an execution was started for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExecutionOrigin {
+    /// A flow triggered by an action event.
+    Event,
+    /// A flow execution an action asked for.
+    ActionFlow,
+    /// A sub flow an action asked a runtime to evaluate.
+    SubFlow,
+    /// A test execution Sagittarius asked for.
+    TestExecution,
+    /// A function a runtime asked a connected action to run.
+    ActionExecution,
+}
+
+impl ExecutionOrigin {
+    /// The order [`ExecutionRegistry::get`] looks through origins in. An
+    /// execution that owns its id comes before a sub flow, which is
+    /// requested under the id of the execution asking for it.
+    const LOOKUP_ORDER: [ExecutionOrigin; 5] = [
+        ExecutionOrigin::Event,
+        ExecutionOrigin::ActionFlow,
+        ExecutionOrigin::TestExecution,
+        ExecutionOrigin::ActionExecution,
+        ExecutionOrigin::SubFlow,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExecutionOrigin::Event => "event",
+            ExecutionOrigin::ActionFlow => "action_flow",
+            ExecutionOrigin::SubFlow => "sub_flow",
+            ExecutionOrigin::TestExecution => "test_execution",
+            ExecutionOrigin::ActionExecution => "action_execution",
+        }
+    }
+}
+
+/// Where an execution is at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionState {
+    /// Being handed to a runtime.
+    Dispatching,
+    /// A runtime has it.
+    Running,
+    /// Forwarded to an action, which hasn't answered yet.
+    AwaitingAction,
+}
+
+impl ExecutionState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExecutionState::Dispatching => "dispatching",
+            ExecutionState::Running => "running",
+            ExecutionState::AwaitingAction => "awaiting_action",
+        }
+    }
+}
+
+/// What's known about an execution when it starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackedExecution {
+    pub origin: ExecutionOrigin,
+    /// `None` where the request doesn't say, e.g. for a sub flow.
+    pub flow_id: Option<i64>,
+    pub project_id: Option<i64>,
+    /// The action that asked for the execution, or was asked to run it.
+    pub action_identifier: Option<String>,
+}
+
+/// Where an execution's result goes, beyond whoever tracked it.
+pub enum ExecutionRoute {
+    /// Whoever tracked the execution waits for its result itself.
+    Awaited,
+    /// An event's flow execution, which the event's action can cancel with
+    /// [`ExecutionRegistry::cancel`]. `cancelled` fires once it did.
+    Cancellable {
+        /// The correlation id of the event the execution was started for.
+        correlation_id: Option<String>,
+        cancelled: Arc<Notify>,
+    },
+    /// Forwarded to an action, whose answer is published to `reply_subject`.
+    Reply {
+        reply_subject: Subject,
+        /// The function the action was asked to run.
+        function_identifier: String,
+        /// When Aquila stops waiting and answers with a timeout instead.
+        deadline: Instant,
+        /// Which of the per-connection stores the execution belongs to.
+        store: u64,
+    },
+    /// An action's flow execution, whose result is delivered on `sender`.
+    ActionStream {
+        sender: ResponseSender,
+        /// When the action stops waiting and receives a timeout instead.
+        deadline: Instant,
+    },
+}
+
+impl ExecutionRoute {
+    fn deadline(&self) -> Option<Instant> {
+        match self {
+            ExecutionRoute::Reply { deadline, .. }
+            | ExecutionRoute::ActionStream { deadline, .. } => Some(*deadline),
+            ExecutionRoute::Awaited | ExecutionRoute::Cancellable { .. } => None,
+        }
+    }
+}
+
+/// An entry taken out of the registry, with everything needed to answer it.
+pub struct FinishedExecution {
+    pub execution_id: String,
+    /// Every other id the execution was also filed under.
+    pub aliases: Vec<String>,
+    pub execution: TrackedExecution,
+    pub route: ExecutionRoute,
+    pub started: Instant,
+}
+
+struct Entry {
+    aliases: Vec<String>,
+    execution: TrackedExecution,
+    route: ExecutionRoute,
+    state: ExecutionState,
+    started_at: SystemTime,
+    started: Instant,
+    expires_at: Instant,
+}
+
+type Key = (ExecutionOrigin, String);
+
+/// Every entry, plus which primary id each alias stands for.
+#[derive(Default)]
+struct Entries {
+    by_id: HashMap<Key, Entry>,
+    aliases: HashMap<Key, String>,
+}
+
+/// A tracked execution as reported by the admin API.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ExecutionSnapshot {
+    pub execution_id: String,
+    pub origin: &'static str,
+    pub state: &'static str,
+    pub flow_id: Option<i64>,
+    pub project_id: Option<i64>,
+    pub action_identifier: Option<String>,
+    pub started_at_unix_ms: u128,
+    pub age_ms: u128,
+}
+
+#[derive(Clone)]
+pub struct ExecutionRegistry {
+    entries: Arc<Mutex<Entries>>,
+    max_per_origin: usize,
+}
+
+impl Default for ExecutionRegistry {
+    fn default() -> Self {
+        Self {
+            entries: Arc::default(),
+            max_per_origin: MAX_EXECUTIONS_PER_ORIGIN,
+        }
+    }
+}
+
+impl ExecutionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts tracking `execution` under `execution_id` in `state`,
+    /// replacing an execution of the same origin already tracked under that
+    /// id. A no-op for an empty `execution_id`, since it could never be
+    /// looked up.
+    pub async fn track(
+        &self,
+        execution_id: &str,
+        execution: TrackedExecution,
+        state: ExecutionState,
+    ) {
+        self.track_routed(
+            execution_id,
+            Vec::new(),
+            execution,
+            state,
+            ExecutionRoute::Awaited,
+        )
+        .await;
+    }
+
+    /// Like [`Self::track`], but the execution's action can also cancel it
+    /// with [`Self::cancel`], by its id or by `correlation_id`. The returned
+    /// notification fires once it's cancelled.
+    pub async fn track_cancellable(
+        &self,
+        execution_id: &str,
+        execution: TrackedExecution,
+        state: ExecutionState,
+        correlation_id: Option<String>,
+    ) -> Arc<Notify> {
+        let cancelled = Arc::new(Notify::new());
+        self.track_routed(
+            execution_id,
+            Vec::new(),
+            execution,
+            state,
+            ExecutionRoute::Cancellable {
+                correlation_id,
+                cancelled: cancelled.clone(),
+            },
+        )
+        .await;
+        cancelled
+    }
+
+    /// Like [`Self::track`], for an execution whose result goes to `route`.
+    /// It can also be looked up by any of `aliases`. It's kept at least
+    /// until the route's deadline, even past the usual expiry.
+    pub async fn track_routed(
+        &self,
+        execution_id: &str,
+        aliases: Vec<String>,
+        execution: TrackedExecution,
+        state: ExecutionState,
+        route: ExecutionRoute,
+    ) {
+        if execution_id.is_empty() {
+            log::warn!(
+                "Cannot track execution because execution_id is empty origin={}",
+                execution.origin.as_str()
+            );
+            return;
+        }
+
+        let origin = execution.origin;
+        let mut entries = self.entries.lock().await;
+        let now = Instant::now();
+        let expired_count = entries.prune_expired(now);
+        let key = (origin, execution_id.to_string());
+        let evicted_execution_id =
+            if !entries.by_id.contains_key(&key) && entries.count(origin) >= self.max_per_origin {
+                entries.remove_soonest_to_expire(origin)
+            } else {
+                entries.remove(&key);
+                None
+            };
+
+        if let Some(evicted_execution_id) = evicted_execution_id {
+            log::warn!(
+                "Evicted tracked execution because the registry is full evicted_execution_id={} origin={} max_entries={}",
+                evicted_execution_id,
+                origin.as_str(),
+                self.max_per_origin
+            );
+        }
+        log::debug!(
+            "Tracking execution execution_id={} origin={} flow_id={:?} state={} expired_entries={}",
+            execution_id,
+            origin.as_str(),
+            execution.flow_id,
+            state.as_str(),
+            expired_count
+        );
+
+        let expires_at = route.deadline().map_or(now + EXECUTION_TTL, |deadline| {
+            deadline.max(now + EXECUTION_TTL)
+        });
+        for alias in &aliases {
+            entries
+                .aliases
+                .insert((origin, alias.clone()), execution_id.to_string());
+        }
+        entries.by_id.insert(
+            key,
+            Entry {
+                aliases,
+                execution,
+                route,
+                state,
+                started_at: SystemTime::now(),
+                started: now,
+                expires_at,
+            },
+        );
+    }
+
+    /// Moves a tracked execution to `state`. Unknown ids are ignored.
+    pub async fn set_state(
+        &self,
+        origin: ExecutionOrigin,
+        execution_id: &str,
+        state: ExecutionState,
+    ) {
+        let mut entries = self.entries.lock().await;
+        if let Some(key) = entries.key_of(origin, execution_id)
+            && let Some(entry) = entries.by_id.get_mut(&key)
+        {
+            entry.state = state;
+        }
+    }
+
+    /// Stops tracking `execution_id` and returns what was known about it,
+    /// unless it had already expired.
+    pub async fn finish(
+        &self,
+        origin: ExecutionOrigin,
+        execution_id: &str,
+    ) -> Option<TrackedExecution> {
+        if execution_id.is_empty() {
+            return None;
+        }
+
+        let mut entries = self.entries.lock().await;
+        let key = entries.key_of(origin, execution_id)?;
+        let entry = entries.remove(&key)?;
+        if entry.expires_at > Instant::now() {
+            Some(entry.execution)
+        } else {
+            log::debug!(
+                "Dropped expired tracked execution execution_id={} origin={}",
+                execution_id,
+                origin.as_str()
+            );
+            None
+        }
+    }
+
+    /// Stops tracking the execution filed under `execution_id` (or one of
+    /// its aliases) and returns it, but only if `take` agrees.
+    pub async fn take_if(
+        &self,
+        origin: ExecutionOrigin,
+        execution_id: &str,
+        take: impl FnOnce(&TrackedExecution, &ExecutionRoute) -> bool,
+    ) -> Option<FinishedExecution> {
+        let mut entries = self.entries.lock().await;
+        let key = entries.key_of(origin, execution_id)?;
+        let entry = entries.by_id.get(&key)?;
+        if !take(&entry.execution, &entry.route) {
+            return None;
+        }
+        let entry = entries.remove(&key)?;
+        Some(entry.finished(key.1))
+    }
+
+    /// Stops tracking every execution of `origin` whose route `take` picks,
+    /// and returns them.
+    pub async fn take_all(
+        &self,
+        origin: ExecutionOrigin,
+        take: impl Fn(&ExecutionRoute) -> bool,
+    ) -> Vec<FinishedExecution> {
+        let mut entries = self.entries.lock().await;
+        let keys: Vec<Key> = entries
+            .by_id
+            .iter()
+            .filter(|((entry_origin, _), entry)| *entry_origin == origin && take(&entry.route))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| {
+                let entry = entries.remove(&key)?;
+                Some(entry.finished(key.1))
+            })
+            .collect()
+    }
+
+    /// How many executions of `origin` have a route `count` picks.
+    pub async fn count(
+        &self,
+        origin: ExecutionOrigin,
+        count: impl Fn(&ExecutionRoute) -> bool,
+    ) -> usize {
+        self.entries
+            .lock()
+            .await
+            .by_id
+            .iter()
+            .filter(|((entry_origin, _), entry)| *entry_origin == origin && count(&entry.route))
+            .count()
+    }
+
+    /// Cancels every cancellable execution `action_identifier` started whose
+    /// id or correlation id is `id`, and returns their ids. They stay
+    /// tracked until whoever waits on them finishes them.
+    pub async fn cancel(&self, action_identifier: &str, id: &str) -> Vec<String> {
+        let entries = self.entries.lock().await;
+        entries
+            .by_id
+            .iter()
+            .filter(|(_, entry)| {
+                entry.execution.action_identifier.as_deref() == Some(action_identifier)
+            })
+            .filter_map(|((_, execution_id), entry)| {
+                let ExecutionRoute::Cancellable {
+                    correlation_id,
+                    cancelled,
+                } = &entry.route
+                else {
+                    return None;
+                };
+                (execution_id == id || correlation_id.as_deref() == Some(id)).then(|| {
+                    cancelled.notify_one();
+                    execution_id.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// The execution tracked under `execution_id`, whatever its origin. If
+    /// several origins track one under that id, the first in
+    /// [`ExecutionOrigin::LOOKUP_ORDER`] is returned.
+    pub async fn get(&self, execution_id: &str) -> Option<ExecutionSnapshot> {
+        let entries = self.entries.lock().await;
+        let now = Instant::now();
+        ExecutionOrigin::LOOKUP_ORDER
+            .into_iter()
+            .find_map(|origin| {
+                entries
+                    .by_id
+                    .get(&(origin, execution_id.to_string()))
+                    .filter(|entry| entry.expires_at > now)
+                    .map(|entry| entry.snapshot(execution_id))
+            })
+    }
+
+    /// Every tracked execution, oldest first.
+    pub async fn snapshot(&self) -> Vec<ExecutionSnapshot> {
+        let mut entries = self.entries.lock().await;
+        entries.prune_expired(Instant::now());
+
+        let mut snapshots: Vec<_> = entries
+            .by_id
+            .iter()
+            .map(|((_, execution_id), entry)| entry.snapshot(execution_id))
+            .collect();
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.age_ms));
+        snapshots
+    }
+}
+
+impl Entries {
+    /// The key of the entry filed under `id`, directly or as an alias.
+    fn key_of(&self, origin: ExecutionOrigin, id: &str) -> Option<Key> {
+        let key = (origin, id.to_string());
+        if self.by_id.contains_key(&key) {
+            return Some(key);
+        }
+        let primary = self.aliases.get(&key)?;
+        Some((origin, primary.clone()))
+    }
+
+    /// Removes the entry under `key` along with the aliases still pointing at it.
+    fn remove(&mut self, key: &Key) -> Option<Entry> {
+        let entry = self.by_id.remove(key)?;
+        for alias in &entry.aliases {
+            let alias_key = (key.0, alias.clone());
+            if self.aliases.get(&alias_key) == Some(&key.1) {
+                self.aliases.remove(&alias_key);
+            }
+        }
+        Some(entry)
+    }
+
+    fn count(&self, origin: ExecutionOrigin) -> usize {
+        self.by_id
+            .keys()
+            .filter(|(entry_origin, _)| *entry_origin == origin)
+            .count()
+    }
+
+    fn prune_expired(&mut self, now: Instant) -> usize {
+        let expired: Vec<Key> = self
+            .by_id
+            .iter()
+            .filter(|(_, entry)| entry.expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.remove(key);
+        }
+        expired.len()
+    }
+
+    /// Evicts whichever entry of `origin` is closest to expiring, which is
+    /// also the one tracked the longest.
+    fn remove_soonest_to_expire(&mut self, origin: ExecutionOrigin) -> Option<String> {
+        let soonest = self
+            .by_id
+            .iter()
+            .filter(|((entry_origin, _), _)| *entry_origin == origin)
+            .min_by_key(|(_, entry)| entry.expires_at)
+            .map(|(key, _)| key.clone())?;
+
+        self.remove(&soonest);
+        Some(soonest.1)
+    }
+}
+
+impl Entry {
+    fn finished(self, execution_id: String) -> FinishedExecution {
+        FinishedExecution {
+            execution_id,
+            aliases: self.aliases,
+            execution: self.execution,
+            route: self.route,
+            started: self.started,
+        }
+    }
+
+    fn snapshot(&self, execution_id: &str) -> ExecutionSnapshot {
+        ExecutionSnapshot {
+            execution_id: execution_id.to_string(),
+            origin: self.execution.origin.as_str(),
+            state: self.state.as_str(),
+            flow_id: self.execution.flow_id,
+            project_id: self.execution.project_id,
+            action_identifier: self.execution.action_identifier.clone(),
+            started_at_unix_ms: self
+                .started_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_millis())
+                .unwrap_or_default(),
+            age_ms: self.started.elapsed().as_millis(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_execution(flow_id: i64) -> TrackedExecution {
+        TrackedExecution {
+            origin: ExecutionOrigin::TestExecution,
+            flow_id: Some(flow_id),
+            project_id: Some(1),
+            action_identifier: None,
+        }
+    }
+
+    #[test]
+    fn finish_returns_and_removes_the_execution() {
+        futures::executor::block_on(async {
+            let registry = ExecutionRegistry::new();
+            registry
+                .track("execution-1", test_execution(42), ExecutionState::Running)
+                .await;
+
+            assert_eq!(
+                registry
+                    .finish(ExecutionOrigin::TestExecution, "execution-1")
+                    .await,
+                Some(test_execution(42))
+            );
+            assert_eq!(
+                registry
+                    .finish(ExecutionOrigin::TestExecution, "execution-1")
+                    .await,
+                None
+            );
+        });
+    }
+
+    #[test]
+    fn empty_execution_id_is_ignored() {
+        futures::executor::block_on(async {
+            let registry = ExecutionRegistry::new();
+            registry
+                .track("", test_execution(42), ExecutionState::Running)
+                .await;
+
+            assert!(registry.snapshot().await.is_empty());
+            assert_eq!(
+                registry.finish(ExecutionOrigin::TestExecution, "").await,
+                None
+            );
+        });
+    }
+
+    #[test]
+    fn expired_executions_are_dropped() {
+        futures::executor::block_on(async {
+            let registry = ExecutionRegistry::new();
+            registry
+                .track("execution-1", test_execution(42), ExecutionState::Running)
+                .await;
+
+            {
+                let mut entries = registry.entries.lock().await;
+                entries
+                    .by_id
+                    .get_mut(&(ExecutionOrigin::TestExecution, "execution-1".to_string()))
+                    .unwrap()
+                    .expires_at = Instant::now() - Duration::from_secs(1);
+            }
+
+            assert!(registry.get("execution-1").await.is_none());
+            assert_eq!(
+                registry
+                    .finish(ExecutionOrigin::TestExecution, "execution-1")
+                    .await,
+                None
+            );
+        });
+    }
+
+    #[test]
+    fn cancel_wakes_the_actions_executions_by_id_or_correlation_id() {
+        futures::executor::block_on(async {
+            let registry = ExecutionRegistry::new();
+            let event_execution = |action_identifier: &str| TrackedExecution {
+                origin: ExecutionOrigin::Event,
+                flow_id: Some(1),
+                project_id: Some(1),
+                action_identifier: Some(action_identifier.to_string()),
+            };
+            let first = registry
+                .track_cancellable(
+                    "first",
+                    event_execution("webhook"),
+                    ExecutionState::Running,
+                    Some("order-42".to_string()),
+                )
+                .await;
+            let second = registry
+                .track_cancellable(
+                    "second",
+                    event_execution("webhook"),
+                    ExecutionState::Running,
+                    Some("order-42".to_string()),
+                )
+                .await;
+            registry
+                .track_cancellable(
+                    "third",
+                    event_execution("other"),
+                    ExecutionState::Running,
+                    Some("order-42".to_string()),
+                )
+                .await;
+
+            assert!(registry.cancel("other", "first").await.is_empty());
+            let mut cancelled = registry.cancel("webhook", "order-42").await;
+            cancelled.sort();
+            assert_eq!(cancelled, vec!["first".to_string(), "second".to_string()]);
+            first.notified().await;
+            second.notified().await;
+
+            assert_eq!(registry.cancel("webhook", "first").await, vec!["first"]);
+            registry
+                .track("plain", event_execution("webhook"), ExecutionState::Running)
+                .await;
+            assert!(registry.cancel("webhook", "plain").await.is_empty());
+        });
+    }
+
+    #[test]
+    fn lookup_reports_origin_and_current_state() {
+        futures::executor::block_on(async {
+            let registry = ExecutionRegistry::new();
+            registry
+                .track(
+                    "execution-1",
+                    test_execution(42),
+                    ExecutionState::Dispatching,
+                )
+                .await;
+            registry
+                .set_state(
+                    ExecutionOrigin::TestExecution,
+                    "execution-1",
+                    ExecutionState::Running,
+                )
+                .await;
+
+            let snapshot = registry
+                .get("execution-1")
+                .await
+                .expect("execution should be tracked");
+
+            assert_eq!(snapshot.origin, "test_execution");
+            assert_eq!(snapshot.state, "running");
+            assert_eq!(snapshot.flow_id, Some(42));
+            assert_eq!(registry.snapshot().await, vec![snapshot]);
+        });
+    }
+
+    #[test]
+    fn origins_sharing_an_execution_id_are_tracked_apart() {
+        futures::executor::block_on(async {
+            let registry = ExecutionRegistry::new();
+            let execution = |origin| TrackedExecution {
+                origin,
+                flow_id: None,
+                project_id: None,
+                action_identifier: Some("action".to_string()),
+            };
+            registry
+                .track(
+                    "parent",
+                    execution(ExecutionOrigin::ActionExecution),
+                    ExecutionState::AwaitingAction,
+                )
+                .await;
+
+            // A sub flow is requested under the id of the execution asking for it.
+            registry
+                .track(
+                    "parent",
+                    execution(ExecutionOrigin::SubFlow),
+                    ExecutionState::Running,
+                )
+                .await;
+            assert_eq!(registry.snapshot().await.len(), 2);
+            registry.finish(ExecutionOrigin::SubFlow, "parent").await;
+
+            let remaining = registry.get("parent").await.expect("still tracked");
+            assert_eq!(remaining.origin, "action_execution");
+        });
+    }
+
+    #[test]
+    fn get_prefers_the_execution_owning_an_id_over_its_sub_flow() {
+        futures::executor::block_on(async {
+            let registry = ExecutionRegistry::new();
+            let execution = |origin| TrackedExecution {
+                origin,
+                flow_id: None,
+                project_id: None,
+                action_identifier: Some("action".to_string()),
+            };
+            // Tracked first, so insertion order can't be what decides.
+            registry
+                .track(
+                    "parent",
+                    execution(ExecutionOrigin::SubFlow),
+                    ExecutionState::Running,
+                )
+                .await;
+            registry
+                .track(
+                    "parent",
+                    execution(ExecutionOrigin::ActionFlow),
+                    ExecutionState::Running,
+                )
+                .await;
+
+            for _ in 0..10 {
+                let found = registry.get("parent").await.expect("tracked");
+                assert_eq!(found.origin, "action_flow");
+            }
+
+            registry.finish(ExecutionOrigin::ActionFlow, "parent").await;
+            let found = registry
+                .get("parent")
+                .await
+                .expect("sub flow still tracked");
+            assert_eq!(found.origin, "sub_flow");
+        });
+    }
+
+    #[test]
+    fn a_full_origin_only_evicts_its_own_executions() {
+        futures::executor::block_on(async {
+            let registry = ExecutionRegistry {
+                max_per_origin: 2,
+                ..ExecutionRegistry::new()
+            };
+            registry
+                .track("test", test_execution(42), ExecutionState::Running)
+                .await;
+            for execution_id in ["first", "second", "third"] {
+                registry
+                    .track(
+                        execution_id,
+                        TrackedExecution {
+                            origin: ExecutionOrigin::Event,
+                            flow_id: Some(1),
+                            project_id: Some(1),
+                            action_identifier: None,
+                        },
+                        ExecutionState::Running,
+                    )
+                    .await;
+            }
+
+            assert_eq!(
+                registry
+                    .finish(ExecutionOrigin::TestExecution, "test")
+                    .await,
+                Some(test_execution(42))
+            );
+            assert!(registry.get("first").await.is_none());
+            assert!(registry.get("third").await.is_some());
+        });
+    }
+
+    #[test]
+    fn routed_executions_are_found_by_alias_and_taken_once() {
+        futures::executor::block_on(async {
+            let registry = ExecutionRegistry::new();
+            let (sender, _receiver) = tokio::sync::mpsc::channel(1);
+            registry
+                .track_routed(
+                    "payload-id",
+                    vec!["subject-id".to_string()],
+                    TrackedExecution {
+                        origin: ExecutionOrigin::ActionFlow,
+                        flow_id: Some(1),
+                        project_id: Some(1),
+                        action_identifier: Some("action".to_string()),
+                    },
+                    ExecutionState::Dispatching,
+                    ExecutionRoute::ActionStream {
+                        sender,
+                        deadline: Instant::now() + Duration::from_secs(60),
+                    },
+                )
+                .await;
+
+            assert!(
+                registry
+                    .take_if(ExecutionOrigin::ActionFlow, "subject-id", |_, _| false)
+                    .await
+                    .is_none()
+            );
+            let taken = registry
+                .take_if(ExecutionOrigin::ActionFlow, "subject-id", |_, _| true)
+                .await
+                .expect("execution should be found by alias");
+            assert_eq!(taken.execution_id, "payload-id");
+            assert_eq!(taken.aliases, vec!["subject-id".to_string()]);
+            assert!(
+                registry
+                    .take_if(ExecutionOrigin::ActionFlow, "payload-id", |_, _| true)
+                    .await
+                    .is_none()
+            );
+        });
+    }
+}
@@ -2,11 +2,21 @@
 //! on it: computing a flow's key and checking whether a key belongs to a
 //! given flow id. There is no secondary index, so every "find by flow id"
 //! lookup elsewhere in the codebase is a scan using [`key_has_flow_id`].
+//!
+//! Executions are handed to runtimes by [`dispatch`]; those that can't be
+//! end up in [`dead_letters`]. Those in flight are tracked in [`executions`].
+//! The output schemas their results are validated against are cached in
+//! [`output_schemas`].
+
+pub mod dead_letters;
+pub mod dispatch;
+pub mod executions;
+pub mod output_schemas;
 
 use futures::TryStreamExt;
 use prost::Message;
 use tucana::aquila::ActionFlow;
-use tucana::shared::{ExecutionFlow, ValidationFlow};
+use tucana::shared::ValidationFlow;
 
 /// Every flow identifier has this key
 /// `<type>.<project_slug>.<project_id>.<flow_id>`
@@ -53,83 +63,61 @@ pub async fn load_validation_flow_by_id(
     store: &async_nats::jetstream::kv::Store,
     flow_id: i64,
 ) -> Option<ValidationFlow> {
-    let mut keys = match store.keys().await {
-        Ok(keys) => keys,
+    match find_validation_flow_by_id(store, flow_id).await {
+        Ok(Some(flow)) => Some(flow),
+        Ok(None) => {
+            log::error!("Validation flow was not found flow_id={}", flow_id);
+            None
+        }
         Err(err) => {
-            log::error!(
-                "Failed to list validation flow keys flow_id={} error={:?}",
-                flow_id,
-                err
-            );
-            return None;
+            log::error!("{} flow_id={}", err, flow_id);
+            None
         }
-    };
+    }
+}
+
+/// Like [`load_validation_flow_by_id`], for callers to whom a flow that
+/// isn't stored (any more) is no error: that's `Ok(None)`, and `Err` only
+/// describes why the store couldn't be read.
+pub async fn find_validation_flow_by_id(
+    store: &async_nats::jetstream::kv::Store,
+    flow_id: i64,
+) -> Result<Option<ValidationFlow>, String> {
+    let mut keys = store
+        .keys()
+        .await
+        .map_err(|err| format!("Failed to list validation flow keys error={:?}", err))?;
 
     let key = loop {
         match keys.try_next().await {
             Ok(Some(key)) if key_has_flow_id(&key, flow_id) => break key,
             Ok(Some(_)) => {}
-            Ok(None) => {
-                log::error!("Validation flow was not found flow_id={}", flow_id);
-                return None;
-            }
+            Ok(None) => return Ok(None),
             Err(err) => {
-                log::error!(
-                    "Failed while scanning validation flow keys flow_id={} error={:?}",
-                    flow_id,
+                return Err(format!(
+                    "Failed while scanning validation flow keys error={:?}",
                     err
-                );
-                return None;
+                ));
             }
         }
     };
 
     match store.get(&key).await {
-        Ok(Some(bytes)) => match ValidationFlow::decode(bytes) {
-            Ok(flow) => Some(flow),
-            Err(err) => {
-                log::error!(
-                    "Failed to decode validation flow flow_id={} error={:?}",
-                    flow_id,
-                    err
-                );
-                None
-            }
-        },
-        Ok(None) => {
-            log::error!(
-                "Validation flow disappeared after key resolution flow_id={} key={}",
-                flow_id,
-                key
-            );
-            None
-        }
-        Err(err) => {
-            log::error!(
-                "Failed to fetch validation flow flow_id={} key={} error={:?}",
-                flow_id,
-                key,
-                err
-            );
-            None
-        }
+        Ok(Some(bytes)) => ValidationFlow::decode(bytes).map(Some).map_err(|err| {
+            format!(
+                "Failed to decode validation flow key={} error={:?}",
+                key, err
+            )
+        }),
+        // Deleted between listing and reading it.
+        Ok(None) => Ok(None),
+        Err(err) => Err(format!(
+            "Failed to fetch validation flow key={} error={:?}",
+            key, err
+        )),
     }
 }
 
-/// Publishes `execution_flow` onto the NATS execution bus under
-/// `execution.<execution_id>`, the subject a runtime picks the request up
-/// from - shared by every execution source (Sagittarius test executions,
-/// action-triggered executions).
-pub async fn dispatch_execution(
-    nats_client: &async_nats::Client,
-    execution_id: &str,
-    execution_flow: &ExecutionFlow,
-) -> Result<(), async_nats::PublishError> {
-    let bytes = execution_flow.encode_to_vec();
-    let topic = format!("execution.{}", execution_id);
-    nats_client.publish(topic, bytes.into()).await
-}
-
 /// A change to the flow store, broadcast so every connected action can keep
 /// its own view of the flows it owns in sync - see [`flow_belongs_to_action`].
 #[derive(Clone, Debug)]
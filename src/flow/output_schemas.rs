@@ -0,0 +1,177 @@
+//! The output schemas of stored flows by flow id, so validating a runtime's
+//! result doesn't scan the flow store every time. The cache follows the
+//! [`FlowChange`] broadcast; a flow it hasn't seen a change for is looked up
+//! once and then remembered, also when no such flow is stored.
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_nats::jetstream::kv::Store;
+use tokio::sync::{RwLock, broadcast};
+use tucana::shared::{Struct, ValidationFlow};
+
+use crate::flow::{FlowChange, find_validation_flow_by_id};
+
+/// What a flow's results have to match.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputSchema {
+    Declared(Arc<Struct>),
+    /// The flow declares no output schema, so any result passes.
+    Undeclared,
+    /// No flow is stored under the id, e.g. because it was deleted.
+    UnknownFlow,
+}
+
+impl OutputSchema {
+    fn of(flow: &ValidationFlow) -> Self {
+        match &flow.output_schema {
+            Some(schema) => OutputSchema::Declared(Arc::new(schema.clone())),
+            None => OutputSchema::Undeclared,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct OutputSchemas {
+    schemas: Arc<RwLock<HashMap<i64, OutputSchema>>>,
+}
+
+impl OutputSchemas {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the cache in sync with `changes` until the sender is dropped.
+    /// Changes missed by lagging behind drop the whole cache, so every flow
+    /// is looked up again.
+    pub fn follow(&self, mut changes: broadcast::Receiver<FlowChange>) {
+        let schemas = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) => schemas.apply(&change).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!(
+                            "Output schema cache fell behind flow changes, clearing it skipped={}",
+                            skipped
+                        );
+                        schemas.schemas.write().await.clear();
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    /// The output schema of `flow_id`, read from `store` the first time it's
+    /// asked for. `Err` describes why the store couldn't be read; nothing is
+    /// cached then.
+    pub async fn get(&self, store: &Store, flow_id: i64) -> Result<OutputSchema, String> {
+        if let Some(schema) = self.cached(flow_id).await {
+            return Ok(schema);
+        }
+
+        let schema = match find_validation_flow_by_id(store, flow_id).await? {
+            Some(flow) => OutputSchema::of(&flow),
+            None => OutputSchema::UnknownFlow,
+        };
+        // A change that arrived during the lookup is newer than what it read.
+        Ok(self
+            .schemas
+            .write()
+            .await
+            .entry(flow_id)
+            .or_insert(schema)
+            .clone())
+    }
+
+    pub async fn apply(&self, change: &FlowChange) {
+        let (flow_id, schema) = match change {
+            FlowChange::Updated(flow) => (flow.flow_id, OutputSchema::of(flow)),
+            FlowChange::Deleted { flow_id, .. } => (*flow_id, OutputSchema::UnknownFlow),
+        };
+        self.schemas.write().await.insert(flow_id, schema);
+    }
+
+    async fn cached(&self, flow_id: i64) -> Option<OutputSchema> {
+        self.schemas.read().await.get(&flow_id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(flow_id: i64, output_schema: Option<Struct>) -> ValidationFlow {
+        ValidationFlow {
+            flow_id,
+            output_schema,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn updates_replace_the_cached_schema() {
+        futures::executor::block_on(async {
+            let schemas = OutputSchemas::new();
+            let schema = Struct::default();
+
+            schemas
+                .apply(&FlowChange::Updated(Box::new(flow(1, None))))
+                .await;
+            assert_eq!(schemas.cached(1).await, Some(OutputSchema::Undeclared));
+
+            schemas
+                .apply(&FlowChange::Updated(Box::new(flow(
+                    1,
+                    Some(schema.clone()),
+                ))))
+                .await;
+            assert_eq!(
+                schemas.cached(1).await,
+                Some(OutputSchema::Declared(Arc::new(schema)))
+            );
+            assert_eq!(schemas.cached(2).await, None);
+        });
+    }
+
+    #[test]
+    fn deleted_flows_are_remembered_as_unknown() {
+        futures::executor::block_on(async {
+            let schemas = OutputSchemas::new();
+            schemas
+                .apply(&FlowChange::Updated(Box::new(flow(
+                    1,
+                    Some(Struct::default()),
+                ))))
+                .await;
+
+            schemas
+                .apply(&FlowChange::Deleted {
+                    flow_id: 1,
+                    definition_source: "action.mail".to_string(),
+                })
+                .await;
+
+            assert_eq!(schemas.cached(1).await, Some(OutputSchema::UnknownFlow));
+        });
+    }
+
+    #[tokio::test]
+    async fn follows_broadcast_flow_changes() {
+        let (tx, rx) = broadcast::channel(4);
+        let schemas = OutputSchemas::new();
+        schemas.follow(rx);
+
+        tx.send(FlowChange::Updated(Box::new(flow(7, None))))
+            .expect("the cache should be subscribed");
+        drop(tx);
+
+        for _ in 0..100 {
+            if schemas.cached(7).await.is_some() {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(schemas.cached(7).await, Some(OutputSchema::Undeclared));
+    }
+}
@@ -4,14 +4,17 @@
 //! sync (or, in static mode, serves a fixed export with no Sagittarius at all).
 //!
 //! See [`startup`] for the static vs. dynamic mode split, [`server`] for the
-//! gRPC surface actions/runtimes talk to, and [`sagittarius`] for the
-//! clients that talk back to Sagittarius.
+//! gRPC surface actions/runtimes talk to, [`sagittarius`] for the
+//! clients that talk back to Sagittarius, and [`admin`] for the optional
+//! HTTP diagnostics API.
 
 use crate::configuration::{
     config::Config as AquilaConfig, service::ServiceConfiguration, state::AppReadiness,
 };
 use code0_flow::flow_config::load_env_file;
+use std::path::PathBuf;
 
+pub mod admin;
 pub mod authorization;
 pub mod configuration;
 pub mod flow;
@@ -42,18 +45,27 @@ async fn main() {
         .as_ref()
         .map(|config| config.opentelemetry.clone())
         .unwrap_or_default();
+    let prometheus_enabled = config_result
+        .as_ref()
+        .is_ok_and(|config| config.prometheus.enabled);
     let environment = config_result
         .as_ref()
         .map(|config| config.environment.to_string())
         .unwrap_or_else(|_| "unknown".into());
+    // With Prometheus enabled Aquila owns the meter provider (see
+    // `telemetry::prometheus`), so code0_flow must not build its own.
+    let mut code0_telemetry_config = telemetry_config.clone();
+    if prometheus_enabled {
+        code0_telemetry_config.metrics_endpoint = None;
+    }
     let telemetry = telemetry::Telemetry::initialize(
-        &telemetry_config,
+        &code0_telemetry_config,
         telemetry::TelemetrySettings {
             environment: &environment,
             default_log_level: log_level,
             service_version: crate::version::runtime_version(),
             instrumentation_name: env!("CARGO_PKG_NAME"),
-            initialize_metrics: Some(telemetry::metrics::initialize),
+            initialize_metrics: (!prometheus_enabled).then_some(telemetry::metrics::initialize),
         },
     )
     .unwrap_or_else(|error| panic!("failed to initialize telemetry: {error}"));
@@ -63,15 +75,35 @@ async fn main() {
         .unwrap_or_else(|error| panic!("failed to load Aquila configuration: {error}"));
     log::info!("Starting Aquila runtime gateway");
 
+    let prometheus = if config.prometheus.enabled {
+        let prometheus = telemetry::prometheus::PrometheusMetrics::initialize(
+            &telemetry_config,
+            &environment,
+            crate::version::runtime_version(),
+        )
+        .unwrap_or_else(|error| panic!("failed to initialize Prometheus metrics: {error}"));
+        let address = format!("{}:{}", config.prometheus.host, config.prometheus.port)
+            .parse()
+            .unwrap_or_else(|error| panic!("invalid Prometheus listen address: {error}"));
+        prometheus.serve(address);
+        Some(prometheus)
+    } else {
+        None
+    };
+
     let app_readiness = AppReadiness::new();
-    let service_config = match std::env::var_os(SERVICE_CONFIG_PATH_ENV) {
+    let service_config_path = std::env::var_os(SERVICE_CONFIG_PATH_ENV).map(PathBuf::from);
+    let service_config = match &service_config_path {
         Some(path) => ServiceConfiguration::from_path(path)
             .unwrap_or_else(|error| panic!("failed to load Aquila service configuration: {error}")),
         None => ServiceConfiguration::default(),
     };
     log::debug!("{config}");
 
-    startup::run(config, app_readiness, service_config).await;
+    startup::run(config, app_readiness, service_config, service_config_path).await;
+    if let Some(prometheus) = prometheus {
+        prometheus.shutdown();
+    }
     telemetry.shutdown();
 }
 
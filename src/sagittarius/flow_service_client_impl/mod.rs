@@ -20,7 +20,10 @@ use tucana::sagittarius_gateway::{
 };
 
 use crate::{
-    authorization::authorization::get_authentication_metadata, flow::FlowChange, telemetry::metrics,
+    authorization::authorization::get_authentication_metadata,
+    flow::FlowChange,
+    sagittarius::stream_state::{FLOW_STREAM, SagittariusStreamStates},
+    telemetry::metrics,
 };
 
 #[derive(Clone)]
@@ -173,7 +176,10 @@ impl SagittariusFlowClient {
 
     /// Opens the flow sync stream and services it until it ends or errors,
     /// at which point the caller is expected to reconnect.
-    pub async fn init_flow_stream(&mut self) -> Result<(), tonic::Status> {
+    pub async fn init_flow_stream(
+        &mut self,
+        stream_states: &SagittariusStreamStates,
+    ) -> Result<(), tonic::Status> {
         self.sagittarius_ready.store(false, Ordering::SeqCst);
 
         let request = Request::from_parts(
@@ -186,6 +192,7 @@ impl SagittariusFlowClient {
             Ok(res) => {
                 log::info!("Sagittarius flow synchronization stream established");
                 self.sagittarius_ready.store(true, Ordering::SeqCst);
+                stream_states.connected(FLOW_STREAM);
                 res
             }
             Err(status) => {
@@ -10,4 +10,5 @@ pub mod module_service_client_impl;
 pub mod retry;
 pub mod runtime_status_heartbeat;
 pub mod runtime_status_service_client_impl;
+pub mod stream_state;
 pub mod test_execution_client_impl;
@@ -1,8 +1,9 @@
 //! Client for Sagittarius' module configuration stream: forwards module
-//! configuration updates onto `action_config_tx` for the action forwarders to
-//! pick up. This used to arrive embedded in the flow synchronization stream
-//! (see [`super::flow_service_client_impl`]) but now has its own dedicated
-//! stream.
+//! configuration updates onto `action_config_tx` for the action forwarders
+//! to pick up, keeping the latest one per module for actions that log on
+//! later or fall behind. This used to arrive embedded in the flow
+//! synchronization stream (see [`super::flow_service_client_impl`]) but now
+//! has its own dedicated stream.
 
 use futures::StreamExt;
 use tokio::sync::broadcast;
@@ -11,7 +12,11 @@ use tucana::sagittarius_gateway::{
     ModuleConfigurationRequest, module_service_client::ModuleServiceClient,
 };
 
-use crate::authorization::authorization::get_authentication_metadata;
+use crate::{
+    authorization::authorization::get_authentication_metadata,
+    configuration::module_configurations::LatestModuleConfigurations,
+    sagittarius::stream_state::{MODULE_CONFIGURATION_STREAM, SagittariusStreamStates},
+};
 
 fn module_config_stats(configs: &tucana::shared::ModuleConfigurations) -> (usize, usize) {
     let project_count = configs.module_configurations.len();
@@ -29,6 +34,7 @@ pub struct SagittariusModuleConfigurationClient {
     client: ModuleServiceClient<Channel>,
     token: String,
     action_config_tx: broadcast::Sender<tucana::shared::ModuleConfigurations>,
+    latest: LatestModuleConfigurations,
 }
 
 impl SagittariusModuleConfigurationClient {
@@ -36,15 +42,17 @@ impl SagittariusModuleConfigurationClient {
         channel: Channel,
         token: String,
         action_config_tx: broadcast::Sender<tucana::shared::ModuleConfigurations>,
+        latest: LatestModuleConfigurations,
     ) -> Self {
         Self {
             client: ModuleServiceClient::new(channel),
             token,
             action_config_tx,
+            latest,
         }
     }
 
-    fn handle_response(&self, module_configurations: tucana::shared::ModuleConfigurations) {
+    async fn handle_response(&self, module_configurations: tucana::shared::ModuleConfigurations) {
         let (project_count, config_count) = module_config_stats(&module_configurations);
         log::debug!(
             "Received module configurations module_identifier={} project_count={} config_count={}",
@@ -53,6 +61,7 @@ impl SagittariusModuleConfigurationClient {
             config_count
         );
 
+        self.latest.update(&module_configurations).await;
         match self.action_config_tx.send(module_configurations) {
             Ok(receiver_count) => log::debug!(
                 "Broadcasted module configurations to action forwarders receiver_count={}",
@@ -66,7 +75,10 @@ impl SagittariusModuleConfigurationClient {
 
     /// Opens the module configuration stream and services it until it ends
     /// or errors, at which point the caller is expected to reconnect.
-    pub async fn init_configuration_stream(&mut self) -> Result<(), tonic::Status> {
+    pub async fn init_configuration_stream(
+        &mut self,
+        stream_states: &SagittariusStreamStates,
+    ) -> Result<(), tonic::Status> {
         let request = Request::from_parts(
             get_authentication_metadata(&self.token),
             Extensions::new(),
@@ -76,6 +88,7 @@ impl SagittariusModuleConfigurationClient {
         let response = match self.client.configurations(request).await {
             Ok(res) => {
                 log::info!("Sagittarius module configuration stream established");
+                stream_states.connected(MODULE_CONFIGURATION_STREAM);
                 res
             }
             Err(status) => {
@@ -93,7 +106,7 @@ impl SagittariusModuleConfigurationClient {
             match result {
                 Ok(res) => {
                     if let Some(module_configurations) = res.module_configurations {
-                        self.handle_response(module_configurations);
+                        self.handle_response(module_configurations).await;
                     } else {
                         log::warn!("Received empty Sagittarius module configuration response");
                     }
@@ -0,0 +1,148 @@
+//! Tracks where each long-lived Sagittarius stream is in its
+//! connect/run/reconnect cycle, so the admin API can report it. The reconnect
+//! loops in [`crate::startup::dynamic_mode`] move a stream between
+//! connecting and disconnected; the stream's client marks it connected once
+//! Sagittarius accepted the stream.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime},
+};
+
+use serde::Serialize;
+
+/// Names the Sagittarius streams are reported under by the admin API.
+pub const FLOW_STREAM: &str = "flow";
+pub const MODULE_CONFIGURATION_STREAM: &str = "module_configuration";
+pub const EXECUTION_STREAM: &str = "execution";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamPhase {
+    /// Waiting for the Sagittarius channel to become available.
+    Connecting,
+    /// Sagittarius accepted the stream and it is being consumed.
+    Connected,
+    /// The stream ended and is waiting out its reconnect backoff.
+    Disconnected,
+}
+
+struct StreamState {
+    phase: StreamPhase,
+    since: Instant,
+    reconnects: u64,
+    last_error: Option<String>,
+    last_connected_at: Option<SystemTime>,
+}
+
+/// A Sagittarius stream as reported by the admin API.
+#[derive(Debug, Serialize)]
+pub struct StreamStateSnapshot {
+    pub stream: &'static str,
+    pub phase: StreamPhase,
+    pub in_phase_for_secs: u64,
+    pub reconnects: u64,
+    pub last_error: Option<String>,
+    pub last_connected_at_unix_ms: Option<u128>,
+}
+
+#[derive(Clone, Default)]
+pub struct SagittariusStreamStates {
+    inner: Arc<Mutex<BTreeMap<&'static str, StreamState>>>,
+}
+
+impl SagittariusStreamStates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connecting(&self, stream: &'static str) {
+        self.update(stream, |state| {
+            if state.phase == StreamPhase::Disconnected {
+                state.reconnects += 1;
+            }
+            state.phase = StreamPhase::Connecting;
+        });
+    }
+
+    pub fn connected(&self, stream: &'static str) {
+        self.update(stream, |state| {
+            state.phase = StreamPhase::Connected;
+            state.last_connected_at = Some(SystemTime::now());
+        });
+    }
+
+    pub fn disconnected(&self, stream: &'static str, error: Option<String>) {
+        self.update(stream, |state| {
+            state.phase = StreamPhase::Disconnected;
+            state.last_error = error;
+        });
+    }
+
+    pub fn snapshot(&self) -> Vec<StreamStateSnapshot> {
+        let streams = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        streams
+            .iter()
+            .map(|(stream, state)| StreamStateSnapshot {
+                stream,
+                phase: state.phase,
+                in_phase_for_secs: state.since.elapsed().as_secs(),
+                reconnects: state.reconnects,
+                last_error: state.last_error.clone(),
+                last_connected_at_unix_ms: state.last_connected_at.and_then(|connected_at| {
+                    connected_at
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .ok()
+                        .map(|since_epoch| since_epoch.as_millis())
+                }),
+            })
+            .collect()
+    }
+
+    fn update(&self, stream: &'static str, apply: impl FnOnce(&mut StreamState)) {
+        let mut streams = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let state = streams.entry(stream).or_insert_with(|| StreamState {
+            phase: StreamPhase::Connecting,
+            since: Instant::now(),
+            reconnects: 0,
+            last_error: None,
+            last_connected_at: None,
+        });
+
+        let previous_phase = state.phase;
+        apply(state);
+        if state.phase != previous_phase {
+            state.since = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconnects_count_every_return_to_connecting() {
+        let states = SagittariusStreamStates::new();
+
+        states.connecting("flow");
+        states.connected("flow");
+        states.disconnected("flow", Some("stream reset".to_string()));
+        states.connecting("flow");
+
+        let snapshot = states.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].phase, StreamPhase::Connecting);
+        assert_eq!(snapshot[0].reconnects, 1);
+        assert_eq!(snapshot[0].last_error.as_deref(), Some("stream reset"));
+        assert!(snapshot[0].last_connected_at_unix_ms.is_some());
+    }
+}
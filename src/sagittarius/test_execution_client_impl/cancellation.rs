@@ -0,0 +1,70 @@
+//! Recognises a Sagittarius request to cancel a test execution. The
+//! protocol has no cancel message, so Sagittarius sends a
+//! `TestExecutionRequest` for the execution whose body is
+//! `{"_aquila": {"cancel": true}}`, and receives a cancelled
+//! `ExecutionResult` in place of the execution's result.
+
+use tucana::sagittarius_gateway::TestExecutionRequest;
+use tucana::shared::helper::value::to_json_value;
+use tucana::shared::{Error, ExecutionResult, execution_result};
+
+use crate::validation;
+
+/// Whether `request` cancels its execution rather than starting one.
+pub(super) fn is_cancellation(request: &TestExecutionRequest) -> bool {
+    let Some(body) = request.body.clone() else {
+        return false;
+    };
+    to_json_value(body)
+        .get("_aquila")
+        .and_then(|reserved| reserved.get("cancel"))
+        .and_then(serde_json::Value::as_bool)
+        .unwrap_or(false)
+}
+
+pub(super) fn cancelled_result(execution_identifier: String, flow_id: i64) -> ExecutionResult {
+    let now = validation::epoch_millis_now();
+    ExecutionResult {
+        execution_identifier,
+        flow_id,
+        started_at: now,
+        finished_at: now,
+        result: Some(execution_result::Result::Error(Error {
+            code: "A-FLOW-EXECUTION-000003".to_string(),
+            category: "Cancelled".to_string(),
+            message: "execution was cancelled".to_string(),
+            timestamp: now,
+            version: crate::version::runtime_version().to_string(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tucana::shared::helper::value::from_json_value;
+
+    use super::*;
+
+    fn request(body: Option<serde_json::Value>) -> TestExecutionRequest {
+        TestExecutionRequest {
+            flow_id: 1,
+            execution_identifier: "abc".to_string(),
+            body: body.map(from_json_value),
+        }
+    }
+
+    #[test]
+    fn only_a_reserved_cancel_marker_cancels() {
+        assert!(is_cancellation(&request(Some(
+            json!({ "_aquila": { "cancel": true } })
+        ))));
+        assert!(!is_cancellation(&request(Some(
+            json!({ "_aquila": { "cancel": false } })
+        ))));
+        assert!(!is_cancellation(&request(Some(json!({ "cancel": true })))));
+        assert!(!is_cancellation(&request(None)));
+    }
+}
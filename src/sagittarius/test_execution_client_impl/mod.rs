@@ -8,10 +8,9 @@
 //!
 //! - [`response_sender`] owns the outgoing half of the stream and is shared
 //!   with every task that needs to report an execution result.
-//! - [`flow_id_cache`] backs the piece of that sender that recovers a
-//!   result's `flow_id` when a runtime doesn't echo it.
+//! - [`cancellation`] recognises a request that cancels a test execution.
 
-mod flow_id_cache;
+mod cancellation;
 mod response_sender;
 
 pub use response_sender::SagittariusExecutionResponseSender;
@@ -27,31 +26,45 @@ use tucana::sagittarius_gateway::execution_service_client::ExecutionServiceClien
 use tucana::sagittarius_gateway::{ExecutionLogonRequest, Logon};
 use tucana::shared::ExecutionFlow;
 
-use crate::{authorization::authorization::get_authentication_metadata, flow, validation};
+use crate::{
+    authorization::authorization::get_authentication_metadata,
+    flow::{
+        self,
+        dead_letters::{DeadLetter, DeadLetters},
+        dispatch::ExecutionDispatcher,
+        executions::ExecutionOrigin,
+    },
+    sagittarius::stream_state::{EXECUTION_STREAM, SagittariusStreamStates},
+    telemetry::metrics,
+    validation,
+};
 
 pub struct SagittariusTestExecutionServiceClient {
-    nats_client: async_nats::Client,
+    dispatcher: ExecutionDispatcher,
     store: Arc<async_nats::jetstream::kv::Store>,
     client: ExecutionServiceClient<Channel>,
     token: String,
     response_sender: SagittariusExecutionResponseSender,
+    dead_letters: DeadLetters,
 }
 
 impl SagittariusTestExecutionServiceClient {
     pub fn new(
-        nats_client: async_nats::Client,
+        dispatcher: ExecutionDispatcher,
         store: Arc<async_nats::jetstream::kv::Store>,
         channel: Channel,
         token: String,
         response_sender: SagittariusExecutionResponseSender,
+        dead_letters: DeadLetters,
     ) -> Self {
         let client = ExecutionServiceClient::new(channel);
         Self {
-            nats_client,
+            dispatcher,
             store,
             client,
             token,
             response_sender,
+            dead_letters,
         }
     }
 
@@ -61,7 +74,7 @@ impl SagittariusTestExecutionServiceClient {
     /// plain async generator so that [`SagittariusExecutionResponseSender`]
     /// can push execution results onto it from other tasks while this loop
     /// is busy reading incoming requests.
-    pub async fn logon(&mut self) {
+    pub async fn logon(&mut self, stream_states: &SagittariusStreamStates) {
         let (tx, rx) = tokio::sync::mpsc::channel::<ExecutionLogonRequest>(10000);
         let logon = ExecutionLogonRequest {
             data: Some(Data::Logon(Logon {})),
@@ -91,6 +104,7 @@ impl SagittariusTestExecutionServiceClient {
         let mut test_execution_stream = match self.client.update(request).await {
             Ok(response) => {
                 log::info!("Sagittarius execution stream established");
+                stream_states.connected(EXECUTION_STREAM);
                 response.into_inner()
             }
             Err(error) => {
@@ -114,6 +128,12 @@ impl SagittariusTestExecutionServiceClient {
                             request.flow_id,
                             request.body.is_some()
                         );
+                        if cancellation::is_cancellation(&request) {
+                            self.cancel(request.execution_identifier, request.flow_id)
+                                .await;
+                            continue;
+                        }
+
                         let validation_flow =
                             match flow::load_validation_flow_by_id(&self.store, request.flow_id)
                                 .await
@@ -179,7 +199,11 @@ impl SagittariusTestExecutionServiceClient {
                         };
 
                         self.response_sender
-                            .remember_execution_flow(&execution_id, execution_flow.flow_id)
+                            .remember_execution_flow(
+                                &execution_id,
+                                execution_flow.flow_id,
+                                execution_flow.project_id,
+                            )
                             .await;
 
                         log::debug!(
@@ -188,12 +212,10 @@ impl SagittariusTestExecutionServiceClient {
                             execution_flow.flow_id,
                             generated_execution_id
                         );
-                        match flow::dispatch_execution(
-                            &self.nats_client,
-                            &execution_id,
-                            &execution_flow,
-                        )
-                        .await
+                        match self
+                            .dispatcher
+                            .dispatch(&execution_id, &execution_flow)
+                            .await
                         {
                             Ok(_) => {
                                 log::info!(
@@ -201,6 +223,9 @@ impl SagittariusTestExecutionServiceClient {
                                     execution_id,
                                     execution_flow.flow_id
                                 );
+                                self.response_sender
+                                    .execution_dispatched(&execution_id)
+                                    .await;
                             }
                             Err(err) => {
                                 log::error!(
@@ -209,6 +234,15 @@ impl SagittariusTestExecutionServiceClient {
                                     execution_flow.flow_id,
                                     err
                                 );
+                                self.dead_letters
+                                    .record(DeadLetter {
+                                        origin: ExecutionOrigin::TestExecution,
+                                        execution_id: &execution_id,
+                                        execution_flow: &execution_flow,
+                                        action_identifier: None,
+                                        error: err.to_string(),
+                                    })
+                                    .await;
                                 self.response_sender
                                     .forget_execution_flow(&execution_id)
                                     .await;
@@ -232,4 +266,39 @@ impl SagittariusTestExecutionServiceClient {
         log::warn!("Sagittarius execution stream ended");
         self.response_sender.clear().await;
     }
+
+    /// Stops a test execution Sagittarius no longer wants and answers it
+    /// with a cancelled result. An execution that isn't awaiting a result
+    /// (already finished, or never started) is left alone.
+    async fn cancel(&self, execution_id: String, requested_flow_id: i64) {
+        let Some(flow_id) = self
+            .response_sender
+            .take_execution_flow(&execution_id)
+            .await
+        else {
+            log::debug!(
+                "Ignored cancellation of a test execution that isn't pending execution_id={} flow_id={}",
+                execution_id,
+                requested_flow_id
+            );
+            return;
+        };
+
+        log::info!(
+            "Cancelling test execution execution_id={} flow_id={}",
+            execution_id,
+            flow_id
+        );
+        self.dispatcher.cancel(&execution_id).await;
+        metrics::execution_cancelled("test_execution");
+
+        let cancelled = cancellation::cancelled_result(execution_id, flow_id);
+        if let Err(status) = self.response_sender.send_execution_result(cancelled).await {
+            log::error!(
+                "Failed to send test execution cancellation result flow_id={} error={:?}",
+                flow_id,
+                status
+            );
+        }
+    }
 }
@@ -1,6 +1,6 @@
 //! Queues execution results onto the outgoing half of the Sagittarius
 //! execution logon stream, and fills in a result's `flow_id` from the
-//! [`ExecutionFlowIdCache`] when a runtime didn't echo it back.
+//! [`ExecutionRegistry`] when a runtime didn't echo it back.
 
 use std::sync::Arc;
 
@@ -10,7 +10,9 @@ use tucana::sagittarius_gateway::ExecutionLogonRequest;
 use tucana::sagittarius_gateway::execution_logon_request::Data;
 use tucana::shared::ExecutionResult;
 
-use super::flow_id_cache::ExecutionFlowIdCache;
+use crate::flow::executions::{
+    ExecutionOrigin, ExecutionRegistry, ExecutionState, TrackedExecution,
+};
 
 /// Handle shared between the task driving the Sagittarius execution stream
 /// and every task that needs to report an execution result back to it.
@@ -18,15 +20,18 @@ use super::flow_id_cache::ExecutionFlowIdCache;
 /// The stream sender is `None` whenever no stream is currently connected
 /// (before the first logon, or after a disconnect), in which case results
 /// are rejected rather than buffered.
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct SagittariusExecutionResponseSender {
     sender: Arc<Mutex<Option<tokio::sync::mpsc::Sender<ExecutionLogonRequest>>>>,
-    execution_flow_ids: ExecutionFlowIdCache,
+    executions: ExecutionRegistry,
 }
 
 impl SagittariusExecutionResponseSender {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(executions: ExecutionRegistry) -> Self {
+        Self {
+            sender: Arc::default(),
+            executions,
+        }
     }
 
     pub(super) async fn attach(&self, sender: tokio::sync::mpsc::Sender<ExecutionLogonRequest>) {
@@ -49,14 +54,50 @@ impl SagittariusExecutionResponseSender {
         );
     }
 
-    pub(super) async fn remember_execution_flow(&self, execution_id: &str, flow_id: i64) {
-        self.execution_flow_ids
-            .remember(execution_id, flow_id)
+    /// Tracks a test execution of `flow_id` that is about to be dispatched.
+    pub(super) async fn remember_execution_flow(
+        &self,
+        execution_id: &str,
+        flow_id: i64,
+        project_id: i64,
+    ) {
+        self.executions
+            .track(
+                execution_id,
+                TrackedExecution {
+                    origin: ExecutionOrigin::TestExecution,
+                    flow_id: Some(flow_id),
+                    project_id: Some(project_id),
+                    action_identifier: None,
+                },
+                ExecutionState::Dispatching,
+            )
+            .await;
+    }
+
+    pub(super) async fn execution_dispatched(&self, execution_id: &str) {
+        self.executions
+            .set_state(
+                ExecutionOrigin::TestExecution,
+                execution_id,
+                ExecutionState::Running,
+            )
             .await;
     }
 
     pub(super) async fn forget_execution_flow(&self, execution_id: &str) {
-        self.execution_flow_ids.forget(execution_id).await;
+        self.executions
+            .finish(ExecutionOrigin::TestExecution, execution_id)
+            .await;
+    }
+
+    /// The flow `execution_id` was dispatched for, if it's still awaiting a
+    /// result. Its result is no longer expected once this returns.
+    pub(super) async fn take_execution_flow(&self, execution_id: &str) -> Option<i64> {
+        self.executions
+            .finish(ExecutionOrigin::TestExecution, execution_id)
+            .await?
+            .flow_id
     }
 
     /// Sends `execution_result` to Sagittarius over the attached stream,
@@ -69,7 +110,7 @@ impl SagittariusExecutionResponseSender {
         let execution_id = execution_result.execution_identifier.clone();
 
         if execution_result.flow_id == 0 {
-            match self.execution_flow_ids.take(&execution_id).await {
+            match self.take_execution_flow(&execution_id).await {
                 Some(flow_id) if flow_id != 0 => {
                     log::warn!(
                         "Filled missing execution result flow_id from Aquila mapping execution_id={} flow_id={}",
@@ -155,3 +196,56 @@ fn execution_result_status(execution_result: &ExecutionResult) -> &'static str {
         None => "missing",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_returns_and_removes_the_mapping() {
+        futures::executor::block_on(async {
+            let sender = SagittariusExecutionResponseSender::new(ExecutionRegistry::new());
+            sender.remember_execution_flow("execution-1", 42, 1).await;
+
+            assert_eq!(sender.take_execution_flow("execution-1").await, Some(42));
+            assert_eq!(sender.take_execution_flow("execution-1").await, None);
+        });
+    }
+
+    #[test]
+    fn forget_removes_without_returning() {
+        futures::executor::block_on(async {
+            let sender = SagittariusExecutionResponseSender::new(ExecutionRegistry::new());
+            sender.remember_execution_flow("execution-1", 42, 1).await;
+            sender.forget_execution_flow("execution-1").await;
+
+            assert_eq!(sender.take_execution_flow("execution-1").await, None);
+        });
+    }
+
+    #[test]
+    fn other_origins_reusing_the_id_leave_the_mapping_alone() {
+        futures::executor::block_on(async {
+            let executions = ExecutionRegistry::new();
+            let sender = SagittariusExecutionResponseSender::new(executions.clone());
+            sender.remember_execution_flow("execution-1", 42, 1).await;
+            executions
+                .track(
+                    "execution-1",
+                    TrackedExecution {
+                        origin: ExecutionOrigin::SubFlow,
+                        flow_id: None,
+                        project_id: None,
+                        action_identifier: Some("action".to_string()),
+                    },
+                    ExecutionState::Running,
+                )
+                .await;
+            executions
+                .finish(ExecutionOrigin::SubFlow, "execution-1")
+                .await;
+
+            assert_eq!(sender.take_execution_flow("execution-1").await, Some(42));
+        });
+    }
+}
@@ -0,0 +1,171 @@
+//! Lets an action cancel an execution. The protocol has no cancel message,
+//! so an action sends a [`CANCEL_EVENT_TYPE`] event whose payload is
+//! `{"execution_id": "..."}`. It can cancel
+//!
+//! - a flow execution it asked Aquila to run: runtimes are told to stop it
+//!   (see [`crate::flow::dispatch`]) and the action receives a cancelled
+//!   `ActionFlowExecutionResponse` instead of its result,
+//! - an execution a runtime asked it to run: the runtime receives a
+//!   cancelled error as the action's answer, or
+//! - a flow execution one of its events triggered: runtimes are told to stop
+//!   it, and the event's results list it as cancelled. Since an event's
+//!   execution ids are only known from its results, a payload of
+//!   `{"correlation_id": "..."}` cancels every execution of that event.
+
+use tucana::{aquila::ActionEvent, shared::helper::value::to_json_value};
+
+use crate::telemetry::{errors, metrics};
+
+use super::{
+    ActionTransferContext,
+    connections::ActionConnection,
+    nats_bridge::{cancel_pending_reply, send_flow_execution_cancelled},
+};
+
+/// The event type an action cancels an execution with. Never published to NATS.
+pub(super) const CANCEL_EVENT_TYPE: &str = "aquila.execution.cancel";
+
+/// What a [`CANCEL_EVENT_TYPE`] event cancels.
+#[derive(Debug, PartialEq)]
+enum CancelTarget {
+    Execution(String),
+    /// Every execution of the event with this correlation id.
+    Event(String),
+}
+
+impl CancelTarget {
+    fn id(&self) -> &str {
+        match self {
+            CancelTarget::Execution(id) | CancelTarget::Event(id) => id,
+        }
+    }
+}
+
+fn cancel_target(event: ActionEvent) -> Option<CancelTarget> {
+    let payload = to_json_value(event.payload?);
+    let field = |name: &str| {
+        payload
+            .get(name)
+            .and_then(|value| value.as_str())
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+    };
+    field("execution_id")
+        .map(CancelTarget::Execution)
+        .or_else(|| field("correlation_id").map(CancelTarget::Event))
+}
+
+/// Applies a [`CANCEL_EVENT_TYPE`] event sent by `action_identifier`.
+pub(super) async fn handle_cancel(
+    action_identifier: &str,
+    event: ActionEvent,
+    context: &ActionTransferContext,
+    connection: &ActionConnection,
+) {
+    let Some(target) = cancel_target(event) else {
+        metrics::action_failure(action_identifier, "cancel_invalid");
+        errors::record_message(
+            "protocol",
+            "action.execution.cancel",
+            "Cancellation has no execution id or correlation id",
+            format!("action.identifier={action_identifier}"),
+        );
+        return;
+    };
+
+    let event_executions = context
+        .executions
+        .cancel(action_identifier, target.id())
+        .await;
+    if !event_executions.is_empty() {
+        for execution_id in &event_executions {
+            log::info!(
+                "Cancelling event execution action={} execution_id={}",
+                action_identifier,
+                execution_id
+            );
+            context.dispatcher.cancel(execution_id).await;
+            metrics::execution_cancelled("event");
+        }
+        return;
+    }
+
+    let CancelTarget::Execution(execution_id) = target else {
+        log::debug!(
+            "Ignored cancellation of an event with no execution in flight action={} correlation_id={}",
+            action_identifier,
+            target.id()
+        );
+        return;
+    };
+
+    if let Some(tx) = context
+        .flow_execution_registry
+        .take_for(&execution_id, action_identifier)
+        .await
+    {
+        log::info!(
+            "Cancelling action flow execution action={} execution_id={}",
+            action_identifier,
+            execution_id
+        );
+        context.dispatcher.cancel(&execution_id).await;
+        metrics::execution_cancelled("action_flow");
+        send_flow_execution_cancelled(&tx, execution_id).await;
+        return;
+    }
+
+    if cancel_pending_reply(
+        action_identifier,
+        &execution_id,
+        &context.client,
+        connection,
+    )
+    .await
+    {
+        log::info!(
+            "Action cancelled an execution it was asked to run action={} execution_id={}",
+            action_identifier,
+            execution_id
+        );
+        metrics::execution_cancelled("action_execution");
+        return;
+    }
+
+    // Most likely the execution finished while the cancellation was on its way.
+    log::debug!(
+        "Ignored cancellation of an execution that isn't pending action={} execution_id={}",
+        action_identifier,
+        execution_id
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tucana::shared::helper::value::from_json_value;
+
+    use super::*;
+
+    fn event(payload: serde_json::Value) -> ActionEvent {
+        ActionEvent {
+            event_type: CANCEL_EVENT_TYPE.to_string(),
+            project_id: 1,
+            payload: Some(from_json_value(payload)),
+        }
+    }
+
+    #[test]
+    fn cancellation_names_a_non_empty_execution_or_correlation_id() {
+        assert_eq!(
+            cancel_target(event(json!({ "execution_id": "abc" }))),
+            Some(CancelTarget::Execution("abc".to_string()))
+        );
+        assert_eq!(
+            cancel_target(event(json!({ "correlation_id": "order-42" }))),
+            Some(CancelTarget::Event("order-42".to_string()))
+        );
+        assert!(cancel_target(event(json!({ "execution_id": "" }))).is_none());
+        assert!(cancel_target(event(json!({ "execution_id": 1 }))).is_none());
+    }
+}
@@ -0,0 +1,350 @@
+//! Lets an action acknowledge the configuration it was sent, so a push it
+//! didn't apply is sent again instead of being lost.
+//!
+//! The protocol carries no version field, so a push's version is its
+//! position on the stream: the first `ModuleConfigurations` message an
+//! action receives is version 1, the next version 2, and so on. An action
+//! that opted in with [`ACKS_HEADER`] acknowledges version `n` - and with
+//! it every earlier one - by sending an [`ACK_EVENT_TYPE`] event whose
+//! payload is `{"version": n}`. Since every push replaces the action's
+//! configuration as a whole, only the latest unacknowledged push is retried.
+//!
+//! Stream positions restart at 1 on every reconnect, so metrics report the
+//! acknowledged push by its [`ConfigurationVersions`] number instead, which
+//! is the same for every stream of the action.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use prost::Message;
+use sha2::{Digest, Sha256};
+use tucana::{aquila::ActionEvent, shared::helper::value::to_json_value};
+
+use crate::{
+    configuration::config::ActionUpdates,
+    telemetry::{errors, metrics},
+};
+
+use super::connections::ActionConnection;
+
+/// Request metadata an action sets to `true` to acknowledge configuration pushes.
+pub(super) const ACKS_HEADER: &str = "aquila-configuration-acks";
+
+/// The event type an action acknowledges a configuration push with. Never
+/// published to NATS.
+pub(super) const ACK_EVENT_TYPE: &str = "aquila.configuration.ack";
+
+/// How long an action has to acknowledge a configuration push, and how
+/// often it's sent before Aquila gives up on it.
+#[derive(Clone, Copy, Debug)]
+pub struct ConfigurationRetry {
+    pub(super) ack_timeout: Duration,
+    pub(super) max_attempts: u32,
+}
+
+impl ConfigurationRetry {
+    pub fn from_config(config: &ActionUpdates) -> Self {
+        Self {
+            ack_timeout: Duration::from_secs(config.configuration_ack_timeout_secs.max(1)),
+            max_attempts: config.configuration_max_attempts.max(1),
+        }
+    }
+}
+
+/// How many pushes per stream, and distinct configurations per action, are
+/// remembered for numbering acknowledgements.
+const REMEMBERED_VERSIONS: usize = 32;
+
+/// Numbers the distinct configurations pushed to each action in the order
+/// they were first sent, so the same configuration has the same version on
+/// every stream of the action, across reconnects and replicas.
+#[derive(Clone, Default)]
+pub struct ConfigurationVersions {
+    inner: Arc<Mutex<HashMap<String, ActionVersions>>>,
+}
+
+#[derive(Default)]
+struct ActionVersions {
+    latest: u64,
+    /// Digests of the most recent configurations and their versions.
+    recent: VecDeque<([u8; 32], u64)>,
+}
+
+impl ConfigurationVersions {
+    /// The version of `cfgs` among the configurations pushed to
+    /// `action_identifier`, numbering it if it wasn't sent before.
+    pub(super) fn version_of(
+        &self,
+        action_identifier: &str,
+        cfgs: &tucana::shared::ModuleConfigurations,
+    ) -> u64 {
+        let digest: [u8; 32] = Sha256::digest(cfgs.encode_to_vec()).into();
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let versions = inner.entry(action_identifier.to_string()).or_default();
+        if let Some((_, version)) = versions.recent.iter().find(|(known, _)| *known == digest) {
+            return *version;
+        }
+
+        versions.latest += 1;
+        if versions.recent.len() == REMEMBERED_VERSIONS {
+            versions.recent.pop_front();
+        }
+        versions.recent.push_back((digest, versions.latest));
+        versions.latest
+    }
+}
+
+/// What became of an acknowledgement.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Acknowledgement {
+    /// Carries the [`ConfigurationVersions`] number of the acknowledged push,
+    /// if it's still remembered.
+    Accepted(Option<u64>),
+    /// A later version was already acknowledged.
+    Stale,
+    /// No push with that version was sent on this stream.
+    Unknown,
+}
+
+#[derive(Default)]
+struct DeliveryState {
+    acks: bool,
+    sent: u64,
+    acknowledged: u64,
+    /// The latest push, while it's unacknowledged and the action acknowledges pushes.
+    outstanding: Option<(u64, tucana::shared::ModuleConfigurations)>,
+    /// The [`ConfigurationVersions`] number of the latest unacknowledged
+    /// pushes, by stream position.
+    configuration_versions: VecDeque<(u64, u64)>,
+}
+
+/// The configuration versions sent to and acknowledged by one stream.
+/// Shared between the config forwarder and the stream task, which receives
+/// the acknowledgements.
+#[derive(Clone, Default)]
+pub(super) struct ConfigurationDelivery {
+    state: Arc<Mutex<DeliveryState>>,
+}
+
+impl ConfigurationDelivery {
+    /// Keeps pushes outstanding until acknowledged from now on.
+    pub(super) fn require_acks(&self) {
+        self.lock().acks = true;
+    }
+
+    pub(super) fn acks_required(&self) -> bool {
+        self.lock().acks
+    }
+
+    /// Records that `cfgs`, numbered `configuration_version` by
+    /// [`ConfigurationVersions`], was sent and returns its stream version.
+    pub(super) fn sent(
+        &self,
+        cfgs: &tucana::shared::ModuleConfigurations,
+        configuration_version: u64,
+    ) -> u64 {
+        let mut state = self.lock();
+        state.sent += 1;
+        let version = state.sent;
+        if state.acks {
+            state.outstanding = Some((version, cfgs.clone()));
+        }
+        if state.configuration_versions.len() == REMEMBERED_VERSIONS {
+            state.configuration_versions.pop_front();
+        }
+        state
+            .configuration_versions
+            .push_back((version, configuration_version));
+        version
+    }
+
+    pub(super) fn acknowledge(&self, version: u64) -> Acknowledgement {
+        let mut state = self.lock();
+        if version == 0 || version > state.sent {
+            return Acknowledgement::Unknown;
+        }
+        if version <= state.acknowledged {
+            return Acknowledgement::Stale;
+        }
+
+        state.acknowledged = version;
+        if matches!(state.outstanding, Some((outstanding, _)) if outstanding <= version) {
+            state.outstanding = None;
+        }
+        let configuration_version = state
+            .configuration_versions
+            .iter()
+            .find(|(sent, _)| *sent == version)
+            .map(|(_, configuration_version)| *configuration_version);
+        state
+            .configuration_versions
+            .retain(|(sent, _)| *sent > version);
+        Acknowledgement::Accepted(configuration_version)
+    }
+
+    /// The latest push if it still waits for an acknowledgement.
+    pub(super) fn unacknowledged(&self) -> Option<(u64, tucana::shared::ModuleConfigurations)> {
+        self.lock().outstanding.clone()
+    }
+
+    /// The latest version sent and the latest one acknowledged, `0` for none.
+    pub(super) fn versions(&self) -> (u64, u64) {
+        let state = self.lock();
+        (state.sent, state.acknowledged)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DeliveryState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Whether the action asked to acknowledge configuration pushes.
+pub(super) fn acks_requested(metadata: &tonic::metadata::MetadataMap) -> bool {
+    metadata
+        .get(ACKS_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+}
+
+/// The version an [`ACK_EVENT_TYPE`] event acknowledges.
+fn acknowledged_version(event: ActionEvent) -> Option<u64> {
+    let payload = to_json_value(event.payload?);
+    payload.get("version")?.as_u64()
+}
+
+/// Applies an [`ACK_EVENT_TYPE`] event sent by `action_identifier`.
+pub(super) fn handle_ack(
+    action_identifier: &str,
+    event: ActionEvent,
+    connection: &ActionConnection,
+) {
+    let Some(version) = acknowledged_version(event) else {
+        metrics::action_failure(action_identifier, "configuration_ack_invalid");
+        errors::record_message(
+            "protocol",
+            "action.configuration.ack",
+            "Configuration acknowledgement has no version",
+            format!(
+                "action.identifier={action_identifier} instance_id={}",
+                connection.instance_id
+            ),
+        );
+        return;
+    };
+
+    match connection.configuration.acknowledge(version) {
+        Acknowledgement::Accepted(configuration_version) => {
+            log::debug!(
+                "Action acknowledged configuration action={} instance_id={} version={} configuration_version={:?}",
+                action_identifier,
+                connection.instance_id,
+                version,
+                configuration_version
+            );
+            metrics::action_config_update(action_identifier, "acknowledged");
+            if let Some(configuration_version) = configuration_version {
+                metrics::action_configuration_version(action_identifier, configuration_version);
+            }
+        }
+        Acknowledgement::Stale => {
+            log::debug!(
+                "Ignored stale configuration acknowledgement action={} version={}",
+                action_identifier,
+                version
+            );
+        }
+        Acknowledgement::Unknown => {
+            metrics::action_failure(action_identifier, "configuration_ack_invalid");
+            errors::record_message(
+                "protocol",
+                "action.configuration.ack",
+                "Configuration acknowledgement for a version that was never sent",
+                format!(
+                    "action.identifier={action_identifier} instance_id={} version={version}",
+                    connection.instance_id
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configurations(identifier: &str) -> tucana::shared::ModuleConfigurations {
+        tucana::shared::ModuleConfigurations {
+            module_identifier: identifier.to_string(),
+            module_configurations: vec![],
+        }
+    }
+
+    #[test]
+    fn acknowledging_a_version_settles_it_and_earlier_pushes() {
+        let delivery = ConfigurationDelivery::default();
+        delivery.require_acks();
+
+        assert_eq!(delivery.sent(&configurations("send-email"), 4), 1);
+        assert_eq!(delivery.sent(&configurations("send-email"), 5), 2);
+        assert_eq!(
+            delivery.unacknowledged().map(|(version, _)| version),
+            Some(2)
+        );
+
+        assert_eq!(delivery.acknowledge(3), Acknowledgement::Unknown);
+        assert_eq!(delivery.acknowledge(1), Acknowledgement::Accepted(Some(4)));
+        assert_eq!(
+            delivery.unacknowledged().map(|(version, _)| version),
+            Some(2)
+        );
+
+        assert_eq!(delivery.acknowledge(2), Acknowledgement::Accepted(Some(5)));
+        assert!(delivery.unacknowledged().is_none());
+        assert_eq!(delivery.acknowledge(1), Acknowledgement::Stale);
+        assert_eq!(delivery.versions(), (2, 2));
+    }
+
+    #[test]
+    fn pushes_are_not_kept_without_acks() {
+        let delivery = ConfigurationDelivery::default();
+
+        assert_eq!(delivery.sent(&configurations("send-email"), 1), 1);
+        assert!(delivery.unacknowledged().is_none());
+    }
+
+    #[test]
+    fn configuration_versions_are_shared_by_every_stream_of_an_action() {
+        let versions = ConfigurationVersions::default();
+        let first = configurations("send-email");
+        let mut second = configurations("send-email");
+        second
+            .module_configurations
+            .push(tucana::shared::ModuleProjectConfigurations {
+                project_id: 1,
+                module_configurations: vec![],
+            });
+
+        assert_eq!(versions.version_of("send-email", &first), 1);
+        assert_eq!(versions.version_of("send-email", &second), 2);
+        // A reconnect is sent the latest configuration again as stream version 1.
+        assert_eq!(versions.version_of("send-email", &second), 2);
+        assert_eq!(versions.version_of("send-email", &first), 1);
+        assert_eq!(versions.version_of("send-sms", &second), 1);
+
+        let reconnected = ConfigurationDelivery::default();
+        let stream_version = reconnected.sent(&second, versions.version_of("send-email", &second));
+        assert_eq!(stream_version, 1);
+        assert_eq!(
+            reconnected.acknowledge(stream_version),
+            Acknowledgement::Accepted(Some(2))
+        );
+    }
+}
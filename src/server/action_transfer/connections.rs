@@ -0,0 +1,249 @@
+//! Keeps track of every action stream that has completed its logon, so the
+//! admin API can show which actions are connected, since when, and how much
+//! traffic each connection has carried.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Instant, SystemTime},
+};
+
+use serde::Serialize;
+use tokio::sync::{Mutex, watch};
+
+use super::{configuration_acks::ConfigurationDelivery, pending_replies::PendingReplyStore};
+use crate::flow::executions::ExecutionRegistry;
+
+/// Per-stream message counters. Shared between the stream task and the NATS
+/// forwarder it spawns, hence the atomics.
+#[derive(Default)]
+pub(super) struct ConnectionCounters {
+    events: AtomicU64,
+    executions: AtomicU64,
+    results: AtomicU64,
+    flow_executions: AtomicU64,
+    sub_flow_executions: AtomicU64,
+}
+
+impl ConnectionCounters {
+    pub(super) fn event(&self) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn execution(&self) {
+        self.executions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn result(&self) {
+        self.results.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn flow_execution(&self) {
+        self.flow_executions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn sub_flow_execution(&self) {
+        self.sub_flow_executions.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Why an action's stream ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum CloseReason {
+    /// The action closed the stream, or Aquila ended it.
+    Ended,
+    /// The stream failed underneath the action, e.g. because the connection
+    /// stopped answering keepalive pings.
+    Lost,
+    /// A reconnect of the same action resumed this stream's session.
+    Replaced,
+}
+
+/// The per-stream state an action's connection carries from logon until the
+/// stream closes.
+#[derive(Clone)]
+pub(super) struct ActionConnection {
+    /// Tells replicas of the same action apart in logs, metrics and the admin API.
+    pub(super) instance_id: String,
+    pub(super) pending_replies: PendingReplyStore,
+    pub(super) counters: Arc<ConnectionCounters>,
+    pub(super) configuration: ConfigurationDelivery,
+    closed: Arc<watch::Sender<Option<CloseReason>>>,
+}
+
+impl ActionConnection {
+    pub(super) fn new(executions: ExecutionRegistry) -> Self {
+        Self {
+            instance_id: uuid::Uuid::new_v4().to_string(),
+            pending_replies: PendingReplyStore::new(executions),
+            counters: Arc::new(ConnectionCounters::default()),
+            configuration: ConfigurationDelivery::default(),
+            closed: Arc::new(watch::channel(None).0),
+        }
+    }
+
+    /// Marks the stream as gone, so the NATS forwarder stops taking work
+    /// for it. Only the first reason sticks.
+    pub(super) fn close(&self, reason: CloseReason) {
+        self.closed.send_if_modified(|closed| {
+            if closed.is_some() {
+                return false;
+            }
+            *closed = Some(reason);
+            true
+        });
+    }
+
+    /// Resolves once [`Self::close`] has been called.
+    pub(super) async fn closed(&self) {
+        let mut closed = self.closed.subscribe();
+        let _ = closed.wait_for(Option::is_some).await;
+    }
+
+    /// Why the stream ended, once it has.
+    pub(super) fn close_reason(&self) -> Option<CloseReason> {
+        *self.closed.borrow()
+    }
+}
+
+struct RegisteredConnection {
+    identifier: String,
+    connected_at: SystemTime,
+    connected_since: Instant,
+    connection: ActionConnection,
+}
+
+/// A connected action as reported by the admin API.
+#[derive(Debug, Serialize)]
+pub struct ActionConnectionSnapshot {
+    pub connection_id: u64,
+    pub identifier: String,
+    pub instance_id: String,
+    pub connected_at_unix_ms: u128,
+    pub connected_for_secs: u64,
+    pub events: u64,
+    pub executions: u64,
+    pub results: u64,
+    pub flow_executions: u64,
+    pub sub_flow_executions: u64,
+    pub pending_replies: usize,
+    /// The latest configuration version sent, `0` before the first push.
+    pub configuration_version: u64,
+    /// The latest configuration version the action acknowledged, if it
+    /// acknowledges pushes at all.
+    pub acknowledged_configuration_version: Option<u64>,
+}
+
+/// Shared registry of every action stream that is past its logon.
+#[derive(Clone, Default)]
+pub struct ActionConnectionRegistry {
+    next_id: Arc<AtomicU64>,
+    inner: Arc<Mutex<HashMap<u64, RegisteredConnection>>>,
+}
+
+impl ActionConnectionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an accepted connection and returns the id to unregister it with.
+    pub(super) async fn register(&self, identifier: String, connection: ActionConnection) -> u64 {
+        let connection_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.inner.lock().await.insert(
+            connection_id,
+            RegisteredConnection {
+                identifier,
+                connected_at: SystemTime::now(),
+                connected_since: Instant::now(),
+                connection,
+            },
+        );
+        connection_id
+    }
+
+    pub(super) async fn unregister(&self, connection_id: u64) {
+        self.inner.lock().await.remove(&connection_id);
+    }
+
+    pub async fn snapshot(&self) -> Vec<ActionConnectionSnapshot> {
+        let connections = self.inner.lock().await;
+        let mut snapshots = Vec::with_capacity(connections.len());
+
+        for (connection_id, registered) in connections.iter() {
+            let counters = &registered.connection.counters;
+            let configuration = &registered.connection.configuration;
+            let (sent, acknowledged) = configuration.versions();
+            snapshots.push(ActionConnectionSnapshot {
+                connection_id: *connection_id,
+                identifier: registered.identifier.clone(),
+                instance_id: registered.connection.instance_id.clone(),
+                connected_at_unix_ms: registered
+                    .connected_at
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|since_epoch| since_epoch.as_millis())
+                    .unwrap_or_default(),
+                connected_for_secs: registered.connected_since.elapsed().as_secs(),
+                events: counters.events.load(Ordering::Relaxed),
+                executions: counters.executions.load(Ordering::Relaxed),
+                results: counters.results.load(Ordering::Relaxed),
+                flow_executions: counters.flow_executions.load(Ordering::Relaxed),
+                sub_flow_executions: counters.sub_flow_executions.load(Ordering::Relaxed),
+                pending_replies: registered.connection.pending_replies.len().await,
+                configuration_version: sent,
+                acknowledged_configuration_version: configuration
+                    .acks_required()
+                    .then_some(acknowledged),
+            });
+        }
+
+        snapshots.sort_by_key(|snapshot| snapshot.connection_id);
+        snapshots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_reports_counters_until_unregistered() {
+        futures::executor::block_on(async {
+            let registry = ActionConnectionRegistry::new();
+            let connection = ActionConnection::new(ExecutionRegistry::new());
+            connection.counters.event();
+            connection.counters.event();
+            connection.counters.result();
+
+            let connection_id = registry
+                .register("send-email".to_string(), connection)
+                .await;
+
+            let snapshot = registry.snapshot().await;
+            assert_eq!(snapshot.len(), 1);
+            assert_eq!(snapshot[0].identifier, "send-email");
+            assert_eq!(snapshot[0].events, 2);
+            assert_eq!(snapshot[0].results, 1);
+            assert_eq!(snapshot[0].executions, 0);
+
+            registry.unregister(connection_id).await;
+            assert!(registry.snapshot().await.is_empty());
+        });
+    }
+
+    #[test]
+    fn first_close_reason_sticks() {
+        futures::executor::block_on(async {
+            let connection = ActionConnection::new(ExecutionRegistry::new());
+            assert_eq!(connection.close_reason(), None);
+
+            connection.close(CloseReason::Lost);
+            connection.close(CloseReason::Ended);
+            connection.closed().await;
+
+            assert_eq!(connection.close_reason(), Some(CloseReason::Lost));
+        });
+    }
+}
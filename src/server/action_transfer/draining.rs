@@ -0,0 +1,116 @@
+//! What a connected action is told while Aquila drains for shutdown. The
+//! protocol has no message for it, so Aquila sends an
+//! `ActionFlowExecutionResponse` under the reserved execution identifier
+//! [`DRAIN_NOTICE_IDENTIFIER`] as soon as draining starts, whose value is
+//! `{"draining": true, "timeout_secs": ...}`. Events the action sends after
+//! that are turned down with an `A-FLOW-EXECUTION-000002` (`Unavailable`)
+//! failure, under the event's correlation id if it has one and under
+//! [`DRAIN_NOTICE_IDENTIFIER`] otherwise.
+
+use std::time::Duration;
+
+use serde_json::json;
+use tucana::{
+    aquila::{
+        ActionFlowExecutionResponse, ActionTransferResponse, action_flow_execution_response,
+        action_transfer_response,
+    },
+    shared::{Error, helper::value::from_json_value},
+};
+
+use crate::validation;
+
+/// The execution identifier of every response about draining.
+pub(super) const DRAIN_NOTICE_IDENTIFIER: &str = "aquila.server.draining";
+
+/// Tells an action Aquila stopped taking new work and closes the stream
+/// once its in-flight executions settled, at the latest after `timeout`.
+pub(super) fn drain_notice(timeout: Duration) -> ActionTransferResponse {
+    response(
+        DRAIN_NOTICE_IDENTIFIER.to_string(),
+        action_flow_execution_response::Result::Success(from_json_value(json!({
+            "draining": true,
+            "timeout_secs": timeout.as_secs(),
+        }))),
+    )
+}
+
+/// Turns down an event received while draining. Its flows weren't triggered.
+pub(super) fn rejected_event(
+    event_type: &str,
+    correlation_id: Option<&str>,
+) -> ActionTransferResponse {
+    response(
+        correlation_id
+            .unwrap_or(DRAIN_NOTICE_IDENTIFIER)
+            .to_string(),
+        action_flow_execution_response::Result::Failure(Error {
+            code: "A-FLOW-EXECUTION-000002".to_string(),
+            category: "Unavailable".to_string(),
+            message: format!("Aquila is shutting down, event {event_type} was not handled"),
+            timestamp: validation::epoch_millis_now(),
+            version: crate::version::runtime_version().to_string(),
+            ..Default::default()
+        }),
+    )
+}
+
+fn response(
+    execution_identifier: String,
+    result: action_flow_execution_response::Result,
+) -> ActionTransferResponse {
+    ActionTransferResponse {
+        data: Some(action_transfer_response::Data::FlowExecutionResponse(
+            ActionFlowExecutionResponse {
+                execution_identifier,
+                result: Some(result),
+            },
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tucana::shared::helper::value::to_json_value;
+
+    use super::*;
+
+    fn flow_execution_response(response: ActionTransferResponse) -> ActionFlowExecutionResponse {
+        match response.data {
+            Some(action_transfer_response::Data::FlowExecutionResponse(response)) => response,
+            other => panic!("expected flow execution response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn drain_notice_carries_the_timeout() {
+        let notice = flow_execution_response(drain_notice(Duration::from_secs(30)));
+
+        assert_eq!(notice.execution_identifier, DRAIN_NOTICE_IDENTIFIER);
+        match notice.result {
+            Some(action_flow_execution_response::Result::Success(value)) => {
+                assert_eq!(
+                    to_json_value(value),
+                    json!({ "draining": true, "timeout_secs": 30 })
+                );
+            }
+            other => panic!("expected success, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejected_event_answers_its_correlation_id() {
+        let correlated = flow_execution_response(rejected_event("order.created", Some("c-1")));
+        let uncorrelated = flow_execution_response(rejected_event("order.created", None));
+
+        assert_eq!(correlated.execution_identifier, "c-1");
+        assert_eq!(uncorrelated.execution_identifier, DRAIN_NOTICE_IDENTIFIER);
+        match correlated.result {
+            Some(action_flow_execution_response::Result::Failure(error)) => {
+                assert_eq!(error.category, "Unavailable");
+                assert!(error.message.contains("order.created"));
+            }
+            other => panic!("expected failure, got {:?}", other),
+        }
+    }
+}
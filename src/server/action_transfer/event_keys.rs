@@ -0,0 +1,101 @@
+//! Drops action events that repeat an idempotency key seen recently, so an
+//! action retrying an event after a network failure doesn't trigger its
+//! flows twice. Keys are recorded in their own KV bucket
+//! (`nats.event_key_bucket`), whose entries expire after the dedupe window,
+//! and are scoped to the action and project the event came from. A key is
+//! recorded when its event arrives, so a retry sent while the first attempt
+//! is still handled counts as a duplicate too, and forgotten again if the
+//! event's flows couldn't be looked up or requested, so a retry is handled.
+
+use async_nats::jetstream::kv::CreateErrorKind;
+use sha2::{Digest, Sha256};
+
+use crate::telemetry::errors;
+
+/// Idempotency keys seen within the dedupe window. Without a bucket, no
+/// event counts as a duplicate.
+#[derive(Clone)]
+pub struct EventKeys {
+    kv: Option<async_nats::jetstream::kv::Store>,
+}
+
+impl EventKeys {
+    pub fn new(kv: Option<async_nats::jetstream::kv::Store>) -> Self {
+        Self { kv }
+    }
+
+    /// Records `idempotency_key` for the action and project, and returns
+    /// whether it was already recorded. A KV failure lets the event through.
+    pub(super) async fn is_duplicate(
+        &self,
+        action_identifier: &str,
+        project_id: i64,
+        idempotency_key: &str,
+    ) -> bool {
+        let Some(kv) = &self.kv else {
+            return false;
+        };
+
+        let key = record_key(action_identifier, project_id, idempotency_key);
+        match kv.create(key, Default::default()).await {
+            Ok(_) => false,
+            Err(err) if err.kind() == CreateErrorKind::AlreadyExists => true,
+            Err(err) => {
+                errors::record(
+                    "flow_storage",
+                    "action.event.idempotency_key",
+                    &err,
+                    format!("action.identifier={action_identifier} project_id={project_id}"),
+                );
+                false
+            }
+        }
+    }
+
+    /// Forgets `idempotency_key` for the action and project, so a retry of
+    /// an event that couldn't be dispatched isn't dropped as a duplicate.
+    pub(super) async fn forget(
+        &self,
+        action_identifier: &str,
+        project_id: i64,
+        idempotency_key: &str,
+    ) {
+        let Some(kv) = &self.kv else {
+            return;
+        };
+
+        let key = record_key(action_identifier, project_id, idempotency_key);
+        if let Err(err) = kv.purge(key).await {
+            errors::record(
+                "flow_storage",
+                "action.event.idempotency_key.forget",
+                &err,
+                format!("action.identifier={action_identifier} project_id={project_id}"),
+            );
+        }
+    }
+}
+
+/// Idempotency keys are chosen by the action and may contain anything, so
+/// the record is keyed by a hash rather than the key itself.
+fn record_key(action_identifier: &str, project_id: i64, idempotency_key: &str) -> String {
+    let digest = Sha256::digest(format!(
+        "{action_identifier}\n{project_id}\n{idempotency_key}"
+    ));
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_key_is_scoped_to_action_and_project() {
+        let key = record_key("webhook", 1, "order-42");
+
+        assert_eq!(key, record_key("webhook", 1, "order-42"));
+        assert_ne!(key, record_key("webhook", 2, "order-42"));
+        assert_ne!(key, record_key("mail", 1, "order-42"));
+        assert!(key.chars().all(|character| character.is_ascii_hexdigit()));
+    }
+}
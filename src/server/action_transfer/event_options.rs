@@ -0,0 +1,195 @@
+//! Options an action event carries for Aquila rather than for the flows it
+//! triggers, in an [`OPTIONS_FIELD`] object in its payload:
+//!
+//! - `match` narrows the triggered flows to those whose settings have the
+//!   given values, e.g. `{"match": {"httpURL": "/hooks/orders"}}`.
+//! - `correlation_id` (and optionally `timeout_ms`) asks for the flows'
+//!   results, see [`super::event_results`].
+//! - `idempotency_key` marks retries of the same event, see [`super::event_keys`].
+//!
+//! The field is stripped before the payload reaches any flow.
+
+use std::time::Duration;
+
+use serde_json::{Map, Value as JsonValue};
+use tucana::{
+    aquila::ActionEvent,
+    shared::{ValidationFlow, helper::value::to_json_value, value::Kind},
+};
+
+/// The payload field an event carries its options in.
+pub(super) const OPTIONS_FIELD: &str = "_aquila";
+
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct EventOptions {
+    pub(super) criteria: MatchCriteria,
+    pub(super) results: Option<ResultRequest>,
+    pub(super) idempotency_key: Option<String>,
+}
+
+/// An event's request to have its flows' results collected.
+#[derive(Debug, PartialEq)]
+pub(super) struct ResultRequest {
+    pub(super) correlation_id: String,
+    pub(super) timeout: Duration,
+}
+
+/// Flow setting values a flow must have for the event to trigger it. No
+/// criteria match every flow.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct MatchCriteria(Map<String, JsonValue>);
+
+impl MatchCriteria {
+    /// Whether every criterion names one of `flow`'s settings and equals its value.
+    pub(super) fn matches(&self, flow: &ValidationFlow) -> bool {
+        self.0.iter().all(|(setting_id, expected)| {
+            flow.settings.iter().any(|setting| {
+                setting.flow_setting_id == *setting_id
+                    && setting
+                        .value
+                        .clone()
+                        .is_some_and(|value| to_json_value(value) == *expected)
+            })
+        })
+    }
+}
+
+/// Removes [`OPTIONS_FIELD`] from the event's payload and returns the
+/// options it holds. A requested result timeout is capped at
+/// `max_result_timeout`, which is also the default.
+pub(super) fn take_event_options(
+    event: &mut ActionEvent,
+    max_result_timeout: Duration,
+) -> EventOptions {
+    let Some(Kind::StructValue(payload)) = event
+        .payload
+        .as_mut()
+        .and_then(|payload| payload.kind.as_mut())
+    else {
+        return EventOptions::default();
+    };
+    let Some(options) = payload.fields.remove(OPTIONS_FIELD).map(to_json_value) else {
+        return EventOptions::default();
+    };
+
+    let criteria = match options.get("match") {
+        Some(JsonValue::Object(criteria)) => MatchCriteria(criteria.clone()),
+        _ => MatchCriteria::default(),
+    };
+    let results = options
+        .get("correlation_id")
+        .and_then(JsonValue::as_str)
+        .filter(|correlation_id| !correlation_id.is_empty())
+        .map(|correlation_id| ResultRequest {
+            correlation_id: correlation_id.to_string(),
+            timeout: options
+                .get("timeout_ms")
+                .and_then(JsonValue::as_u64)
+                .map(Duration::from_millis)
+                .map_or(max_result_timeout, |timeout| {
+                    timeout.min(max_result_timeout)
+                }),
+        });
+
+    let idempotency_key = options
+        .get("idempotency_key")
+        .and_then(JsonValue::as_str)
+        .filter(|idempotency_key| !idempotency_key.is_empty())
+        .map(str::to_string);
+
+    EventOptions {
+        criteria,
+        results,
+        idempotency_key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tucana::shared::{FlowSetting, helper::value::from_json_value};
+
+    use super::*;
+
+    fn event(payload: JsonValue) -> ActionEvent {
+        ActionEvent {
+            event_type: "webhook".to_string(),
+            project_id: 1,
+            payload: Some(from_json_value(payload)),
+        }
+    }
+
+    fn flow(settings: JsonValue) -> ValidationFlow {
+        let JsonValue::Object(settings) = settings else {
+            panic!("settings must be an object");
+        };
+        ValidationFlow {
+            settings: settings
+                .into_iter()
+                .map(|(flow_setting_id, value)| FlowSetting {
+                    flow_setting_id,
+                    value: Some(from_json_value(value)),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn options_are_taken_out_of_the_payload() {
+        let max_timeout = Duration::from_secs(30);
+        let mut requested = event(json!({
+            "body": "hello",
+            "_aquila": {
+                "correlation_id": "abc",
+                "timeout_ms": 60_000,
+                "match": { "httpURL": "/hooks/orders" },
+                "idempotency_key": "order-42",
+            },
+        }));
+
+        let options = take_event_options(&mut requested, max_timeout);
+        assert_eq!(
+            options.results,
+            Some(ResultRequest {
+                correlation_id: "abc".to_string(),
+                timeout: max_timeout,
+            })
+        );
+        assert_eq!(
+            options.criteria,
+            MatchCriteria(Map::from_iter([(
+                "httpURL".to_string(),
+                json!("/hooks/orders")
+            )]))
+        );
+        assert_eq!(options.idempotency_key.as_deref(), Some("order-42"));
+        assert_eq!(
+            to_json_value(requested.payload.expect("payload")),
+            json!({ "body": "hello" })
+        );
+
+        let mut plain = event(json!({ "body": "hello" }));
+        assert_eq!(
+            take_event_options(&mut plain, max_timeout),
+            EventOptions::default()
+        );
+    }
+
+    #[test]
+    fn criteria_match_flows_with_equal_settings() {
+        let orders = flow(json!({ "httpURL": "/hooks/orders", "httpMethod": "POST" }));
+        let invoices = flow(json!({ "httpURL": "/hooks/invoices", "httpMethod": "POST" }));
+
+        let mut requested = event(json!({
+            "_aquila": { "match": { "httpURL": "/hooks/orders" } },
+        }));
+        let criteria = take_event_options(&mut requested, Duration::from_secs(30)).criteria;
+        assert!(criteria.matches(&orders));
+        assert!(!criteria.matches(&invoices));
+        assert!(!criteria.matches(&flow(json!({}))));
+
+        assert!(MatchCriteria::default().matches(&invoices));
+    }
+}
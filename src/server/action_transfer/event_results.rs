@@ -0,0 +1,292 @@
+//! Collection of what the flows an action event triggered produced, for an
+//! event that asked for it (see [`super::event_options`]). Once every
+//! triggered flow answered, or the timeout passed, the action receives a
+//! single `ActionFlowExecutionResponse` whose execution identifier is the
+//! event's correlation id and whose value lists one entry per flow. An
+//! event without a correlation id is answered under
+//! [`UNCORRELATED_EVENT_IDENTIFIER`] when Aquila has to tell the action
+//! something about it.
+
+use serde_json::{Value as JsonValue, json};
+use tucana::{
+    aquila::{
+        ActionFlowExecutionResponse, ActionTransferResponse, action_flow_execution_response,
+        action_transfer_response,
+    },
+    shared::{
+        Error, execution_result,
+        helper::value::{from_json_value, to_json_value},
+        value::Kind,
+    },
+};
+
+use crate::validation::{self, ValidatedResult};
+
+/// The execution identifier responses about an event without a correlation
+/// id are sent under.
+pub(super) const UNCORRELATED_EVENT_IDENTIFIER: &str = "aquila.event";
+
+/// How one triggered flow's execution ended, as far as the event is concerned.
+pub(super) enum FlowOutcome {
+    /// The flow answered; a result delivered despite not matching the flow's
+    /// output schema reports why as `output_mismatch`.
+    Finished(Box<ValidatedResult>),
+    /// The flow didn't answer before the event's timeout.
+    TimedOut,
+    /// The action cancelled the execution before it answered.
+    Cancelled,
+    /// The execution couldn't be requested or its result couldn't be read.
+    Failed(String),
+}
+
+/// The single response carrying every flow's outcome for `correlation_id`.
+pub(super) fn aggregated_response(
+    correlation_id: &str,
+    outcomes: Vec<(i64, String, FlowOutcome)>,
+) -> ActionTransferResponse {
+    let results: Vec<JsonValue> = outcomes
+        .into_iter()
+        .map(|(flow_id, execution_id, outcome)| {
+            let mut entry = match outcome {
+                FlowOutcome::Finished(validated) => {
+                    let mut entry = match validated.result.result {
+                        Some(execution_result::Result::Success(value)) => {
+                            json!({ "status": "success", "value": to_json_value(value) })
+                        }
+                        Some(execution_result::Result::Error(error)) => json!({
+                            "status": "failure",
+                            "error": {
+                                "code": error.code,
+                                "category": error.category,
+                                "message": error.message,
+                            },
+                        }),
+                        None => json!({ "status": "success", "value": null }),
+                    };
+                    if let Some(mismatch) = validated.output_mismatch {
+                        entry["output_mismatch"] = json!(mismatch);
+                    }
+                    entry
+                }
+                FlowOutcome::TimedOut => json!({ "status": "timeout" }),
+                FlowOutcome::Cancelled => json!({ "status": "cancelled" }),
+                FlowOutcome::Failed(message) => json!({ "status": "failed", "message": message }),
+            };
+            entry["flow_id"] = json!(flow_id);
+            entry["execution_id"] = json!(execution_id);
+            entry
+        })
+        .collect();
+
+    response(
+        correlation_id,
+        json!({
+            "correlation_id": correlation_id,
+            "results": results,
+        }),
+    )
+}
+
+/// The response to an event dropped as a duplicate: its flows weren't
+/// triggered again, so there are no results. An event without a correlation
+/// id is recognised by its `idempotency_key`.
+pub(super) fn duplicate_response(
+    correlation_id: Option<&str>,
+    idempotency_key: &str,
+) -> ActionTransferResponse {
+    response(
+        correlation_id.unwrap_or(UNCORRELATED_EVENT_IDENTIFIER),
+        json!({
+            "correlation_id": correlation_id,
+            "idempotency_key": idempotency_key,
+            "duplicate": true,
+            "results": [],
+        }),
+    )
+}
+
+/// The response to an event turned down because its stream already handles
+/// `action_events.max_in_flight` events and has `action_events.max_queued`
+/// more waiting. Its flows weren't triggered; the action may send it again.
+/// The error's details name the event type and idempotency key, so an event
+/// without a correlation id can still be told apart.
+pub(super) fn busy_response(
+    event_type: &str,
+    correlation_id: Option<&str>,
+    idempotency_key: Option<&str>,
+) -> ActionTransferResponse {
+    let details = match from_json_value(json!({
+        "event_type": event_type,
+        "idempotency_key": idempotency_key,
+    }))
+    .kind
+    {
+        Some(Kind::StructValue(details)) => Some(details),
+        _ => None,
+    };
+    ActionTransferResponse {
+        data: Some(action_transfer_response::Data::FlowExecutionResponse(
+            ActionFlowExecutionResponse {
+                execution_identifier: correlation_id
+                    .unwrap_or(UNCORRELATED_EVENT_IDENTIFIER)
+                    .to_string(),
+                result: Some(action_flow_execution_response::Result::Failure(Error {
+                    code: "A-FLOW-EXECUTION-000002".to_string(),
+                    category: "Unavailable".to_string(),
+                    message: format!(
+                        "Too many events in flight, event {event_type} was not handled"
+                    ),
+                    timestamp: validation::epoch_millis_now(),
+                    version: crate::version::runtime_version().to_string(),
+                    details,
+                    ..Default::default()
+                })),
+            },
+        )),
+    }
+}
+
+fn response(correlation_id: &str, value: JsonValue) -> ActionTransferResponse {
+    ActionTransferResponse {
+        data: Some(action_transfer_response::Data::FlowExecutionResponse(
+            ActionFlowExecutionResponse {
+                execution_identifier: correlation_id.to_string(),
+                result: Some(action_flow_execution_response::Result::Success(
+                    from_json_value(value),
+                )),
+            },
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tucana::shared::ExecutionResult;
+
+    use super::*;
+
+    #[test]
+    fn aggregated_response_lists_every_flow() {
+        let response = aggregated_response(
+            "abc",
+            vec![
+                (1, "one".to_string(), FlowOutcome::TimedOut),
+                (
+                    2,
+                    "two".to_string(),
+                    FlowOutcome::Failed("no runtime".to_string()),
+                ),
+            ],
+        );
+
+        let Some(action_transfer_response::Data::FlowExecutionResponse(response)) = response.data
+        else {
+            panic!("expected a flow execution response");
+        };
+        assert_eq!(response.execution_identifier, "abc");
+        let Some(action_flow_execution_response::Result::Success(value)) = response.result else {
+            panic!("expected a success value");
+        };
+        assert_eq!(
+            to_json_value(value),
+            json!({
+                "correlation_id": "abc",
+                "results": [
+                    { "flow_id": 1, "execution_id": "one", "status": "timeout" },
+                    {
+                        "flow_id": 2,
+                        "execution_id": "two",
+                        "status": "failed",
+                        "message": "no runtime",
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn aggregated_response_reports_output_mismatches() {
+        let result = ExecutionResult {
+            execution_identifier: "one".to_string(),
+            flow_id: 1,
+            result: Some(execution_result::Result::Success(from_json_value(
+                json!({ "name": 1 }),
+            ))),
+            ..Default::default()
+        };
+        let response = aggregated_response(
+            "abc",
+            vec![(
+                1,
+                "one".to_string(),
+                FlowOutcome::Finished(Box::new(ValidatedResult {
+                    result,
+                    output_mismatch: Some("name is not a string".to_string()),
+                })),
+            )],
+        );
+
+        let Some(action_transfer_response::Data::FlowExecutionResponse(response)) = response.data
+        else {
+            panic!("expected a flow execution response");
+        };
+        let Some(action_flow_execution_response::Result::Success(value)) = response.result else {
+            panic!("expected a success value");
+        };
+        assert_eq!(
+            to_json_value(value)["results"][0],
+            json!({
+                "flow_id": 1,
+                "execution_id": "one",
+                "status": "success",
+                "value": { "name": 1 },
+                "output_mismatch": "name is not a string",
+            })
+        );
+    }
+
+    #[test]
+    fn busy_response_names_the_event_it_turned_down() {
+        let response = busy_response("order.created", None, Some("order-42"));
+
+        let Some(action_transfer_response::Data::FlowExecutionResponse(response)) = response.data
+        else {
+            panic!("expected a flow execution response");
+        };
+        assert_eq!(response.execution_identifier, UNCORRELATED_EVENT_IDENTIFIER);
+        let Some(action_flow_execution_response::Result::Failure(error)) = response.result else {
+            panic!("expected a failure");
+        };
+        assert_eq!(error.code, "A-FLOW-EXECUTION-000002");
+        let details = error.details.expect("the rejection should carry details");
+        assert_eq!(
+            to_json_value(tucana::shared::Value {
+                kind: Some(Kind::StructValue(details)),
+            }),
+            json!({ "event_type": "order.created", "idempotency_key": "order-42" })
+        );
+    }
+
+    #[test]
+    fn duplicate_response_answers_uncorrelated_events_by_their_key() {
+        let response = duplicate_response(None, "order-42");
+
+        let Some(action_transfer_response::Data::FlowExecutionResponse(response)) = response.data
+        else {
+            panic!("expected a flow execution response");
+        };
+        assert_eq!(response.execution_identifier, UNCORRELATED_EVENT_IDENTIFIER);
+        let Some(action_flow_execution_response::Result::Success(value)) = response.result else {
+            panic!("expected a success value");
+        };
+        assert_eq!(
+            to_json_value(value),
+            json!({
+                "correlation_id": null,
+                "idempotency_key": "order-42",
+                "duplicate": true,
+                "results": [],
+            })
+        );
+    }
+}
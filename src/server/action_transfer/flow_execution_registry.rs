@@ -5,59 +5,302 @@
 //! result back onto a NATS reply subject), a flow execution an action itself
 //! asked Aquila to run needs its result delivered back over the *same*
 //! `ActionTransferResponse` stream the action is already connected on - so
-//! this registry stores the stream's sender directly instead.
+//! this registry stores the stream's sender directly instead. The sender
+//! lives in the execution's entry in the shared [`ExecutionRegistry`]; this
+//! is only a view onto the `action_flow` entries there.
+//!
+//! An execution whose result never arrives is expired after the registry's
+//! timeout, and those of a stream are dropped once the stream closes, so a
+//! runtime that never reports back can't keep an action's sender alive.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use std::{collections::HashMap, sync::Arc};
+use tokio::sync::{Notify, futures::Notified};
 
-use tokio::sync::Mutex;
-use tucana::aquila::ActionTransferResponse;
+use crate::flow::executions::{
+    ExecutionOrigin, ExecutionRegistry, ExecutionRoute, ExecutionState, FinishedExecution,
+    ResponseSender, TrackedExecution,
+};
 
-type ResponseSender = tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>;
+const ORIGIN: ExecutionOrigin = ExecutionOrigin::ActionFlow;
 
-/// Shared, lock-protected registry mapping execution identifiers to the
-/// action stream a result must eventually be delivered to.
+/// Shared registry mapping execution identifiers to the action stream a
+/// result must eventually be delivered to.
 #[derive(Clone, Default)]
 pub struct ActionFlowExecutionRegistry {
-    inner: Arc<Mutex<HashMap<String, ResponseSender>>>,
+    /// Woken whenever an execution leaves the registry, for [`Self::released`].
+    released: Arc<Notify>,
+    executions: ExecutionRegistry,
+    timeout: Duration,
 }
 
 impl ActionFlowExecutionRegistry {
-    pub fn new() -> Self {
-        Self::default()
+    /// A registry whose executions expire once `timeout` has passed.
+    pub fn new(executions: ExecutionRegistry, timeout: Duration) -> Self {
+        Self {
+            released: Arc::default(),
+            executions,
+            timeout,
+        }
     }
 
-    pub async fn insert(&self, execution_id: String, sender: ResponseSender) {
-        self.inner.lock().await.insert(execution_id, sender);
+    /// How long a registered execution may wait for its result.
+    pub(super) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Registers an execution of `flow_id` that `action_identifier` asked
+    /// for and is about to be dispatched.
+    pub async fn insert(
+        &self,
+        execution_id: String,
+        action_identifier: String,
+        flow_id: i64,
+        project_id: i64,
+        sender: ResponseSender,
+    ) {
+        self.executions
+            .track_routed(
+                &execution_id,
+                Vec::new(),
+                TrackedExecution {
+                    origin: ORIGIN,
+                    flow_id: Some(flow_id),
+                    project_id: Some(project_id),
+                    action_identifier: Some(action_identifier),
+                },
+                ExecutionState::Dispatching,
+                ExecutionRoute::ActionStream {
+                    sender,
+                    deadline: Instant::now() + self.timeout,
+                },
+            )
+            .await;
+    }
+
+    /// Marks a registered execution as handed to a runtime.
+    pub(super) async fn dispatched(&self, execution_id: &str) {
+        self.executions
+            .set_state(ORIGIN, execution_id, ExecutionState::Running)
+            .await;
     }
 
     /// Removes and returns the sender registered under `execution_id`, if any.
     pub async fn take(&self, execution_id: &str) -> Option<ResponseSender> {
-        self.inner.lock().await.remove(execution_id)
+        self.take_if(execution_id, |_, _| true).await
+    }
+
+    /// Like [`Self::take`], but only if `action_identifier` asked for the
+    /// execution, so an action can't cancel another one's.
+    pub(super) async fn take_for(
+        &self,
+        execution_id: &str,
+        action_identifier: &str,
+    ) -> Option<ResponseSender> {
+        self.take_if(execution_id, |execution, _| {
+            execution.action_identifier.as_deref() == Some(action_identifier)
+        })
+        .await
+    }
+
+    /// Removes and returns the sender registered under `execution_id` only
+    /// if its deadline has passed by `now`. An execution id that was answered
+    /// and then reused for a newer request is left alone.
+    pub(super) async fn expire(&self, execution_id: &str, now: Instant) -> Option<ResponseSender> {
+        self.take_if(execution_id, |_, route| {
+            matches!(route, ExecutionRoute::ActionStream { deadline, .. } if *deadline <= now)
+        })
+        .await
+    }
+
+    async fn take_if(
+        &self,
+        execution_id: &str,
+        take: impl FnOnce(&TrackedExecution, &ExecutionRoute) -> bool,
+    ) -> Option<ResponseSender> {
+        let finished = self.executions.take_if(ORIGIN, execution_id, take).await?;
+        self.released.notify_waiters();
+        sender(finished)
+    }
+
+    /// Drops every execution waiting to deliver its result to the stream
+    /// behind `sender`, once that stream has closed. Returns how many there were.
+    pub(super) async fn remove_for(&self, sender: &ResponseSender) -> usize {
+        let removed = self
+            .executions
+            .take_all(ORIGIN, |route| delivers_to(route, sender))
+            .await;
+        self.released.notify_waiters();
+        removed.len()
+    }
+
+    /// Resolves the next time an execution leaves the registry, counting
+    /// from when this is called rather than first polled.
+    pub(super) fn released(&self) -> Notified<'_> {
+        self.released.notified()
+    }
+
+    /// Whether any execution is still waiting to deliver its result to the
+    /// stream behind `sender`.
+    pub(super) async fn has_pending_for(&self, sender: &ResponseSender) -> bool {
+        self.executions
+            .count(ORIGIN, |route| delivers_to(route, sender))
+            .await
+            > 0
+    }
+}
+
+fn delivers_to(route: &ExecutionRoute, sender: &ResponseSender) -> bool {
+    matches!(route, ExecutionRoute::ActionStream { sender: registered, .. } if registered.same_channel(sender))
+}
+
+fn sender(finished: FinishedExecution) -> Option<ResponseSender> {
+    match finished.route {
+        ExecutionRoute::ActionStream { sender, .. } => Some(sender),
+        _ => None,
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use tucana::aquila::ActionTransferResponse;
+
     use super::*;
 
     #[test]
     fn take_removes_the_registered_entry() {
         futures::executor::block_on(async {
-            let registry = ActionFlowExecutionRegistry::new();
+            let executions = ExecutionRegistry::new();
+            let registry =
+                ActionFlowExecutionRegistry::new(executions.clone(), Duration::from_secs(60));
             let (tx, _rx) =
                 tokio::sync::mpsc::channel::<Result<ActionTransferResponse, tonic::Status>>(1);
 
-            registry.insert("execution-id".to_string(), tx).await;
+            registry
+                .insert("execution-id".to_string(), "action".to_string(), 1, 1, tx)
+                .await;
+            registry.dispatched("execution-id").await;
+
+            let tracked = executions
+                .get("execution-id")
+                .await
+                .expect("execution should be tracked");
+            assert_eq!(tracked.origin, "action_flow");
+            assert_eq!(tracked.state, "running");
 
             assert!(registry.take("execution-id").await.is_some());
             assert!(registry.take("execution-id").await.is_none());
+            assert!(executions.get("execution-id").await.is_none());
+        });
+    }
+
+    #[test]
+    fn has_pending_for_only_matches_the_registered_stream() {
+        futures::executor::block_on(async {
+            let registry =
+                ActionFlowExecutionRegistry::new(ExecutionRegistry::new(), Duration::from_secs(60));
+            let (tx, _rx) =
+                tokio::sync::mpsc::channel::<Result<ActionTransferResponse, tonic::Status>>(1);
+            let (other_tx, _other_rx) =
+                tokio::sync::mpsc::channel::<Result<ActionTransferResponse, tonic::Status>>(1);
+
+            registry
+                .insert(
+                    "execution-id".to_string(),
+                    "action".to_string(),
+                    1,
+                    1,
+                    tx.clone(),
+                )
+                .await;
+
+            assert!(registry.has_pending_for(&tx).await);
+            assert!(!registry.has_pending_for(&other_tx).await);
+
+            registry.take("execution-id").await;
+            assert!(!registry.has_pending_for(&tx).await);
+        });
+    }
+
+    #[test]
+    fn take_for_leaves_other_actions_executions_alone() {
+        futures::executor::block_on(async {
+            let registry =
+                ActionFlowExecutionRegistry::new(ExecutionRegistry::new(), Duration::from_secs(60));
+            let (tx, _rx) =
+                tokio::sync::mpsc::channel::<Result<ActionTransferResponse, tonic::Status>>(1);
+
+            registry
+                .insert("execution-id".to_string(), "action".to_string(), 1, 1, tx)
+                .await;
+
+            assert!(registry.take_for("execution-id", "other").await.is_none());
+            assert!(registry.take_for("execution-id", "action").await.is_some());
+            assert!(registry.take("execution-id").await.is_none());
+        });
+    }
+
+    #[test]
+    fn expire_only_removes_executions_past_their_deadline() {
+        futures::executor::block_on(async {
+            let registry =
+                ActionFlowExecutionRegistry::new(ExecutionRegistry::new(), Duration::from_secs(60));
+            let (tx, _rx) =
+                tokio::sync::mpsc::channel::<Result<ActionTransferResponse, tonic::Status>>(1);
+
+            registry
+                .insert("execution-id".to_string(), "action".to_string(), 1, 1, tx)
+                .await;
+
+            assert!(
+                registry
+                    .expire("execution-id", Instant::now())
+                    .await
+                    .is_none()
+            );
+
+            let later = Instant::now() + Duration::from_secs(61);
+            assert!(registry.expire("execution-id", later).await.is_some());
+            assert!(registry.take("execution-id").await.is_none());
+        });
+    }
+
+    #[test]
+    fn remove_for_only_drops_the_closed_streams_executions() {
+        futures::executor::block_on(async {
+            let executions = ExecutionRegistry::new();
+            let registry =
+                ActionFlowExecutionRegistry::new(executions.clone(), Duration::from_secs(60));
+            let (tx, _rx) =
+                tokio::sync::mpsc::channel::<Result<ActionTransferResponse, tonic::Status>>(1);
+            let (other_tx, _other_rx) =
+                tokio::sync::mpsc::channel::<Result<ActionTransferResponse, tonic::Status>>(1);
+
+            registry
+                .insert("first".to_string(), "action".to_string(), 1, 1, tx.clone())
+                .await;
+            registry
+                .insert("second".to_string(), "action".to_string(), 1, 1, tx.clone())
+                .await;
+            registry
+                .insert("other".to_string(), "action".to_string(), 1, 1, other_tx)
+                .await;
+
+            assert_eq!(registry.remove_for(&tx).await, 2);
+            assert!(!registry.has_pending_for(&tx).await);
+            assert!(registry.take("other").await.is_some());
+            assert!(executions.snapshot().await.is_empty());
         });
     }
 
     #[test]
     fn take_returns_none_for_unknown_execution_id() {
         futures::executor::block_on(async {
-            let registry = ActionFlowExecutionRegistry::new();
+            let registry =
+                ActionFlowExecutionRegistry::new(ExecutionRegistry::new(), Duration::from_secs(60));
             assert!(registry.take("missing").await.is_none());
         });
     }
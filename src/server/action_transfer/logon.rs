@@ -1,9 +1,20 @@
 //! Handles the first message of an action's transfer stream: authenticating
 //! the token, registering the action's module with Sagittarius, and wiring
-//! up the NATS subscriptions that feed the rest of the stream.
+//! up the NATS subscriptions that feed the rest of the stream. A logon that
+//! resumes a session skips whatever the previous stream already did, and a
+//! module Sagittarius already has isn't sent again.
 
+use std::{collections::HashSet, time::Duration};
+
+use tokio::{
+    sync::broadcast::{self, error::RecvError, error::TryRecvError},
+    task::JoinHandle,
+};
 use tonic::Status;
-use tucana::aquila::{ActionFlowUpdate, ActionLogon, ActionTransferResponse};
+use tucana::{
+    aquila::{ActionFlowUpdate, ActionLogon, ActionTransferResponse},
+    shared::Flows,
+};
 
 use crate::{
     flow::{FlowChange, flow_belongs_to_action, to_action_flow},
@@ -12,10 +23,38 @@ use crate::{
 
 use super::{
     ActionTransferContext,
-    nats_bridge::{forward_nats_to_action, get_flows},
-    pending_replies::PendingReplyStore,
+    configuration_acks::{ConfigurationRetry, ConfigurationVersions},
+    connections::ActionConnection,
+    module_updates::module_digest,
+    nats_bridge::{ExecutionLimits, forward_nats_to_action, get_flows, subscribe_executions},
+    sessions::{FlowCursor, SessionState},
 };
 
+/// The tasks a logon spawns for its stream. The execution and flow
+/// forwarders stop once the connection closes; joining them tells the
+/// stream when its pending replies are settled and where flow updates left
+/// off.
+#[derive(Default)]
+pub(super) struct StreamForwarders {
+    config_started: bool,
+    executions: Option<JoinHandle<()>>,
+    flows: Option<JoinHandle<FlowCursor>>,
+}
+
+impl StreamForwarders {
+    /// Waits for the execution and flow forwarders to stop and returns the
+    /// flow cursor, if the flow forwarder handed one back.
+    pub(super) async fn join(self) -> Option<FlowCursor> {
+        if let Some(executions) = self.executions {
+            let _ = executions.await;
+        }
+        match self.flows {
+            Some(flows) => flows.await.ok(),
+            None => None,
+        }
+    }
+}
+
 /// Extracts the bearer token from gRPC metadata.
 pub(super) fn extract_token(
     request: &tonic::Request<tonic::Streaming<tucana::aquila::ActionTransferRequest>>,
@@ -81,14 +120,18 @@ fn overwrite_module_definition_sources(
 }
 
 /// Validates the logon request, starts NATS + config/flow forwarders, and returns the accepted logon.
+///
+/// With a `resumed` session, the module is only sent to Sagittarius again
+/// if it changed, and flow updates continue from the previous stream's
+/// cursor instead of re-sending every known flow.
 pub(super) async fn handle_logon(
     token: &str,
     mut action_logon: ActionLogon,
     context: ActionTransferContext,
     tx: tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
-    pending_replies: PendingReplyStore,
-    cfg_forwarder_started: &mut bool,
-    flow_forwarder_started: &mut bool,
+    connection: ActionConnection,
+    resumed: Option<SessionState>,
+    forwarders: &mut StreamForwarders,
 ) -> Result<ActionLogon, Status> {
     let module = match action_logon.module.as_mut() {
         Some(m) => m,
@@ -98,7 +141,11 @@ pub(super) async fn handle_logon(
         }
     };
     let identifier = module.identifier.clone();
-    log::info!("Action logon attempt identifier={}", identifier);
+    log::info!(
+        "Action logon attempt identifier={} instance_id={}",
+        identifier,
+        connection.instance_id
+    );
 
     if !context.actions.has_action(&token.to_string(), &identifier) {
         metrics::action_connection(&identifier, "rejected");
@@ -114,40 +161,80 @@ pub(super) async fn handle_logon(
 
     overwrite_module_definition_sources(module, &identifier);
 
-    if let Some(module_service) = &context.module_service {
+    let (module_registered, flow_cursor) = match resumed {
+        Some(state) => (state.module.as_ref() == Some(&*module), state.flows),
+        None => (false, None),
+    };
+
+    if module_registered {
+        log::debug!(
+            "Skipping module update for resumed action session identifier={}",
+            identifier
+        );
+    } else if let Some(module_service) = &context.module_service {
         let available_definition_sources = context.actions.collect_modules();
-        let mut client = module_service.lock().await;
-        let response = client
-            .update_modules(
-                tucana::aquila::ModuleUpdateRequest {
-                    modules: vec![module.clone()],
-                },
-                available_definition_sources,
-            )
-            .await;
-
-        if !response.success {
-            metrics::action_connection(&identifier, "rejected");
-            metrics::action_failure(&identifier, "module_update");
-            errors::record_message(
-                "dependency",
-                "action.logon",
-                "Sagittarius rejected the action module update",
-                format!("action.identifier={identifier}"),
+        let digest = match module_digest(module, &available_definition_sources) {
+            Ok(digest) => Some(digest),
+            Err(err) => {
+                errors::record(
+                    "protocol",
+                    "action.module_digest",
+                    &err,
+                    format!("action.identifier={identifier}"),
+                );
+                None
+            }
+        };
+
+        let unchanged = match &digest {
+            Some(digest) => context.module_updates.is_current(&identifier, digest).await,
+            None => false,
+        };
+
+        if unchanged {
+            log::debug!(
+                "Skipping module update for unchanged action module identifier={}",
+                identifier
             );
-            return Err(Status::internal(
-                "could not update action module via Sagittarius",
-            ));
+        } else {
+            let mut client = module_service.lock().await;
+            let response = client
+                .update_modules(
+                    tucana::aquila::ModuleUpdateRequest {
+                        modules: vec![module.clone()],
+                    },
+                    available_definition_sources,
+                )
+                .await;
+            drop(client);
+
+            if !response.success {
+                metrics::action_connection(&identifier, "rejected");
+                metrics::action_failure(&identifier, "module_update");
+                errors::record_message(
+                    "dependency",
+                    "action.logon",
+                    "Sagittarius rejected the action module update",
+                    format!("action.identifier={identifier}"),
+                );
+                return Err(Status::internal(
+                    "could not update action module via Sagittarius",
+                ));
+            }
+
+            if let Some(digest) = digest {
+                context.module_updates.record(&identifier, digest).await;
+            }
         }
     }
 
-    log::debug!("Action connected identifier={}", identifier);
+    log::debug!(
+        "Action connected identifier={} instance_id={}",
+        identifier,
+        connection.instance_id
+    );
 
-    let sub = match context
-        .client
-        .subscribe(format!("action.{}.*", identifier))
-        .await
-    {
+    let sub = match subscribe_executions(&context.client, &identifier).await {
         Ok(s) => s,
         Err(err) => {
             metrics::action_connection(&identifier, "rejected");
@@ -178,76 +265,145 @@ pub(super) async fn handle_logon(
         ));
     }
 
-    log::debug!("Subscribed to action subject action.{}.*", identifier);
+    log::debug!(
+        "Subscribed to action subject action.{}.* queue_group={} instance_id={}",
+        identifier,
+        identifier,
+        connection.instance_id
+    );
 
+    let limits = ExecutionLimits {
+        timeout: context
+            .actions
+            .action_execution_timeout(token, &identifier)
+            .unwrap_or(context.execution_timeout),
+        max_in_flight: context.actions.action_max_in_flight(token, &identifier),
+    };
     let tx_clone = tx.clone();
-    let pending_replies_clone = pending_replies.clone();
     let forwarder_identifier = identifier.clone();
-    tokio::spawn(async move {
-        forward_nats_to_action(forwarder_identifier, sub, tx_clone, pending_replies_clone).await;
-    });
+    let client = context.client.clone();
+    let drain = context.drain.clone();
+    let forwarder_connection = connection.clone();
+    forwarders.executions = Some(tokio::spawn(async move {
+        forward_nats_to_action(
+            forwarder_identifier,
+            sub,
+            tx_clone,
+            forwarder_connection,
+            limits,
+            client,
+            drain,
+        )
+        .await;
+    }));
 
     // A logon is only the first message on the stream, but `handle_logon` can't
-    // assume it's only ever called once per stream, so the caller-owned flag
+    // assume it's only ever called once per stream, so the caller-owned state
     // guards against starting a second, redundant forwarder task.
-    if !*cfg_forwarder_started {
-        *cfg_forwarder_started = true;
+    if !forwarders.config_started {
+        forwarders.config_started = true;
         log::debug!("Starting config forwarder action={}", identifier);
         spawn_cfg_forwarder(
             identifier.clone(),
-            context.action_config_tx.clone(),
+            token.to_string(),
+            context.clone(),
             tx.clone(),
+            connection.clone(),
         );
     }
 
-    if !*flow_forwarder_started {
-        *flow_forwarder_started = true;
-        send_known_flows(&identifier, context.kv.clone(), tx.clone()).await;
+    if forwarders.flows.is_none() {
+        let cursor = match flow_cursor {
+            Some(cursor) => {
+                log::debug!(
+                    "Continuing flow updates of resumed action session action={}",
+                    identifier
+                );
+                cursor
+            }
+            None => {
+                // Subscribed before the forwarder reads the store, so a
+                // change made in between is forwarded rather than lost.
+                FlowCursor {
+                    receiver: context.action_flow_tx.subscribe(),
+                    undelivered: None,
+                    known: HashSet::new(),
+                    out_of_sync: true,
+                }
+            }
+        };
         log::debug!("Starting flow forwarder action={}", identifier);
-        spawn_flow_forwarder(
+        forwarders.flows = Some(spawn_flow_forwarder(
             identifier.clone(),
-            context.action_flow_tx.clone(),
+            cursor,
+            context.kv.clone(),
             tx.clone(),
-        );
+            connection,
+        ));
     }
 
     Ok(action_logon)
 }
 
-/// Sends every flow this action already owns from the flow store, so a
-/// newly connected action doesn't have to wait for its next update to learn
-/// about flows created before it connected.
-async fn send_known_flows(
+/// How long the flow forwarder waits before reading the flow store again
+/// after it couldn't re-sync an action.
+const RESYNC_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// How a re-sync from the flow store went.
+#[derive(Debug, PartialEq, Eq)]
+enum Resync {
+    Synced,
+    /// The flow store couldn't be read; nothing was sent.
+    StoreUnavailable,
+    /// The response stream closed while the flows were being sent.
+    StreamClosed,
+}
+
+/// Sends every flow this action owns in `flows`, read from the flow store,
+/// so a newly connected action doesn't have to wait for its next update to
+/// learn about flows created before it connected. `known` holds the flows
+/// the action was already sent; any of them no longer in the store are sent
+/// as deleted, so the same call re-syncs an action that missed updates.
+async fn resync_flows<E: std::error::Error + 'static>(
     action_identifier: &str,
-    kv: async_nats::jetstream::kv::Store,
-    tx: tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
-) {
-    let flows = match get_flows("*.*.*.*".to_string(), kv).await {
+    flows: Result<Flows, E>,
+    tx: &tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+    known: &mut HashSet<i64>,
+) -> Resync {
+    let flows = match flows {
         Ok(flows) => flows,
         Err(err) => {
+            metrics::action_failure(action_identifier, "flow_resync");
             errors::record(
                 "flow_storage",
-                "action.logon.known_flows",
+                "action.flows.resync",
                 &err,
                 format!("action.identifier={action_identifier}"),
             );
-            return;
+            return Resync::StoreUnavailable;
         }
     };
 
-    let mut sent_count = 0;
-    for flow in flows.flows {
-        if !flow_belongs_to_action(&flow, action_identifier) {
-            continue;
-        }
+    let flows: Vec<_> = flows
+        .flows
+        .into_iter()
+        .filter(|flow| flow_belongs_to_action(flow, action_identifier))
+        .collect();
+    let current: HashSet<i64> = flows.iter().map(|flow| flow.flow_id).collect();
+    let mut stale: Vec<i64> = known.difference(&current).copied().collect();
+    stale.sort_unstable();
+
+    let updates = flows
+        .iter()
+        .map(|flow| tucana::aquila::action_flow_update::Data::UpdatedFlow(to_action_flow(flow)));
+    let deletions = stale
+        .iter()
+        .map(|flow_id| tucana::aquila::action_flow_update::Data::DeletedFlow(*flow_id));
 
+    for data in updates.chain(deletions) {
         let resp = ActionTransferResponse {
             data: Some(tucana::aquila::action_transfer_response::Data::FlowUpdate(
-                ActionFlowUpdate {
-                    data: Some(tucana::aquila::action_flow_update::Data::UpdatedFlow(
-                        to_action_flow(&flow),
-                    )),
-                },
+                ActionFlowUpdate { data: Some(data) },
             )),
         };
 
@@ -256,79 +412,289 @@ async fn send_known_flows(
                 "Action transfer response stream closed while sending known flows action={}",
                 action_identifier
             );
-            return;
+            return Resync::StreamClosed;
         }
-        sent_count += 1;
     }
 
     log::debug!(
-        "Sent known flows to action action={} flow_count={}",
+        "Sent known flows to action action={} flow_count={} deleted_count={}",
         action_identifier,
-        sent_count
+        current.len(),
+        stale.len()
     );
+    *known = current;
+    Resync::Synced
 }
 
-/// Forwards config updates for the given action identifier to the gRPC stream.
+/// Discards everything a receiver that fell behind still has buffered; the
+/// snapshot it's re-synced with supersedes those changes.
+fn discard_buffered<T: Clone>(receiver: &mut broadcast::Receiver<T>) {
+    loop {
+        match receiver.try_recv() {
+            Ok(_) | Err(TryRecvError::Lagged(_)) => continue,
+            Err(TryRecvError::Empty | TryRecvError::Closed) => return,
+        }
+    }
+}
+
+/// Sends the action its current configuration, then forwards config updates
+/// for the given action identifier to the gRPC stream. If the forwarder
+/// falls behind the broadcast, the action is re-sent its current
+/// configuration instead. For an action that acknowledges pushes,
+/// the latest one is re-sent until it's acknowledged or runs out of attempts.
 pub(super) fn spawn_cfg_forwarder(
     action_identifier: String,
-    cfg_tx: tokio::sync::broadcast::Sender<tucana::shared::ModuleConfigurations>,
+    token: String,
+    context: ActionTransferContext,
     tx: tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+    connection: ActionConnection,
 ) {
-    let mut cfg_rx = cfg_tx.subscribe();
+    // Subscribed before reading the snapshot, so a push in between is
+    // forwarded rather than lost.
+    let cfg_rx = context.action_config_tx.subscribe();
     tokio::spawn(async move {
-        while let Ok(cfgs) = cfg_rx.recv().await {
-            if !applies_to_action(&cfgs, &action_identifier) {
-                log::debug!(
-                    "Config update does not apply to action {}",
-                    action_identifier
-                );
-                continue;
-            }
+        forward_configuration_updates(
+            &action_identifier,
+            cfg_rx,
+            || context.configuration_snapshot(&token, &action_identifier),
+            context.configuration_retry,
+            &context.configuration_versions,
+            &tx,
+            &connection,
+        )
+        .await;
+        log::debug!("Config forwarder stopped for {}", action_identifier);
+    });
+}
 
-            log::debug!("Forwarding config update to action {}", action_identifier);
-            let resp = ActionTransferResponse {
-                data: Some(
-                    tucana::aquila::action_transfer_response::Data::ModuleConfigurations(cfgs),
-                ),
-            };
+/// The loop behind [`spawn_cfg_forwarder`]: sends `snapshot` first and after
+/// falling behind `cfg_rx`, every push on `cfg_rx` in between. Returns once
+/// the broadcast, the stream or the connection closes.
+async fn forward_configuration_updates<F, Fut>(
+    action_identifier: &str,
+    mut cfg_rx: broadcast::Receiver<tucana::shared::ModuleConfigurations>,
+    snapshot: F,
+    retry: ConfigurationRetry,
+    versions: &ConfigurationVersions,
+    tx: &tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+    connection: &ActionConnection,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Vec<tucana::shared::ModuleConfigurations>>,
+{
+    let mut retry_at = None;
+    let mut attempts = 0;
+    let mut updates = snapshot().await;
+    loop {
+        let forwarded = std::mem::take(&mut updates);
+        match forward_configurations(action_identifier, forwarded, versions, tx, connection).await {
+            Some(true) if connection.configuration.acks_required() => {
+                attempts = 1;
+                retry_at = Some(tokio::time::Instant::now() + retry.ack_timeout);
+            }
+            Some(_) => {}
+            None => break,
+        }
 
-            if tx.send(Ok(resp)).await.is_err() {
-                metrics::action_config_update(&action_identifier, "failed");
-                metrics::action_failure(&action_identifier, "configuration_forward");
-                log::debug!("Config forwarder channel closed for {}", action_identifier);
-                break;
+        updates = tokio::select! {
+            received = cfg_rx.recv() => match received {
+                Ok(cfgs) => vec![cfgs],
+                Err(RecvError::Lagged(skipped)) => {
+                    metrics::action_update_lag(action_identifier, "configuration");
+                    log::warn!(
+                        "Config forwarder fell behind; re-syncing action={} skipped={}",
+                        action_identifier,
+                        skipped
+                    );
+                    discard_buffered(&mut cfg_rx);
+                    snapshot().await
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = tokio::time::sleep_until(retry_at.unwrap_or_else(tokio::time::Instant::now)),
+                if retry_at.is_some() =>
+            {
+                retry_at = None;
+                let Some((version, cfgs)) = connection.configuration.unacknowledged() else {
+                    continue;
+                };
+                if attempts >= retry.max_attempts {
+                    metrics::action_config_update(action_identifier, "unacknowledged");
+                    errors::record_message(
+                        "protocol",
+                        "action.configuration.ack",
+                        "Action never acknowledged its configuration",
+                        format!(
+                            "action.identifier={action_identifier} instance_id={} version={version} attempts={attempts}",
+                            connection.instance_id
+                        ),
+                    );
+                    continue;
+                }
+                log::warn!(
+                    "Re-sending unacknowledged configuration action={} version={} attempt={}",
+                    action_identifier,
+                    version,
+                    attempts + 1
+                );
+                metrics::action_config_update(action_identifier, "retried");
+                match send_configuration(action_identifier, cfgs, versions, tx, connection).await {
+                    Some(_) => {
+                        attempts += 1;
+                        retry_at = Some(tokio::time::Instant::now() + retry.ack_timeout);
+                        continue;
+                    }
+                    None => break,
+                }
             }
-            metrics::action_config_update(&action_identifier, "success");
+            _ = connection.closed() => break,
+        };
+    }
+}
+
+/// Sends the configurations meant for `action_identifier` to the gRPC
+/// stream. Returns whether any was sent, or `None` once the stream is closed.
+async fn forward_configurations(
+    action_identifier: &str,
+    updates: Vec<tucana::shared::ModuleConfigurations>,
+    versions: &ConfigurationVersions,
+    tx: &tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+    connection: &ActionConnection,
+) -> Option<bool> {
+    let mut sent = false;
+    for cfgs in updates {
+        if !applies_to_action(&cfgs, action_identifier) {
+            log::debug!(
+                "Config update does not apply to action {}",
+                action_identifier
+            );
+            continue;
         }
 
-        log::debug!("Config forwarder stopped for {}", action_identifier);
-    });
+        send_configuration(action_identifier, cfgs, versions, tx, connection).await?;
+        sent = true;
+    }
+
+    Some(sent)
+}
+
+/// Sends one configuration push and returns its version, or `None` once
+/// the stream is closed.
+async fn send_configuration(
+    action_identifier: &str,
+    cfgs: tucana::shared::ModuleConfigurations,
+    versions: &ConfigurationVersions,
+    tx: &tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+    connection: &ActionConnection,
+) -> Option<u64> {
+    // Versioned before sending, so an acknowledgement can't outrun it.
+    let version = connection
+        .configuration
+        .sent(&cfgs, versions.version_of(action_identifier, &cfgs));
+    log::debug!(
+        "Forwarding config update to action {} version={}",
+        action_identifier,
+        version
+    );
+    let resp = ActionTransferResponse {
+        data: Some(tucana::aquila::action_transfer_response::Data::ModuleConfigurations(cfgs)),
+    };
+
+    if tx.send(Ok(resp)).await.is_err() {
+        metrics::action_config_update(action_identifier, "failed");
+        metrics::action_failure(action_identifier, "configuration_forward");
+        log::debug!("Config forwarder channel closed for {}", action_identifier);
+        return None;
+    }
+    metrics::action_config_update(action_identifier, "success");
+    Some(version)
 }
 
-/// Forwards flow store changes that belong to the given action identifier to the gRPC stream.
+/// Forwards flow store changes that belong to the given action identifier to
+/// the gRPC stream, starting at `cursor`. Hands the cursor back once the
+/// connection closes or the stream stops accepting updates. A cursor that
+/// is out of sync, because it's new or fell behind the broadcast, is first
+/// re-synced from the flow store. Until that succeeds, no changes are
+/// forwarded and the store is read again every [`RESYNC_RETRY_DELAY`].
 pub(super) fn spawn_flow_forwarder(
     action_identifier: String,
-    flow_tx: tokio::sync::broadcast::Sender<FlowChange>,
+    cursor: FlowCursor,
+    kv: async_nats::jetstream::kv::Store,
     tx: tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
-) {
-    let mut flow_rx = flow_tx.subscribe();
+    connection: ActionConnection,
+) -> JoinHandle<FlowCursor> {
+    let FlowCursor {
+        receiver: mut flow_rx,
+        mut undelivered,
+        mut known,
+        mut out_of_sync,
+    } = cursor;
     tokio::spawn(async move {
-        while let Ok(change) = flow_rx.recv().await {
-            let data = match change {
+        loop {
+            if out_of_sync {
+                // The snapshot read below supersedes everything buffered so far.
+                undelivered = None;
+                discard_buffered(&mut flow_rx);
+                let flows = get_flows("*.*.*.*".to_string(), kv.clone()).await;
+                match resync_flows(&action_identifier, flows, &tx, &mut known).await {
+                    Resync::Synced => out_of_sync = false,
+                    Resync::StoreUnavailable => {
+                        tokio::select! {
+                            _ = tokio::time::sleep(RESYNC_RETRY_DELAY) => continue,
+                            _ = connection.closed() => break,
+                        }
+                    }
+                    Resync::StreamClosed => break,
+                }
+            }
+
+            let change = match undelivered.take() {
+                Some(change) => change,
+                None => {
+                    let received = tokio::select! {
+                        received = flow_rx.recv() => received,
+                        _ = connection.closed() => break,
+                    };
+                    match received {
+                        Ok(change) => change,
+                        Err(RecvError::Lagged(skipped)) => {
+                            metrics::action_update_lag(&action_identifier, "flow");
+                            log::warn!(
+                                "Flow forwarder fell behind; re-syncing action={} skipped={}",
+                                action_identifier,
+                                skipped
+                            );
+                            out_of_sync = true;
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            };
+
+            let (data, flow_id, deleted) = match &change {
                 FlowChange::Updated(flow) => {
-                    if !flow_belongs_to_action(&flow, &action_identifier) {
+                    if !flow_belongs_to_action(flow, &action_identifier) {
                         continue;
                     }
-                    tucana::aquila::action_flow_update::Data::UpdatedFlow(to_action_flow(&flow))
+                    (
+                        tucana::aquila::action_flow_update::Data::UpdatedFlow(to_action_flow(flow)),
+                        flow.flow_id,
+                        false,
+                    )
                 }
                 FlowChange::Deleted {
                     flow_id,
                     definition_source,
                 } => {
-                    if definition_source != format!("action.{action_identifier}") {
+                    if *definition_source != format!("action.{action_identifier}") {
                         continue;
                     }
-                    tucana::aquila::action_flow_update::Data::DeletedFlow(flow_id)
+                    (
+                        tucana::aquila::action_flow_update::Data::DeletedFlow(*flow_id),
+                        *flow_id,
+                        true,
+                    )
                 }
             };
 
@@ -343,13 +709,25 @@ pub(super) fn spawn_flow_forwarder(
                 metrics::flow_operation("forward", "failure", 1);
                 metrics::action_failure(&action_identifier, "flow_forward");
                 log::debug!("Flow forwarder channel closed for {}", action_identifier);
+                undelivered = Some(change);
                 break;
             }
             metrics::flow_operation("forward", "success", 1);
+            if deleted {
+                known.remove(&flow_id);
+            } else {
+                known.insert(flow_id);
+            }
         }
 
         log::debug!("Flow forwarder stopped for {}", action_identifier);
-    });
+        FlowCursor {
+            receiver: flow_rx,
+            undelivered,
+            known,
+            out_of_sync,
+        }
+    })
 }
 
 #[cfg(test)]
@@ -373,6 +751,248 @@ mod tests {
         assert!(!applies_to_action(&configs, "another-action"));
     }
 
+    #[test]
+    fn discard_buffered_skips_past_lag_to_an_empty_receiver() {
+        let (sender, mut receiver) = broadcast::channel(2);
+        for value in 0..5 {
+            sender.send(value).expect("receiver is subscribed");
+        }
+
+        discard_buffered(&mut receiver);
+
+        assert!(matches!(receiver.try_recv(), Err(TryRecvError::Empty)));
+        sender.send(5).expect("receiver is subscribed");
+        assert_eq!(receiver.try_recv().ok(), Some(5));
+    }
+
+    fn owned_flow(flow_id: i64, action_identifier: &str) -> tucana::shared::ValidationFlow {
+        tucana::shared::ValidationFlow {
+            flow_id,
+            definition_source: Some(format!("action.{action_identifier}")),
+            ..Default::default()
+        }
+    }
+
+    fn flow_update(
+        response: Result<ActionTransferResponse, Status>,
+    ) -> tucana::aquila::action_flow_update::Data {
+        match response.expect("response is not an error").data {
+            Some(tucana::aquila::action_transfer_response::Data::FlowUpdate(
+                ActionFlowUpdate { data: Some(data) },
+            )) => data,
+            other => panic!("expected flow update, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resync_sends_owned_flows_and_deletes_stale_ones() {
+        futures::executor::block_on(async {
+            let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+            let mut known = HashSet::from([1, 2]);
+            let flows: Result<Flows, std::io::Error> = Ok(Flows {
+                flows: vec![owned_flow(1, "send-email"), owned_flow(3, "another-action")],
+            });
+
+            let resync = resync_flows("send-email", flows, &tx, &mut known).await;
+
+            assert_eq!(resync, Resync::Synced);
+            assert_eq!(known, HashSet::from([1]));
+            assert!(matches!(
+                flow_update(rx.try_recv().expect("flow 1 is sent")),
+                tucana::aquila::action_flow_update::Data::UpdatedFlow(flow) if flow.flow_id == 1
+            ));
+            assert_eq!(
+                flow_update(rx.try_recv().expect("flow 2 is deleted")),
+                tucana::aquila::action_flow_update::Data::DeletedFlow(2)
+            );
+            assert!(rx.try_recv().is_err());
+        });
+    }
+
+    #[test]
+    fn resync_keeps_known_flows_when_the_store_is_unavailable() {
+        futures::executor::block_on(async {
+            let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+            let mut known = HashSet::from([1, 2]);
+            let flows: Result<Flows, _> = Err(std::io::Error::other("flow store unavailable"));
+
+            let resync = resync_flows("send-email", flows, &tx, &mut known).await;
+
+            assert_eq!(resync, Resync::StoreUnavailable);
+            assert_eq!(known, HashSet::from([1, 2]));
+            assert!(rx.try_recv().is_err());
+        });
+    }
+
+    #[test]
+    fn resync_stops_once_the_stream_is_closed() {
+        futures::executor::block_on(async {
+            let (tx, rx) = tokio::sync::mpsc::channel(8);
+            drop(rx);
+            let mut known = HashSet::from([2]);
+            let flows: Result<Flows, std::io::Error> = Ok(Flows {
+                flows: vec![owned_flow(1, "send-email")],
+            });
+
+            let resync = resync_flows("send-email", flows, &tx, &mut known).await;
+
+            assert_eq!(resync, Resync::StreamClosed);
+            assert_eq!(known, HashSet::from([2]));
+        });
+    }
+
+    #[test]
+    fn resync_after_lag_deletes_flows_removed_while_behind() {
+        futures::executor::block_on(async {
+            let (sender, mut receiver) = broadcast::channel(1);
+            sender
+                .send(FlowChange::Deleted {
+                    flow_id: 1,
+                    definition_source: "action.send-email".to_string(),
+                })
+                .expect("receiver is subscribed");
+            sender
+                .send(FlowChange::Updated(Box::new(owned_flow(2, "send-email"))))
+                .expect("receiver is subscribed");
+            assert!(matches!(receiver.try_recv(), Err(TryRecvError::Lagged(1))));
+
+            discard_buffered(&mut receiver);
+            let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+            let mut known = HashSet::from([1]);
+            let flows: Result<Flows, std::io::Error> = Ok(Flows {
+                flows: vec![owned_flow(2, "send-email")],
+            });
+
+            let resync = resync_flows("send-email", flows, &tx, &mut known).await;
+
+            assert_eq!(resync, Resync::Synced);
+            assert!(matches!(receiver.try_recv(), Err(TryRecvError::Empty)));
+            assert_eq!(known, HashSet::from([2]));
+            assert!(matches!(
+                flow_update(rx.try_recv().expect("flow 2 is sent")),
+                tucana::aquila::action_flow_update::Data::UpdatedFlow(flow) if flow.flow_id == 2
+            ));
+            assert_eq!(
+                flow_update(rx.try_recv().expect("flow 1 is deleted")),
+                tucana::aquila::action_flow_update::Data::DeletedFlow(1)
+            );
+        });
+    }
+
+    fn static_service_configuration(
+        endpoint: &str,
+    ) -> crate::configuration::service::ServiceConfiguration {
+        crate::configuration::service::ServiceConfiguration::from_json(
+            &serde_json::json!({
+                "actions": [{
+                    "token": "action-token",
+                    "identifier": "send-email",
+                    "configs": [{
+                        "project_id": 1,
+                        "configs": [{ "identifier": "endpoint", "value": endpoint }],
+                    }],
+                }],
+                "runtimes": [],
+            })
+            .to_string(),
+        )
+        .expect("service configuration is valid")
+    }
+
+    fn retry() -> ConfigurationRetry {
+        ConfigurationRetry {
+            ack_timeout: std::time::Duration::from_secs(30),
+            max_attempts: 1,
+        }
+    }
+
+    fn forwarded_configurations(
+        rx: &mut tokio::sync::mpsc::Receiver<Result<ActionTransferResponse, Status>>,
+    ) -> Vec<tucana::shared::ModuleConfigurations> {
+        let mut forwarded = Vec::new();
+        while let Ok(response) = rx.try_recv() {
+            match response.expect("response is not an error").data {
+                Some(tucana::aquila::action_transfer_response::Data::ModuleConfigurations(
+                    configurations,
+                )) => forwarded.push(configurations),
+                other => panic!("expected module configurations, got {:?}", other),
+            }
+        }
+        forwarded
+    }
+
+    #[tokio::test]
+    async fn static_logon_delivers_configuration_exactly_once() {
+        let service_configuration = static_service_configuration("mail.example");
+        let (cfg_tx, cfg_rx) = broadcast::channel(8);
+        // A push for another action isn't forwarded.
+        cfg_tx
+            .send(tucana::shared::ModuleConfigurations {
+                module_identifier: "send-sms".to_string(),
+                module_configurations: vec![],
+            })
+            .expect("receiver is subscribed");
+        drop(cfg_tx);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let connection = ActionConnection::new(crate::flow::executions::ExecutionRegistry::new());
+
+        forward_configuration_updates(
+            "send-email",
+            cfg_rx,
+            || async {
+                service_configuration.get_action_configuration(
+                    &"action-token".to_string(),
+                    &"send-email".to_string(),
+                )
+            },
+            retry(),
+            &ConfigurationVersions::default(),
+            &tx,
+            &connection,
+        )
+        .await;
+
+        let forwarded = forwarded_configurations(&mut rx);
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(forwarded[0].module_identifier, "send-email");
+    }
+
+    #[tokio::test]
+    async fn static_configuration_is_sent_again_only_when_it_changed() {
+        let service_configuration = static_service_configuration("mail.example");
+        let (cfg_tx, cfg_rx) = broadcast::channel(8);
+        for endpoint in ["mail.example", "smtp.example", "smtp.example"] {
+            for configurations in service_configuration
+                .update_action_configurations(&static_service_configuration(endpoint))
+            {
+                cfg_tx.send(configurations).expect("receiver is subscribed");
+            }
+        }
+        drop(cfg_tx);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let connection = ActionConnection::new(crate::flow::executions::ExecutionRegistry::new());
+
+        forward_configuration_updates(
+            "send-email",
+            cfg_rx,
+            || async { Vec::new() },
+            retry(),
+            &ConfigurationVersions::default(),
+            &tx,
+            &connection,
+        )
+        .await;
+
+        let forwarded = forwarded_configurations(&mut rx);
+        assert_eq!(forwarded.len(), 1);
+        assert_eq!(
+            forwarded[0],
+            service_configuration
+                .get_action_configuration(&"action-token".to_string(), &"send-email".to_string())
+                [0]
+        );
+    }
+
     #[test]
     fn overwrite_module_definition_sources_uses_action_source() {
         let mut module = tucana::shared::Module {
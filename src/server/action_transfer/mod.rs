@@ -5,19 +5,47 @@
 //!
 //! The module is split by responsibility:
 //! - [`logon`] validates the initial logon message and registers the action.
+//! - [`connections`] keeps track of every connected action for the admin API.
 //! - [`nats_bridge`] moves execution requests/results between NATS and gRPC.
+//! - [`event_options`] reads what an event asks of Aquila: which flows to
+//!   trigger, and whether to return their results.
+//! - [`event_results`] collects the results of the flows an event triggered.
+//! - [`event_keys`] drops events that repeat a recent idempotency key.
 //! - [`pending_replies`] tracks which NATS reply subject an execution result belongs to.
 //! - [`flow_execution_registry`] tracks which action stream an action-triggered
 //!   flow execution's result belongs to.
-
+//! - [`sessions`] lets a reconnecting action resume its previous stream's session.
+//! - [`module_updates`] skips module updates Sagittarius already has.
+//! - [`configuration_acks`] retries configuration pushes an action didn't acknowledge.
+//! - [`cancellation`] lets an action cancel an execution.
+//! - [`draining`] tells an action Aquila is draining for shutdown.
+
+mod cancellation;
+mod configuration_acks;
+mod connections;
+mod draining;
+mod event_keys;
+mod event_options;
+mod event_results;
 mod flow_execution_registry;
 mod logon;
+mod module_updates;
 mod nats_bridge;
 mod pending_replies;
+mod sessions;
 
+pub use configuration_acks::{ConfigurationRetry, ConfigurationVersions};
+pub use connections::{ActionConnectionRegistry, ActionConnectionSnapshot};
+pub use event_keys::EventKeys;
 pub use flow_execution_registry::ActionFlowExecutionRegistry;
+pub use module_updates::ModuleUpdateCache;
+pub use sessions::ActionSessionRegistry;
 
-use std::{pin::Pin, sync::Arc};
+use std::{
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use futures::StreamExt;
 use futures_core::Stream;
@@ -31,16 +59,32 @@ use tucana::aquila::{
 };
 
 use crate::{
-    configuration::service::ServiceConfiguration, flow::FlowChange,
-    sagittarius::module_service_client_impl::SagittariusModuleServiceClient, telemetry::metrics,
+    configuration::{
+        config::OutputValidationMode, module_configurations::LatestModuleConfigurations,
+        service::ServiceConfiguration,
+    },
+    flow::{
+        FlowChange, dead_letters::DeadLetters, dispatch::ExecutionDispatcher,
+        executions::ExecutionRegistry,
+    },
+    sagittarius::module_service_client_impl::SagittariusModuleServiceClient,
+    server::Drain,
+    telemetry::metrics,
 };
 
-use logon::{extract_token, handle_logon};
+use cancellation::{CANCEL_EVENT_TYPE, handle_cancel};
+use configuration_acks::{ACK_EVENT_TYPE, acks_requested, handle_ack};
+use connections::{ActionConnection, CloseReason};
+use draining::{drain_notice, rejected_event};
+use event_options::take_event_options;
+use event_results::{busy_response, duplicate_response};
+use logon::{StreamForwarders, extract_token, handle_logon};
 use nats_bridge::{
-    handle_event, handle_flow_execution, handle_result, handle_sub_flow_execution,
-    send_stream_error,
+    EventDispatch, fail_pending_replies, handle_event, handle_flow_execution, handle_result,
+    handle_sub_flow_execution, reject_flow_execution, send_stream_error,
 };
 use pending_replies::PendingReplyStore;
+use sessions::{SESSION_ID_HEADER, SessionState};
 
 /// Every dependency an action's connection needs, bundled into one
 /// `Clone`-able value instead of threaded individually through every layer
@@ -51,21 +95,140 @@ use pending_replies::PendingReplyStore;
 pub(super) struct ActionTransferContext {
     pub(super) client: async_nats::Client,
     pub(super) kv: async_nats::jetstream::kv::Store,
+    /// Hands the flow executions actions ask for to runtimes.
+    pub(super) dispatcher: ExecutionDispatcher,
     /// Static, pre-provisioned action tokens/configuration loaded at startup.
     /// Read-only after startup, so it's shared directly rather than behind a lock.
     pub(super) actions: ServiceConfiguration,
     /// Present only in dynamic mode, where module updates must be relayed to Sagittarius.
     pub(super) module_service: Option<Arc<Mutex<SagittariusModuleServiceClient>>>,
+    /// Which modules Sagittarius already has, so unchanged ones aren't resent.
+    pub(super) module_updates: ModuleUpdateCache,
     /// Broadcasts module configuration updates to every connected action's config forwarder.
     pub(super) action_config_tx:
         tokio::sync::broadcast::Sender<tucana::shared::ModuleConfigurations>,
     /// Broadcasts flow store changes to every connected action's flow forwarder.
     pub(super) action_flow_tx: tokio::sync::broadcast::Sender<FlowChange>,
+    /// The latest configuration pushed per module, sent to an action on
+    /// logon and when it missed updates. Unused in static mode.
+    pub(super) module_configurations: LatestModuleConfigurations,
     /// Correlates action-triggered flow executions with the action stream to
     /// deliver their result to, once a runtime reports it.
     pub(super) flow_execution_registry: ActionFlowExecutionRegistry,
+    /// Every execution in flight, whatever asked for it.
+    pub(super) executions: ExecutionRegistry,
+    /// Every action stream past its logon, for the admin API.
+    pub(super) connections: ActionConnectionRegistry,
+    /// Sessions a reconnecting action can resume.
+    pub(super) sessions: ActionSessionRegistry,
+    /// How long an action may take to answer a forwarded execution request,
+    /// unless the service configuration overrides it for that action.
+    pub(super) execution_timeout: Duration,
+    /// The longest an event may wait for the results of the flows it triggered.
+    pub(super) event_result_timeout: Duration,
+    /// How many execution requests an action's events may have outstanding at once.
+    pub(super) event_fan_out_limit: usize,
+    /// How many of a connection's events are handled at once.
+    pub(super) event_max_in_flight: usize,
+    /// How many further events of a connection wait for a slot before
+    /// being turned down.
+    pub(super) event_max_queued: usize,
+    /// How the results of events that asked for them are checked against
+    /// their flow's output schema.
+    pub(super) output_validation: OutputValidationMode,
+    /// Idempotency keys of recent events, so retried events aren't handled twice.
+    pub(super) event_keys: EventKeys,
+    /// Where executions that couldn't be dispatched are recorded.
+    pub(super) dead_letters: DeadLetters,
+    /// When configuration pushes to actions that acknowledge them are retried.
+    pub(super) configuration_retry: ConfigurationRetry,
+    pub(super) configuration_versions: ConfigurationVersions,
     /// Whether Aquila is running in static mode, which changes how config updates are sourced.
     pub(super) is_static: bool,
+    /// Tells every stream when to stop taking new work and close during shutdown.
+    pub(super) drain: Drain,
+}
+
+impl ActionTransferContext {
+    /// The configuration `identifier` should currently have: in static mode
+    /// whatever the service configuration says, otherwise the latest push.
+    /// In static mode, a static action is sent this once at logon and again
+    /// only when the service configuration file changes.
+    pub(super) async fn configuration_snapshot(
+        &self,
+        token: &str,
+        identifier: &str,
+    ) -> Vec<tucana::shared::ModuleConfigurations> {
+        if self.is_static {
+            self.actions
+                .get_action_configuration(&token.to_string(), &identifier.to_string())
+        } else {
+            self.module_configurations
+                .get(identifier)
+                .await
+                .into_iter()
+                .collect()
+        }
+    }
+}
+
+/// Waits until no execution on this stream is still waiting for a result -
+/// neither a forwarded execution request, an action-triggered flow
+/// execution nor one of its events - or until `deadline` passes, whichever
+/// comes first.
+async fn in_flight_settled(
+    pending_replies: &PendingReplyStore,
+    flow_execution_registry: &ActionFlowExecutionRegistry,
+    event_dispatch: &EventDispatch,
+    tx: &tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+    deadline: Instant,
+) {
+    let executions_settled = async {
+        loop {
+            // Registered before checking, so a release in between isn't missed.
+            let replies_released = pending_replies.released();
+            let flows_released = flow_execution_registry.released();
+            if pending_replies.is_empty().await
+                && !flow_execution_registry.has_pending_for(tx).await
+            {
+                return;
+            }
+            tokio::select! {
+                _ = replies_released => {}
+                _ = flows_released => {}
+            }
+        }
+    };
+    let settled = async {
+        tokio::join!(executions_settled, event_dispatch.events_settled());
+    };
+
+    if tokio::time::timeout_at(deadline.into(), settled)
+        .await
+        .is_err()
+    {
+        log::warn!("Drain deadline passed with action executions still in flight");
+    }
+}
+
+/// Fails the pending replies of a detached session nobody resumed within
+/// its grace period.
+async fn expire_session(
+    sessions: ActionSessionRegistry,
+    session_id: String,
+    client: async_nats::Client,
+) {
+    tokio::time::sleep(sessions.grace_period()).await;
+    let Some(state) = sessions.expire(&session_id, Instant::now()).await else {
+        return;
+    };
+
+    log::info!(
+        "Action session expired action={} session_id={}",
+        state.identifier,
+        session_id
+    );
+    fail_pending_replies(&state.identifier, &client, &state.connection).await;
 }
 
 /// Implements the `ActionTransfer` gRPC service that a connected action
@@ -106,6 +269,17 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
         request: tonic::Request<tonic::Streaming<ActionTransferRequest>>,
     ) -> std::result::Result<tonic::Response<Self::TransferStream>, tonic::Status> {
         let token = extract_token(&request)?;
+        let resume_session_id = request
+            .metadata()
+            .get(SESSION_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty() && self.context.sessions.enabled())
+            .map(str::to_owned);
+        let configuration_acks = acks_requested(request.metadata());
+        if self.context.drain.is_draining() {
+            log::info!("Rejected action transfer stream reason=draining");
+            return Err(Status::unavailable("Aquila is shutting down"));
+        }
         log::debug!("Action transfer stream opened");
 
         let mut first_request = true;
@@ -113,27 +287,87 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
         let mut stream = request.into_inner();
 
         let context = self.context.clone();
-        let pending_replies = PendingReplyStore::new();
+        let mut connection = ActionConnection::new(context.executions.clone());
+        if configuration_acks {
+            connection.configuration.require_acks();
+        }
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let response_session_id = session_id.clone();
 
         let (tx, rx) =
             tokio::sync::mpsc::channel::<Result<ActionTransferResponse, tonic::Status>>(32);
 
         let stream_span = tracing::info_span!(
             "aquila.action.stream",
-            action.identifier = tracing::field::Empty
+            action.identifier = tracing::field::Empty,
+            action.instance_id = %connection.instance_id
         );
+        let stream_guard = context.drain.track_stream();
         tokio::spawn(async move {
-            let mut cfg_forwarder_started = false;
-            let mut flow_forwarder_started = false;
+            let _stream_guard = stream_guard;
+            let mut forwarders = StreamForwarders::default();
+            // The action whose executions this stream answers for, set once a
+            // logon is accepted or has taken over a previous session's.
+            let mut session_identifier = None;
             let mut connected_at = None;
             let mut connected_identifier = None;
+            let mut connection_id = None;
+            let mut drain_deadline = None;
+            let mut close_reason = CloseReason::Ended;
+            let event_dispatch = EventDispatch::new(
+                context.kv.clone(),
+                context.client.clone(),
+                context.event_fan_out_limit,
+                context.event_max_in_flight,
+                context.event_max_queued,
+                context.dead_letters.clone(),
+                context.executions.clone(),
+                context.output_validation,
+            );
             log::debug!("Action transfer stream started");
 
-            while let Some(next) = stream.next().await {
+            // While draining, the stream keeps reading so results for in-flight
+            // executions still arrive, and closes itself once they've settled.
+            loop {
+                let next = tokio::select! {
+                    next = stream.next() => next,
+                    _ = context.drain.draining(), if drain_deadline.is_none() => {
+                        log::info!("Draining action transfer stream");
+                        drain_deadline = Some(Instant::now() + context.drain.timeout());
+                        if connected_identifier.is_some()
+                            && tx.send(Ok(drain_notice(context.drain.timeout()))).await.is_err()
+                        {
+                            log::debug!("Action transfer response stream closed before drain notice could be sent");
+                        }
+                        continue;
+                    }
+                    _ = connection.closed() => {
+                        send_stream_error(&tx, Status::aborted("action session was resumed by another stream")).await;
+                        break;
+                    }
+                    _ = in_flight_settled(
+                        &connection.pending_replies,
+                        &context.flow_execution_registry,
+                        &event_dispatch,
+                        &tx,
+                        drain_deadline.unwrap_or_else(Instant::now),
+                    ), if drain_deadline.is_some() => {
+                        send_stream_error(&tx, Status::unavailable("Aquila is shutting down")).await;
+                        break;
+                    }
+                };
+
+                let Some(next) = next else {
+                    break;
+                };
+
                 let transfer_request = match next {
                     Ok(tr) => tr,
                     Err(status) => {
+                        // Also where a connection that stopped answering
+                        // keepalive pings ends up.
                         log::warn!("Action transfer input stream failed status={:?}", status);
+                        close_reason = CloseReason::Lost;
                         break;
                     }
                 };
@@ -166,14 +400,48 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
 
                             log::debug!("Received logon for action {}", identifier);
 
+                            if drain_deadline.is_some() {
+                                send_stream_error(&tx, Status::unavailable("Aquila is shutting down"))
+                                    .await;
+                                break;
+                            }
+
+                            let resumed = match &resume_session_id {
+                                Some(resume_session_id) => {
+                                    context
+                                        .sessions
+                                        .resume(resume_session_id, &token, &identifier)
+                                        .await
+                                }
+                                None => None,
+                            };
+                            if let Some(state) = &resumed {
+                                // Results for executions the previous stream
+                                // forwarded are routed through this one now.
+                                connection.pending_replies = state.connection.pending_replies.clone();
+                                session_identifier = Some(identifier.clone());
+                                log::info!(
+                                    "Resuming action session identifier={} session_id={}",
+                                    identifier,
+                                    session_id
+                                );
+                            } else if let Some(resume_session_id) = &resume_session_id {
+                                log::info!(
+                                    "Action session not resumable identifier={} requested_session_id={}",
+                                    identifier,
+                                    resume_session_id
+                                );
+                            }
+                            let resuming = resumed.is_some();
+
                             let accepted = match handle_logon(
                                 &token,
                                 action_logon,
                                 context.clone(),
                                 tx.clone(),
-                                pending_replies.clone(),
-                                &mut cfg_forwarder_started,
-                                &mut flow_forwarder_started,
+                                connection.clone(),
+                                resumed,
+                                &mut forwarders,
                             )
                             .await
                             {
@@ -193,9 +461,30 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
                             action_props = Some(accepted);
                             tracing::Span::current()
                                 .record("action.identifier", identifier.as_str());
-                            metrics::action_connection(&identifier, "accepted");
+                            metrics::action_connection(
+                                &identifier,
+                                if resuming { "resumed" } else { "accepted" },
+                            );
+                            if context.sessions.enabled() {
+                                context
+                                    .sessions
+                                    .attach(
+                                        session_id.clone(),
+                                        identifier.clone(),
+                                        token.clone(),
+                                        connection.clone(),
+                                    )
+                                    .await;
+                            }
+                            session_identifier = Some(identifier.clone());
                             metrics::action_active(&identifier, 1);
                             connected_at = Some(std::time::Instant::now());
+                            connection_id = Some(
+                                context
+                                    .connections
+                                    .register(identifier.clone(), connection.clone())
+                                    .await,
+                            );
                             connected_identifier = Some(identifier);
                         }
                         _ => {
@@ -228,18 +517,6 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
                     }
                 };
 
-                // Static mode has no Sagittarius to push config updates from, so
-                // re-broadcast the action's configured values on every message it
-                // sends instead of relying on a one-shot delivery at logon time.
-                if context.is_static {
-                    let configs = context.actions.get_action_configuration(&token, &identifier);
-                    for conf in configs {
-                        if let Err(err) = context.action_config_tx.send(conf) {
-                            log::warn!("No action configuration receivers available: {:?}", err);
-                        }
-                    }
-                };
-
                 match data {
                     tucana::aquila::action_transfer_request::Data::Logon(_) => {
                         log::warn!(
@@ -253,16 +530,118 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
                         .await;
                         break;
                     }
-                    tucana::aquila::action_transfer_request::Data::Event(event) => {
+                    tucana::aquila::action_transfer_request::Data::Event(event)
+                        if event.event_type == ACK_EVENT_TYPE =>
+                    {
+                        handle_ack(&identifier, event, &connection);
+                    }
+                    tucana::aquila::action_transfer_request::Data::Event(event)
+                        if event.event_type == CANCEL_EVENT_TYPE =>
+                    {
+                        handle_cancel(&identifier, event, &context, &connection).await;
+                    }
+                    tucana::aquila::action_transfer_request::Data::Event(mut event)
+                        if drain_deadline.is_some() =>
+                    {
+                        metrics::action_failure(&identifier, "draining");
+                        log::info!(
+                            "Rejected action event while draining action={} event_type={}",
+                            identifier,
+                            event.event_type
+                        );
+                        let options = take_event_options(&mut event, context.event_result_timeout);
+                        let correlation_id = options
+                            .results
+                            .as_ref()
+                            .map(|request| request.correlation_id.as_str());
+                        if tx
+                            .send(Ok(rejected_event(&event.event_type, correlation_id)))
+                            .await
+                            .is_err()
+                        {
+                            log::debug!("Action transfer response stream closed before event rejection could be sent");
+                        }
+                    }
+                    tucana::aquila::action_transfer_request::Data::Event(mut event) => {
                         log::debug!("Received event action={}", identifier);
                         metrics::action_event(&identifier);
-                        handle_event(
-                            &identifier,
-                            event,
-                            context.kv.clone(),
-                            context.client.clone(),
-                        )
-                        .await;
+                        connection.counters.event();
+                        let options =
+                            take_event_options(&mut event, context.event_result_timeout);
+                        // Waited for off the stream task, and turned down once
+                        // too many wait: the stream has to keep reading, since
+                        // the flows of the events it handles may need this very
+                        // stream to answer.
+                        let Some(admitted) = event_dispatch.admit_event() else {
+                            metrics::action_failure(&identifier, "events_saturated");
+                            log::warn!(
+                                "Rejected action event with the event queue full action={} event_type={}",
+                                identifier,
+                                event.event_type
+                            );
+                            let correlation_id = options
+                                .results
+                                .as_ref()
+                                .map(|request| request.correlation_id.as_str());
+                            if tx
+                                .send(Ok(busy_response(
+                                    &event.event_type,
+                                    correlation_id,
+                                    options.idempotency_key.as_deref(),
+                                )))
+                                .await
+                                .is_err()
+                            {
+                                log::debug!("Action transfer response stream closed before event rejection could be sent");
+                            }
+                            continue;
+                        };
+                        let dispatch = event_dispatch.clone();
+                        let event_keys = context.event_keys.clone();
+                        let tx = tx.clone();
+                        // Off the stream task, so results and further events
+                        // aren't held up by the flows this one triggers, which
+                        // may need this very stream to answer.
+                        tokio::spawn(async move {
+                            let Some(slot) = dispatch.start_event(admitted).await else {
+                                return;
+                            };
+                            if let Some(idempotency_key) = &options.idempotency_key
+                                && event_keys
+                                    .is_duplicate(&identifier, event.project_id, idempotency_key)
+                                    .await
+                            {
+                                log::debug!(
+                                    "Dropped duplicate event action={} idempotency_key={}",
+                                    identifier,
+                                    idempotency_key
+                                );
+                                metrics::action_event_duplicate(&identifier);
+                                let correlation_id = options
+                                    .results
+                                    .as_ref()
+                                    .map(|request| request.correlation_id.as_str());
+                                let _ = tx
+                                    .send(Ok(duplicate_response(correlation_id, idempotency_key)))
+                                    .await;
+                                return;
+                            }
+                            let project_id = event.project_id;
+                            let idempotency_key = options.idempotency_key.clone();
+                            let dispatched =
+                                handle_event(&identifier, event, options, dispatch, slot, tx)
+                                    .await;
+                            if !dispatched && let Some(idempotency_key) = idempotency_key {
+                                log::debug!(
+                                    "Forgetting idempotency key of an event that wasn't dispatched action={} idempotency_key={}",
+                                    identifier,
+                                    idempotency_key
+                                );
+                                event_keys
+                                    .forget(&identifier, project_id, &idempotency_key)
+                                    .await;
+                            }
+                        });
                     }
                     tucana::aquila::action_transfer_request::Data::Result(execution_result) => {
                         log::debug!(
@@ -270,12 +649,13 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
                             execution_result.execution_identifier,
                             identifier
                         );
+                        connection.counters.result();
 
                         handle_result(
                             &identifier,
                             execution_result,
                             context.client.clone(),
-                            pending_replies.clone(),
+                            &connection,
                         )
                         .await;
                     }
@@ -285,15 +665,28 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
                             identifier,
                             request.execution_identifier
                         );
+                        connection.counters.sub_flow_execution();
 
                         handle_sub_flow_execution(
                             &identifier,
                             request,
                             context.client.clone(),
+                            context.executions.clone(),
                             tx.clone(),
                         )
                         .await;
                     }
+                    tucana::aquila::action_transfer_request::Data::FlowExecution(request)
+                        if drain_deadline.is_some() =>
+                    {
+                        metrics::action_failure(&identifier, "draining");
+                        reject_flow_execution(
+                            &tx,
+                            request.execution_identifier,
+                            "Aquila is shutting down".to_string(),
+                        )
+                        .await;
+                    }
                     tucana::aquila::action_transfer_request::Data::FlowExecution(request) => {
                         log::debug!(
                             "Received flow execution request action={} execution_id={} flow_id={}",
@@ -301,13 +694,15 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
                             request.execution_identifier,
                             request.flow_id
                         );
+                        connection.counters.flow_execution();
 
                         handle_flow_execution(
                             &identifier,
                             request,
                             context.kv.clone(),
-                            context.client.clone(),
+                            context.dispatcher.clone(),
                             context.flow_execution_registry.clone(),
+                            context.dead_letters.clone(),
                             tx.clone(),
                         )
                         .await;
@@ -315,6 +710,53 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
                 }
             }
 
+            connection.close(close_reason);
+            // Senders left registered would keep the response stream open.
+            let abandoned = context.flow_execution_registry.remove_for(&tx).await;
+            if abandoned > 0 {
+                log::debug!(
+                    "Dropped flow executions of a closed action stream count={}",
+                    abandoned
+                );
+            }
+            let flow_cursor = forwarders.join().await;
+            if let Some(identifier) = session_identifier {
+                let module = action_props.and_then(|logon| logon.module);
+                if connected_identifier.is_some()
+                    && context.sessions.enabled()
+                    && !context.drain.is_draining()
+                {
+                    log::debug!(
+                        "Detached action session identifier={} session_id={}",
+                        identifier,
+                        session_id
+                    );
+                    context
+                        .sessions
+                        .detach(
+                            session_id.clone(),
+                            SessionState::new(
+                                identifier,
+                                token,
+                                module,
+                                connection.clone(),
+                                flow_cursor,
+                            ),
+                        )
+                        .await;
+                    tokio::spawn(expire_session(
+                        context.sessions.clone(),
+                        session_id,
+                        context.client.clone(),
+                    ));
+                } else {
+                    context.sessions.forget(&session_id).await;
+                    fail_pending_replies(&identifier, &context.client, &connection).await;
+                }
+            }
+            if let Some(connection_id) = connection_id {
+                context.connections.unregister(connection_id).await;
+            }
             if let Some(identifier) = connected_identifier {
                 metrics::action_active(&identifier, -1);
                 metrics::action_connection(&identifier, "closed");
@@ -329,6 +771,13 @@ impl ActionTransferService for AquilaActionTransferServiceServer {
         }
         .instrument(stream_span));
 
-        Ok(tonic::Response::new(Box::pin(ReceiverStream::new(rx))))
+        let mut response: tonic::Response<Self::TransferStream> =
+            tonic::Response::new(Box::pin(ReceiverStream::new(rx)));
+        if self.context.sessions.enabled()
+            && let Ok(value) = response_session_id.parse()
+        {
+            response.metadata_mut().insert(SESSION_ID_HEADER, value);
+        }
+        Ok(response)
     }
 }
@@ -0,0 +1,189 @@
+//! Remembers which module each action last had accepted by Sagittarius, so
+//! a logon with an unchanged module doesn't need the Sagittarius round-trip.
+//! Digests live in their own KV bucket (`nats.module_bucket`) and survive
+//! restarts and are shared across Aquila instances. A digest older than the
+//! forced refresh interval no longer counts, so Sagittarius still hears
+//! about every module now and then.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{configuration::module_configurations::module_key, telemetry::errors, validation};
+
+/// The last module accepted for an action.
+#[derive(Debug, Deserialize, Serialize)]
+struct AcceptedModule {
+    digest: String,
+    accepted_at_unix_ms: i64,
+}
+
+/// Module digests per action identifier. Without a bucket, every logon
+/// updates Sagittarius.
+#[derive(Clone)]
+pub struct ModuleUpdateCache {
+    kv: Option<async_nats::jetstream::kv::Store>,
+    /// `None` never forces a refresh of an unchanged module.
+    force_refresh_interval: Option<Duration>,
+}
+
+impl ModuleUpdateCache {
+    /// A `force_refresh_interval` of zero disables forced refreshes.
+    pub fn new(
+        kv: Option<async_nats::jetstream::kv::Store>,
+        force_refresh_interval: Duration,
+    ) -> Self {
+        Self {
+            kv,
+            force_refresh_interval: (!force_refresh_interval.is_zero())
+                .then_some(force_refresh_interval),
+        }
+    }
+
+    /// Whether `digest` is what Sagittarius last accepted for `identifier`,
+    /// recently enough that it needn't be sent again. Any KV failure
+    /// counts as "no", so the update goes through.
+    pub(super) async fn is_current(&self, identifier: &str, digest: &str) -> bool {
+        let Some(kv) = &self.kv else {
+            return false;
+        };
+
+        let entry = match kv.get(module_key(identifier)).await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return false,
+            Err(err) => {
+                errors::record(
+                    "flow_storage",
+                    "action.module_digest.get",
+                    &err,
+                    format!("action.identifier={identifier}"),
+                );
+                return false;
+            }
+        };
+
+        let accepted: AcceptedModule = match serde_json::from_slice(&entry) {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                errors::record(
+                    "flow_storage",
+                    "action.module_digest.decode",
+                    &err,
+                    format!("action.identifier={identifier}"),
+                );
+                return false;
+            }
+        };
+
+        accepted.is_current(
+            digest,
+            validation::epoch_millis_now(),
+            self.force_refresh_interval,
+        )
+    }
+
+    /// Stores `digest` as the module Sagittarius just accepted for
+    /// `identifier`. A failure only costs an extra update next time.
+    pub(super) async fn record(&self, identifier: &str, digest: String) {
+        let Some(kv) = &self.kv else {
+            return;
+        };
+
+        let accepted = AcceptedModule {
+            digest,
+            accepted_at_unix_ms: validation::epoch_millis_now(),
+        };
+        let payload = match serde_json::to_vec(&accepted) {
+            Ok(payload) => payload,
+            Err(err) => {
+                errors::record(
+                    "flow_storage",
+                    "action.module_digest.encode",
+                    &err,
+                    format!("action.identifier={identifier}"),
+                );
+                return;
+            }
+        };
+
+        if let Err(err) = kv.put(module_key(identifier), payload.into()).await {
+            errors::record(
+                "flow_storage",
+                "action.module_digest.put",
+                &err,
+                format!("action.identifier={identifier}"),
+            );
+        }
+    }
+}
+
+impl AcceptedModule {
+    fn is_current(&self, digest: &str, now_unix_ms: i64, refresh: Option<Duration>) -> bool {
+        if self.digest != digest {
+            return false;
+        }
+
+        match refresh {
+            Some(refresh) => {
+                let age_ms = now_unix_ms.saturating_sub(self.accepted_at_unix_ms);
+                u128::try_from(age_ms).is_ok_and(|age_ms| age_ms < refresh.as_millis())
+            }
+            None => true,
+        }
+    }
+}
+
+/// SHA-256 over everything a module update sends: the module and the
+/// definition sources sent alongside it. Hashed from the JSON form rather
+/// than the protobuf encoding, since the latter writes map fields in
+/// arbitrary order.
+pub(super) fn module_digest(
+    module: &tucana::shared::Module,
+    available_definition_sources: &[String],
+) -> Result<String, serde_json::Error> {
+    let canonical =
+        serde_json::to_vec(&(serde_json::to_value(module)?, available_definition_sources))?;
+    let digest = Sha256::digest(&canonical);
+    Ok(digest.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_changes_with_module_content_only() {
+        let module = tucana::shared::Module {
+            identifier: "send-email".to_string(),
+            ..Default::default()
+        };
+        let sources = vec!["send-email".to_string()];
+
+        let digest = module_digest(&module, &sources).expect("digest");
+        assert_eq!(
+            digest,
+            module_digest(&module.clone(), &sources).expect("digest")
+        );
+
+        let changed = tucana::shared::Module {
+            identifier: "send-sms".to_string(),
+            ..Default::default()
+        };
+        assert_ne!(digest, module_digest(&changed, &sources).expect("digest"));
+    }
+
+    #[test]
+    fn accepted_module_expires_after_refresh_interval() {
+        let accepted = AcceptedModule {
+            digest: "abc".to_string(),
+            accepted_at_unix_ms: 1_000,
+        };
+        let refresh = Some(Duration::from_secs(60));
+
+        assert!(accepted.is_current("abc", 2_000, refresh));
+        assert!(!accepted.is_current("def", 2_000, refresh));
+        assert!(!accepted.is_current("abc", 61_000, refresh));
+        assert!(accepted.is_current("abc", 61_000, None));
+    }
+}
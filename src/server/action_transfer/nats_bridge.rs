@@ -2,9 +2,15 @@
 //! gRPC stream: looks up matching flows for incoming events, forwards
 //! execution requests to the action, and publishes results back to NATS.
 
-use async_nats::{Subject, Subscriber};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_nats::{RequestError, RequestErrorKind, StatusCode, Subject, Subscriber};
 use futures::StreamExt;
 use prost::Message;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tucana::{
     aquila::{
         ActionEvent, ActionExecutionRequest, ActionExecutionResponse, ActionFlowExecutionRequest,
@@ -13,18 +19,135 @@ use tucana::{
         action_transfer_response,
     },
     shared::{
-        Error, ExecutionFlow, ExecutionResult, Flows, ValidationFlow, Value, execution_result,
+        Error, ExecutionFlow, ExecutionResult, Flows, NodeExecutionResult, ValidationFlow, Value,
+        execution_result, node_execution_result,
     },
 };
 
 use crate::{
-    flow,
+    configuration::config::OutputValidationMode,
+    flow::{
+        self,
+        dead_letters::{DeadLetter, DeadLetters},
+        dispatch::ExecutionDispatcher,
+        executions::{ExecutionOrigin, ExecutionRegistry, ExecutionState, TrackedExecution},
+    },
+    server::Drain,
     telemetry::{errors, metrics},
-    validation,
+    validation::{self, ValidatedResult},
 };
 
 use super::flow_execution_registry::ActionFlowExecutionRegistry;
-use super::pending_replies::{PendingReplyStore, pending_reply_keys};
+use super::{
+    connections::{ActionConnection, CloseReason},
+    event_options::{EventOptions, MatchCriteria},
+    event_results::{FlowOutcome, aggregated_response},
+    pending_replies::{PendingReply, pending_reply_keys},
+};
+
+/// What handling an event needs besides the event itself, shared by every
+/// event on one stream.
+#[derive(Clone)]
+pub(super) struct EventDispatch {
+    pub(super) kv: async_nats::jetstream::kv::Store,
+    pub(super) client: async_nats::Client,
+    /// Limits the execution requests the stream's events have outstanding.
+    pub(super) permits: Arc<Semaphore>,
+    admission: EventAdmission,
+    pub(super) dead_letters: DeadLetters,
+    pub(super) executions: ExecutionRegistry,
+    /// How event results are checked against their flow's output schema.
+    pub(super) output_validation: OutputValidationMode,
+}
+
+impl EventDispatch {
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn new(
+        kv: async_nats::jetstream::kv::Store,
+        client: async_nats::Client,
+        fan_out_limit: usize,
+        max_in_flight: usize,
+        max_queued: usize,
+        dead_letters: DeadLetters,
+        executions: ExecutionRegistry,
+        output_validation: OutputValidationMode,
+    ) -> Self {
+        Self {
+            kv,
+            client,
+            permits: Arc::new(Semaphore::new(fan_out_limit)),
+            admission: EventAdmission::new(max_in_flight, max_queued),
+            dead_letters,
+            executions,
+            output_validation,
+        }
+    }
+
+    pub(super) fn admit_event(&self) -> Option<OwnedSemaphorePermit> {
+        self.admission.admit()
+    }
+
+    pub(super) async fn start_event(&self, admitted: OwnedSemaphorePermit) -> Option<EventSlot> {
+        self.admission.start(admitted).await
+    }
+
+    pub(super) async fn events_settled(&self) {
+        self.admission.settled().await
+    }
+}
+
+/// How many of a stream's events are handled at once, and how many more may
+/// wait for their turn.
+#[derive(Clone)]
+struct EventAdmission {
+    /// Limits how many events are handled at once.
+    handling: Arc<Semaphore>,
+    /// Limits how many events are handled or waiting for a slot in `handling`.
+    admitted: Arc<Semaphore>,
+    max_admitted: usize,
+}
+
+/// An event's slot among those its stream handles at once, released when
+/// dropped.
+pub(super) struct EventSlot {
+    _admitted: OwnedSemaphorePermit,
+    _handling: OwnedSemaphorePermit,
+}
+
+impl EventAdmission {
+    fn new(max_in_flight: usize, max_queued: usize) -> Self {
+        let max_admitted = max_in_flight
+            .saturating_add(max_queued)
+            .min(Semaphore::MAX_PERMITS);
+        Self {
+            handling: Arc::new(Semaphore::new(max_in_flight)),
+            admitted: Arc::new(Semaphore::new(max_admitted)),
+            max_admitted,
+        }
+    }
+
+    /// Admits one more event, held until the returned permit is dropped, or
+    /// `None` while as many events as may be handled and queued at once are
+    /// already admitted.
+    fn admit(&self) -> Option<OwnedSemaphorePermit> {
+        self.admitted.clone().try_acquire_owned().ok()
+    }
+
+    /// Waits until the `admitted` event may be handled.
+    async fn start(&self, admitted: OwnedSemaphorePermit) -> Option<EventSlot> {
+        let handling = self.handling.clone().acquire_owned().await.ok()?;
+        Some(EventSlot {
+            _admitted: admitted,
+            _handling: handling,
+        })
+    }
+
+    /// Resolves once no event is being handled or waiting anymore.
+    async fn settled(&self) {
+        let all = u32::try_from(self.max_admitted).unwrap_or(u32::MAX);
+        let _ = self.admitted.acquire_many(all).await;
+    }
+}
 
 /// Wraps the underlying NATS/KV error from a failed flow lookup so callers
 /// get a stable, human-readable message while [`std::error::Error::source`]
@@ -178,13 +301,230 @@ pub(super) async fn send_stream_error(
 /// Each match is dispatched as an independent NATS request-reply exchange on
 /// its own `execution.<uuid>` subject, so one flow failing to find a runtime
 /// doesn't block the others, and a runtime's response arrives correlated to
-/// exactly the flow that triggered it.
+/// exactly the flow that triggered it. The requests run concurrently, each
+/// holding one of the stream's permits until its reply arrives. The
+/// replies are dropped unless the event asked for its results, which are
+/// then sent to the action as one response. An event that didn't ask gives
+/// up its `slot` once every request is published, one that did once its
+/// results are sent.
+///
+/// Returns whether the event was dispatched: its flows were looked up and
+/// every request reached a runtime, even if one didn't answer in time.
 pub(super) async fn handle_event(
     action_identifier: &str,
     event: ActionEvent,
-    kv: async_nats::jetstream::kv::Store,
-    client: async_nats::Client,
+    options: EventOptions,
+    dispatch: EventDispatch,
+    slot: EventSlot,
+    tx: tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+) -> bool {
+    let Some(flows) = event_flows(
+        action_identifier,
+        &event,
+        &options.criteria,
+        dispatch.kv.clone(),
+    )
+    .await
+    else {
+        if let Some(request) = options.results {
+            send_event_results(action_identifier, &tx, &request.correlation_id, Vec::new()).await;
+        }
+        return false;
+    };
+    let Some(request) = options.results else {
+        // Every request shares the slot, so it's released once the last is published.
+        let slot = Arc::new(slot);
+        let flows: Vec<_> = flows
+            .into_iter()
+            .map(|flow| (flow, Arc::clone(&slot)))
+            .collect();
+        drop(slot);
+        let replies = fan_out(flows, &dispatch.permits, None, |(flow, slot)| {
+            let execution_id = uuid::Uuid::new_v4().to_string();
+            let (event, dispatch) = (&event, &dispatch);
+            async move {
+                request_event_execution(
+                    action_identifier,
+                    event,
+                    None,
+                    Some(slot),
+                    flow,
+                    &execution_id,
+                    dispatch,
+                )
+                .await
+            }
+        })
+        .await;
+        return replies.iter().all(reached_runtime);
+    };
+
+    let deadline = tokio::time::Instant::now() + request.timeout;
+    let flows: Vec<(String, ValidationFlow)> = flows
+        .into_iter()
+        .map(|flow| (uuid::Uuid::new_v4().to_string(), flow))
+        .collect();
+    let planned: Vec<_> = flows
+        .iter()
+        .map(|(execution_id, flow)| {
+            (
+                flow.flow_id,
+                execution_id.clone(),
+                flow.output_schema.clone(),
+            )
+        })
+        .collect();
+    // A flow still waiting for a permit at the deadline isn't requested at all.
+    let replies = fan_out(
+        flows,
+        &dispatch.permits,
+        Some(deadline),
+        |(execution_id, flow)| {
+            let (event, dispatch, correlation_id) = (&event, &dispatch, &request.correlation_id);
+            async move {
+                request_event_execution(
+                    action_identifier,
+                    event,
+                    Some(correlation_id),
+                    None,
+                    flow,
+                    &execution_id,
+                    dispatch,
+                )
+                .await
+            }
+        },
+    )
+    .await;
+    // Requests given up on at the deadline never got to finish theirs.
+    for (_, execution_id, _) in &planned {
+        dispatch
+            .executions
+            .finish(ExecutionOrigin::Event, execution_id)
+            .await;
+    }
+    let dispatched = replies.iter().all(reached_runtime);
+    let outcomes: Vec<_> = planned
+        .into_iter()
+        .zip(replies)
+        .map(|((flow_id, execution_id, output_schema), reply)| {
+            let outcome =
+                event_flow_outcome(action_identifier, flow_id, &execution_id, reply, |result| {
+                    validation::apply_output_validation(
+                        dispatch.output_validation,
+                        output_schema.as_ref(),
+                        result,
+                    )
+                });
+            (flow_id, execution_id, outcome)
+        })
+        .collect();
+
+    send_event_results(action_identifier, &tx, &request.correlation_id, outcomes).await;
+    dispatched
+}
+
+async fn send_event_results(
+    action_identifier: &str,
+    tx: &tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+    correlation_id: &str,
+    outcomes: Vec<(i64, String, FlowOutcome)>,
 ) {
+    log::debug!(
+        "Returning event results action={} correlation_id={} flow_count={}",
+        action_identifier,
+        correlation_id,
+        outcomes.len()
+    );
+    if tx
+        .send(Ok(aggregated_response(correlation_id, outcomes)))
+        .await
+        .is_err()
+    {
+        log::debug!("Action transfer response stream closed before event results could be sent");
+    }
+}
+
+/// Whether a flow's request was published to a runtime, answered or not. A
+/// flow given up on at the deadline counts as reached, since it may be
+/// running by now, and so does one the action cancelled.
+fn reached_runtime(reply: &Option<EventReply>) -> bool {
+    !matches!(reply, Some(EventReply::Failed(kind)) if *kind != RequestErrorKind::TimedOut)
+}
+
+/// Runs `request` for each of `items` concurrently, with no more than
+/// `permits` allows outstanding at once. With a `deadline`, an item still
+/// waiting for a permit by then isn't requested at all and one still
+/// running is given up on; either ends as `None`. Outcomes are returned in
+/// the order of `items`.
+async fn fan_out<I, T, F, Fut>(
+    items: Vec<I>,
+    permits: &Semaphore,
+    deadline: Option<tokio::time::Instant>,
+    request: F,
+) -> Vec<Option<T>>
+where
+    F: Fn(I) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let request = &request;
+    futures::future::join_all(items.into_iter().map(|item| async move {
+        let requested = async {
+            let _permit = permits.acquire().await.ok()?;
+            Some(request(item).await)
+        };
+        match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, requested)
+                .await
+                .ok()
+                .flatten(),
+            None => requested.await,
+        }
+    }))
+    .await
+}
+
+/// What a flow's reply, or the lack of one by the deadline, means for the
+/// event. A result it answered with is passed through `validate`.
+fn event_flow_outcome(
+    action_identifier: &str,
+    flow_id: i64,
+    execution_id: &str,
+    reply: Option<EventReply>,
+    validate: impl FnOnce(ExecutionResult) -> ValidatedResult,
+) -> FlowOutcome {
+    match reply {
+        None => FlowOutcome::TimedOut,
+        Some(EventReply::Cancelled) => FlowOutcome::Cancelled,
+        Some(EventReply::Failed(_)) => {
+            FlowOutcome::Failed("failed to request execution".to_string())
+        }
+        Some(EventReply::Answered(reply)) => match ExecutionResult::decode(reply.payload) {
+            Ok(result) => FlowOutcome::Finished(Box::new(validate(result))),
+            Err(err) => {
+                errors::record(
+                    "protocol",
+                    "action.event.decode_result",
+                    &err,
+                    format!(
+                        "action.identifier={} flow_id={} execution_id={}",
+                        action_identifier, flow_id, execution_id
+                    ),
+                );
+                FlowOutcome::Failed("failed to decode execution result".to_string())
+            }
+        },
+    }
+}
+
+/// Every flow `event` triggers: those of its type and project whose
+/// settings meet `criteria`. A failed lookup is recorded and yields `None`.
+async fn event_flows(
+    action_identifier: &str,
+    event: &ActionEvent,
+    criteria: &MatchCriteria,
+    kv: async_nats::jetstream::kv::Store,
+) -> Option<Vec<ValidationFlow>> {
     let pattern = format!("{}.*.{}.*", event.event_type, event.project_id);
     log::debug!(
         "Handling action event event_type={} project_id={}",
@@ -204,46 +544,161 @@ pub(super) async fn handle_event(
                     action_identifier, event.event_type, event.project_id, pattern
                 ),
             );
-            return;
+            return None;
         }
     };
 
-    let matched_count = flows.flows.len();
+    let candidate_count = flows.flows.len();
+    let flows: Vec<ValidationFlow> = flows
+        .flows
+        .into_iter()
+        .filter(|flow| criteria.matches(flow))
+        .collect();
     log::info!(
-        "Matched flows for action event event_type={} project_id={} flow_count={}",
+        "Matched flows for action event event_type={} project_id={} flow_count={} filtered_count={}",
         event.event_type,
         event.project_id,
-        matched_count
+        flows.len(),
+        candidate_count - flows.len()
     );
-    for flow in flows.flows {
-        let uuid = uuid::Uuid::new_v4().to_string();
-        let flow_id = flow.flow_id;
-        let execution_flow: ExecutionFlow = convert_validation_flow(flow, event.payload.clone());
-        let bytes = execution_flow.encode_to_vec();
-        let topic = format!("execution.{}", uuid);
-
-        log::info!(
-            "Requesting execution flow_id={} execution_id={} event_type={} project_id={}",
-            flow_id,
-            uuid,
-            event.event_type,
-            event.project_id
-        );
+    Some(flows)
+}
+
+/// How a flow's execution request for an event ended.
+enum EventReply {
+    Answered(async_nats::Message),
+    /// The action cancelled the execution before it answered.
+    Cancelled,
+    Failed(RequestErrorKind),
+}
+
+/// Requests `flow`'s execution for `event` and waits for the reply, or
+/// records why there is none. An execution no runtime took is dead-lettered;
+/// one that timed out may still be running, so it isn't. Until the reply
+/// arrives, the action can cancel the execution by its id or by the event's
+/// `correlation_id`. A `slot` is released as soon as the request is published.
+async fn request_event_execution(
+    action_identifier: &str,
+    event: &ActionEvent,
+    correlation_id: Option<&str>,
+    slot: Option<Arc<EventSlot>>,
+    flow: ValidationFlow,
+    execution_id: &str,
+    dispatch: &EventDispatch,
+) -> EventReply {
+    let flow_id = flow.flow_id;
+    let execution_flow: ExecutionFlow = convert_validation_flow(flow, event.payload.clone());
+    let bytes = execution_flow.encode_to_vec();
+    let topic = format!("execution.{}", execution_id);
+
+    log::info!(
+        "Requesting execution flow_id={} execution_id={} event_type={} project_id={}",
+        flow_id,
+        execution_id,
+        event.event_type,
+        event.project_id
+    );
+
+    let cancelled = dispatch
+        .executions
+        .track_cancellable(
+            execution_id,
+            TrackedExecution {
+                origin: ExecutionOrigin::Event,
+                flow_id: Some(flow_id),
+                project_id: Some(event.project_id),
+                action_identifier: Some(action_identifier.to_string()),
+            },
+            ExecutionState::Running,
+            correlation_id.map(str::to_string),
+        )
+        .await;
+    let request = async {
+        match slot {
+            Some(slot) => request_releasing(&dispatch.client, topic.clone(), bytes, slot).await,
+            None => dispatch.client.request(topic.clone(), bytes.into()).await,
+        }
+    };
+    let reply = tokio::select! {
+        reply = request => Some(reply),
+        _ = cancelled.notified() => None,
+    };
+    dispatch
+        .executions
+        .finish(ExecutionOrigin::Event, execution_id)
+        .await;
 
-        if let Err(err) = client.request(topic.clone(), bytes.into()).await {
+    match reply {
+        None => {
+            log::info!(
+                "Stopped waiting on cancelled event execution action={} flow_id={} execution_id={}",
+                action_identifier,
+                flow_id,
+                execution_id
+            );
+            EventReply::Cancelled
+        }
+        Some(Ok(reply)) => EventReply::Answered(reply),
+        Some(Err(err)) => {
             errors::record(
                 "messaging",
                 "action.event.request_execution",
                 &err,
                 format!(
                     "action.identifier={} flow_id={} execution_id={} topic={}",
-                    action_identifier, flow_id, uuid, topic
+                    action_identifier, flow_id, execution_id, topic
                 ),
             );
+            if err.kind() != RequestErrorKind::TimedOut {
+                dispatch
+                    .dead_letters
+                    .record(DeadLetter {
+                        origin: ExecutionOrigin::Event,
+                        execution_id,
+                        execution_flow: &execution_flow,
+                        action_identifier: Some(action_identifier),
+                        error: err.to_string(),
+                    })
+                    .await;
+            }
+            EventReply::Failed(err.kind())
         }
     }
 }
 
+/// Requests like [`async_nats::Client::request`], but drops `published` as
+/// soon as the request is sent rather than once its reply arrived.
+async fn request_releasing<T>(
+    client: &async_nats::Client,
+    subject: String,
+    payload: Vec<u8>,
+    published: T,
+) -> Result<async_nats::Message, RequestError> {
+    let inbox = client.new_inbox();
+    let mut replies = client.subscribe(inbox.clone()).await?;
+    client
+        .publish_with_reply(subject, inbox, payload.into())
+        .await?;
+    drop(published);
+
+    let reply = match client.timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, replies.next())
+            .await
+            .map_err(|err| RequestError::with_source(RequestErrorKind::TimedOut, err))?,
+        None => replies.next().await,
+    };
+    match reply {
+        Some(reply) if reply.status == Some(StatusCode::NO_RESPONDERS) => Err(
+            RequestError::with_source(RequestErrorKind::NoResponders, "no responders"),
+        ),
+        Some(reply) => Ok(reply),
+        None => Err(RequestError::with_source(
+            RequestErrorKind::Other,
+            "reply subscription closed",
+        )),
+    }
+}
+
 /// Validates and dispatches a flow execution an action asked Aquila to run
 /// onto the NATS execution bus, registering `tx` under the execution id so
 /// the result (delivered separately once a runtime reports it, see
@@ -253,8 +708,9 @@ pub(super) async fn handle_flow_execution(
     action_identifier: &str,
     request: ActionFlowExecutionRequest,
     kv: async_nats::jetstream::kv::Store,
-    nats_client: async_nats::Client,
+    dispatcher: ExecutionDispatcher,
     registry: ActionFlowExecutionRegistry,
+    dead_letters: DeadLetters,
     tx: tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
 ) {
     let execution_id = if request.execution_identifier.is_empty() {
@@ -320,7 +776,15 @@ pub(super) async fn handle_flow_execution(
         project_id: validation_flow.project_id,
     };
 
-    registry.insert(execution_id.clone(), tx.clone()).await;
+    registry
+        .insert(
+            execution_id.clone(),
+            action_identifier.to_string(),
+            flow_id,
+            execution_flow.project_id,
+            tx.clone(),
+        )
+        .await;
 
     log::debug!(
         "Publishing action flow execution request to NATS action={} execution_id={} flow_id={}",
@@ -329,7 +793,7 @@ pub(super) async fn handle_flow_execution(
         flow_id
     );
 
-    if let Err(err) = flow::dispatch_execution(&nats_client, &execution_id, &execution_flow).await {
+    if let Err(err) = dispatcher.dispatch(&execution_id, &execution_flow).await {
         errors::record(
             "messaging",
             "action.flow_execution.dispatch",
@@ -339,6 +803,15 @@ pub(super) async fn handle_flow_execution(
                 action_identifier, execution_id, flow_id
             ),
         );
+        dead_letters
+            .record(DeadLetter {
+                origin: ExecutionOrigin::ActionFlow,
+                execution_id: &execution_id,
+                execution_flow: &execution_flow,
+                action_identifier: Some(action_identifier),
+                error: err.to_string(),
+            })
+            .await;
         registry.take(&execution_id).await;
         send_flow_execution_failure(
             &tx,
@@ -346,6 +819,59 @@ pub(super) async fn handle_flow_execution(
             "failed to dispatch execution".to_string(),
         )
         .await;
+        return;
+    }
+    registry.dispatched(&execution_id).await;
+
+    tokio::spawn(expire_flow_execution(
+        action_identifier.to_string(),
+        execution_id,
+        registry,
+    ));
+}
+
+/// Waits out a flow execution's deadline and, if no runtime has reported
+/// its result by then, answers the action with a timeout in its place.
+async fn expire_flow_execution(
+    action_identifier: String,
+    execution_id: String,
+    registry: ActionFlowExecutionRegistry,
+) {
+    let timeout = registry.timeout();
+    tokio::time::sleep(timeout).await;
+
+    let Some(tx) = registry.expire(&execution_id, Instant::now()).await else {
+        return;
+    };
+
+    metrics::action_failure(&action_identifier, "flow_execution_timeout");
+    log::warn!(
+        "Action flow execution timed out action={} execution_id={} timeout={:?}",
+        action_identifier,
+        execution_id,
+        timeout
+    );
+
+    let resp = ActionTransferResponse {
+        data: Some(action_transfer_response::Data::FlowExecutionResponse(
+            ActionFlowExecutionResponse {
+                execution_identifier: execution_id,
+                result: Some(action_flow_execution_response::Result::Failure(Error {
+                    code: "A-FLOW-EXECUTION-000004".to_string(),
+                    category: "DeadlineExceeded".to_string(),
+                    message: format!("no result arrived within {}s", timeout.as_secs()),
+                    timestamp: validation::epoch_millis_now(),
+                    version: crate::version::runtime_version().to_string(),
+                    ..Default::default()
+                })),
+            },
+        )),
+    };
+
+    if tx.send(Ok(resp)).await.is_err() {
+        log::debug!(
+            "Action transfer response stream closed before flow execution timeout could be sent"
+        );
     }
 }
 
@@ -380,6 +906,65 @@ async fn send_flow_execution_failure(
     }
 }
 
+/// Answers a flow execution the action that asked for it cancelled.
+pub(super) async fn send_flow_execution_cancelled(
+    tx: &tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+    execution_identifier: String,
+) {
+    let resp = ActionTransferResponse {
+        data: Some(action_transfer_response::Data::FlowExecutionResponse(
+            ActionFlowExecutionResponse {
+                execution_identifier,
+                result: Some(action_flow_execution_response::Result::Failure(Error {
+                    code: "A-FLOW-EXECUTION-000003".to_string(),
+                    category: "Cancelled".to_string(),
+                    message: "execution was cancelled".to_string(),
+                    timestamp: validation::epoch_millis_now(),
+                    version: crate::version::runtime_version().to_string(),
+                    ..Default::default()
+                })),
+            },
+        )),
+    };
+
+    if tx.send(Ok(resp)).await.is_err() {
+        log::debug!(
+            "Action transfer response stream closed before flow execution cancellation could be sent"
+        );
+    }
+}
+
+/// Turns down an action's flow execution request Aquila can't take on right
+/// now (e.g. while draining for shutdown), as a retryable failure rather than
+/// the `InvalidArgument` [`send_flow_execution_failure`] reports.
+pub(super) async fn reject_flow_execution(
+    tx: &tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
+    execution_identifier: String,
+    message: String,
+) {
+    let resp = ActionTransferResponse {
+        data: Some(action_transfer_response::Data::FlowExecutionResponse(
+            ActionFlowExecutionResponse {
+                execution_identifier,
+                result: Some(action_flow_execution_response::Result::Failure(Error {
+                    code: "A-FLOW-EXECUTION-000002".to_string(),
+                    category: "Unavailable".to_string(),
+                    message,
+                    timestamp: validation::epoch_millis_now(),
+                    version: crate::version::runtime_version().to_string(),
+                    ..Default::default()
+                })),
+            },
+        )),
+    };
+
+    if tx.send(Ok(resp)).await.is_err() {
+        log::debug!(
+            "Action transfer response stream closed before flow execution rejection could be sent"
+        );
+    }
+}
+
 /// A sub flow is the value of a function parameter that isn't a literal
 /// (`shared.Value`) but the result of executing another flow. When the
 /// action needs that value, it sends `ActionSubFlowExecutionRequest` back to
@@ -393,6 +978,7 @@ pub(super) async fn handle_sub_flow_execution(
     action_identifier: &str,
     request: ActionSubFlowExecutionRequest,
     nats_client: async_nats::Client,
+    executions: ExecutionRegistry,
     tx: tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
 ) {
     let execution_id = request.execution_identifier.clone();
@@ -406,7 +992,24 @@ pub(super) async fn handle_sub_flow_execution(
         topic
     );
 
-    let reply = match nats_client.request(topic.clone(), bytes.into()).await {
+    executions
+        .track(
+            &execution_id,
+            TrackedExecution {
+                origin: ExecutionOrigin::SubFlow,
+                flow_id: None,
+                project_id: None,
+                action_identifier: Some(action_identifier.to_string()),
+            },
+            ExecutionState::Running,
+        )
+        .await;
+    let reply = nats_client.request(topic.clone(), bytes.into()).await;
+    executions
+        .finish(ExecutionOrigin::SubFlow, &execution_id)
+        .await;
+
+    let reply = match reply {
         Ok(reply) => reply,
         Err(err) => {
             errors::record(
@@ -516,12 +1119,14 @@ pub(super) async fn handle_result(
     action_identifier: &str,
     execution_result: ActionExecutionResponse,
     client: async_nats::Client,
-    pending_replies: PendingReplyStore,
+    connection: &ActionConnection,
 ) {
     let execution_id = execution_result.execution_identifier.clone();
     metrics::action_result(action_identifier, action_result_outcome(&execution_result));
 
-    let Some(pending_reply) = pending_replies.remove(&execution_id).await else {
+    let pending_reply = connection.pending_replies.remove(&execution_id).await;
+    report_in_flight(action_identifier, connection).await;
+    let Some(pending_reply) = pending_reply else {
         metrics::action_failure(action_identifier, "result_unmatched");
         errors::record_message(
             "protocol",
@@ -571,20 +1176,408 @@ pub(super) async fn handle_result(
     }
 }
 
+/// Waits out an execution's deadline and, if the action still hasn't
+/// answered by then, replies to the runtime in its place with a timeout
+/// error so the runtime gets a reason instead of a bare NATS request timeout.
+async fn expire_pending_reply(
+    action_identifier: String,
+    execution_id: String,
+    timeout: Duration,
+    client: async_nats::Client,
+    connection: ActionConnection,
+) {
+    tokio::time::sleep(timeout).await;
+
+    let Some(pending_reply) = connection
+        .pending_replies
+        .expire(&execution_id, Instant::now())
+        .await
+    else {
+        return;
+    };
+    report_in_flight(&action_identifier, &connection).await;
+
+    metrics::action_result(&action_identifier, "timeout");
+    metrics::action_execution_duration(
+        &action_identifier,
+        pending_reply.started_at.elapsed().as_secs_f64(),
+    );
+    log::warn!(
+        "Action execution timed out action={} execution_id={} timeout={:?}",
+        action_identifier,
+        execution_id,
+        timeout
+    );
+
+    let response = execution_failure_response(
+        execution_id,
+        &pending_reply,
+        "A-EXECUTION-000001",
+        "DeadlineExceeded",
+        format!(
+            "action {} did not answer within {}s",
+            action_identifier,
+            timeout.as_secs()
+        ),
+    );
+    publish_failure_response(&action_identifier, &client, &pending_reply, response).await;
+}
+
+/// Answers an execution the action was asked to run, and cancelled instead,
+/// with a cancelled error. Returns whether the execution was still pending
+/// on `connection`.
+pub(super) async fn cancel_pending_reply(
+    action_identifier: &str,
+    execution_id: &str,
+    client: &async_nats::Client,
+    connection: &ActionConnection,
+) -> bool {
+    let Some(pending_reply) = connection.pending_replies.remove(execution_id).await else {
+        return false;
+    };
+    report_in_flight(action_identifier, connection).await;
+
+    metrics::action_result(action_identifier, "cancelled");
+    metrics::action_execution_duration(
+        action_identifier,
+        pending_reply.started_at.elapsed().as_secs_f64(),
+    );
+    let response = execution_failure_response(
+        execution_id.to_string(),
+        &pending_reply,
+        "A-EXECUTION-000004",
+        "Cancelled",
+        format!("action {action_identifier} cancelled the execution"),
+    );
+    publish_failure_response(action_identifier, client, &pending_reply, response).await;
+    true
+}
+
+/// Answers every execution still waiting on a connection that has gone
+/// away, so the runtime learns right away instead of at its deadline.
+/// Another replica can't take these over: the action may already have
+/// started working on them.
+pub(super) async fn fail_pending_replies(
+    action_identifier: &str,
+    client: &async_nats::Client,
+    connection: &ActionConnection,
+) {
+    let instance_id = connection.instance_id.as_str();
+    let pending = connection.pending_replies.take_all().await;
+    report_in_flight(action_identifier, connection).await;
+    if pending.is_empty() {
+        return;
+    }
+
+    log::warn!(
+        "Failing executions left pending by a closed action connection action={} instance_id={} count={}",
+        action_identifier,
+        instance_id,
+        pending.len()
+    );
+
+    let (outcome, code, message) = match connection.close_reason() {
+        Some(CloseReason::Lost) => (
+            "connection_lost",
+            "A-EXECUTION-000003",
+            format!("connection to action {action_identifier} was lost before it answered"),
+        ),
+        _ => (
+            "disconnected",
+            "A-EXECUTION-000002",
+            format!("action {action_identifier} disconnected before answering"),
+        ),
+    };
+
+    for (execution_id, pending_reply) in pending {
+        metrics::action_result(action_identifier, outcome);
+        let response = execution_failure_response(
+            execution_id,
+            &pending_reply,
+            code,
+            "Unavailable",
+            message.clone(),
+        );
+        publish_failure_response(action_identifier, client, &pending_reply, response).await;
+    }
+}
+
+/// How long a saturated connection waits before retrying to rejoin its queue
+/// group after NATS refused the subscription.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// Subscribes to execution requests for `action_identifier`. Every replica
+/// of an action joins the same queue group, so NATS hands each execution
+/// request to exactly one of them.
+pub(super) async fn subscribe_executions(
+    client: &async_nats::Client,
+    action_identifier: &str,
+) -> Result<Subscriber, async_nats::SubscribeError> {
+    client
+        .queue_subscribe(
+            format!("action.{}.*", action_identifier),
+            action_identifier.to_string(),
+        )
+        .await
+}
+
+/// Unsubscribes `sub` from its queue group and hands every request it had
+/// already buffered back to the group, so other replicas take them. Returns
+/// whether it left the group.
+async fn leave_queue_group(
+    action_identifier: &str,
+    instance_id: &str,
+    sub: &mut Subscriber,
+    client: &async_nats::Client,
+) -> bool {
+    if let Err(err) = sub.unsubscribe().await {
+        errors::record(
+            "messaging",
+            "action.unsubscribe",
+            &err,
+            format!("action.identifier={action_identifier} instance_id={instance_id}"),
+        );
+        return false;
+    }
+
+    while let Some(msg) = sub.next().await {
+        requeue(action_identifier, client, msg).await;
+    }
+    true
+}
+
+/// Records how many executions `connection` is currently waiting on.
+async fn report_in_flight(action_identifier: &str, connection: &ActionConnection) {
+    metrics::action_in_flight(
+        action_identifier,
+        connection.pending_replies.unreported_change().await,
+    );
+}
+
+/// The error result Aquila answers an execution with when the action can't.
+fn execution_failure_response(
+    execution_identifier: String,
+    pending_reply: &PendingReply,
+    code: &str,
+    category: &str,
+    message: String,
+) -> ActionExecutionResponse {
+    let now = validation::epoch_millis_now();
+
+    ActionExecutionResponse {
+        execution_identifier,
+        node_result: Some(NodeExecutionResult {
+            started_at: now,
+            finished_at: now,
+            id: Some(node_execution_result::Id::FunctionIdentifier(
+                pending_reply.function_identifier.clone(),
+            )),
+            result: Some(node_execution_result::Result::Error(Error {
+                code: code.to_string(),
+                category: category.to_string(),
+                message,
+                timestamp: now,
+                version: crate::version::runtime_version().to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }),
+    }
+}
+
+async fn publish_failure_response(
+    action_identifier: &str,
+    client: &async_nats::Client,
+    pending_reply: &PendingReply,
+    response: ActionExecutionResponse,
+) {
+    if let Err(err) = client
+        .publish(
+            pending_reply.reply_subject.clone(),
+            response.encode_to_vec().into(),
+        )
+        .await
+    {
+        metrics::action_failure(action_identifier, "result_publish");
+        errors::record(
+            "messaging",
+            "action.execution.failure.publish",
+            &err,
+            format!(
+                "action.identifier={} execution_id={} reply_subject={}",
+                action_identifier, response.execution_identifier, pending_reply.reply_subject
+            ),
+        );
+    }
+}
+
+/// Hands an execution request this connection received but never passed
+/// on back to NATS, with its original reply subject, so another replica in
+/// the queue group can pick it up.
+async fn requeue(action_identifier: &str, client: &async_nats::Client, msg: async_nats::Message) {
+    let Some(reply_subject) = msg.reply else {
+        return;
+    };
+
+    log::debug!(
+        "Requeueing undelivered execution request action={} subject={}",
+        action_identifier,
+        msg.subject
+    );
+    let published = match msg.headers {
+        Some(headers) => {
+            client
+                .publish_with_reply_and_headers(
+                    msg.subject.clone(),
+                    reply_subject,
+                    headers,
+                    msg.payload,
+                )
+                .await
+        }
+        None => {
+            client
+                .publish_with_reply(msg.subject.clone(), reply_subject, msg.payload)
+                .await
+        }
+    };
+
+    if let Err(err) = published {
+        metrics::action_failure(action_identifier, "execution_requeue");
+        errors::record(
+            "messaging",
+            "action.execution.requeue",
+            &err,
+            format!(
+                "action.identifier={} subject={}",
+                action_identifier, msg.subject
+            ),
+        );
+    }
+}
+
+/// Per-action bounds on the executions forwarded to a connection, resolved
+/// at logon from the service configuration and `action_execution` defaults.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct ExecutionLimits {
+    /// How long the action gets to answer each request.
+    pub(super) timeout: Duration,
+    /// How many requests may be awaiting a result at once; `None` is unlimited.
+    pub(super) max_in_flight: Option<usize>,
+}
+
 /// Forwards NATS execution requests to the connected action via gRPC and stores reply subjects.
 ///
-/// Runs for as long as the action's `action.<identifier>.*` subscription is
-/// alive, which is the same lifetime as the action's logon; a fresh task is
-/// spawned per logon rather than reused across reconnects.
+/// Runs from the action's logon until its connection closes; a fresh task
+/// is spawned per logon rather than reused across reconnects. The
+/// subscription is part of a queue group shared by every replica of the
+/// action, so each request reaches only one of them. Once `drain` starts,
+/// the subscription is dropped so no new executions are taken on, and only
+/// requests NATS had already delivered are still forwarded.
+///
+/// Every forwarded request gets `limits.timeout` to be answered; see
+/// [`expire_pending_reply`]. While `limits.max_in_flight` requests are
+/// awaiting a result, the connection leaves the queue group and hands the
+/// requests it had buffered back to it, so other replicas take them. It
+/// rejoins once the action answers one. When the connection closes,
+/// requests that were delivered here but never forwarded go back to the
+/// queue group. Forwarded
+/// ones still waiting for a result are left to the stream, which either
+/// keeps them for a session resume or fails them with [`fail_pending_replies`].
 pub(super) async fn forward_nats_to_action(
     action_identifier: String,
     mut sub: Subscriber,
     tx: tokio::sync::mpsc::Sender<Result<ActionTransferResponse, tonic::Status>>,
-    pending_replies: PendingReplyStore,
+    connection: ActionConnection,
+    limits: ExecutionLimits,
+    client: async_nats::Client,
+    drain: Drain,
 ) {
-    log::debug!("Waiting for incoming action execution request");
+    let pending_replies = connection.pending_replies.clone();
+    let instance_id = connection.instance_id.as_str();
+    log::debug!(
+        "Waiting for incoming action execution request action={} instance_id={}",
+        action_identifier,
+        instance_id
+    );
+    let mut unsubscribed = false;
+    let mut subscription_ended = false;
+    // Whether the connection left the queue group because it reached
+    // `max_in_flight`, so NATS hands new requests to the other replicas.
+    let mut saturated = false;
+
+    loop {
+        let at_capacity = match limits.max_in_flight {
+            Some(max_in_flight) => pending_replies.len().await >= max_in_flight,
+            None => false,
+        };
+
+        if at_capacity && !saturated && !subscription_ended {
+            log::debug!(
+                "Leaving execution queue group at max_in_flight action={} instance_id={}",
+                action_identifier,
+                instance_id
+            );
+            saturated = leave_queue_group(&action_identifier, instance_id, &mut sub, &client).await;
+            subscription_ended = saturated;
+        } else if !at_capacity && saturated && !unsubscribed {
+            match subscribe_executions(&client, &action_identifier).await {
+                Ok(resubscribed) => {
+                    log::debug!(
+                        "Rejoined execution queue group action={} instance_id={}",
+                        action_identifier,
+                        instance_id
+                    );
+                    sub = resubscribed;
+                    saturated = false;
+                    subscription_ended = false;
+                }
+                Err(err) => {
+                    metrics::action_failure(&action_identifier, "subscription");
+                    errors::record(
+                        "messaging",
+                        "action.subscribe",
+                        &err,
+                        format!("action.identifier={action_identifier} instance_id={instance_id}"),
+                    );
+                    tokio::select! {
+                        _ = tokio::time::sleep(RESUBSCRIBE_DELAY) => {}
+                        _ = drain.draining() => unsubscribed = true,
+                        _ = connection.closed() => break,
+                    }
+                    continue;
+                }
+            }
+        }
+
+        let next = tokio::select! {
+            next = sub.next(), if !subscription_ended && !at_capacity => next,
+            _ = pending_replies.below(limits.max_in_flight.unwrap_or(usize::MAX)), if at_capacity => continue,
+            _ = drain.draining(), if !unsubscribed => {
+                unsubscribed = true;
+                if subscription_ended {
+                    continue;
+                }
+                log::debug!("Unsubscribing action from execution requests while draining action={} instance_id={}", action_identifier, instance_id);
+                if let Err(err) = sub.unsubscribe().await {
+                    errors::record(
+                        "messaging",
+                        "action.unsubscribe",
+                        &err,
+                        format!("action.identifier={action_identifier} instance_id={instance_id}"),
+                    );
+                    subscription_ended = true;
+                }
+                continue;
+            }
+            _ = connection.closed() => break,
+        };
+
+        let Some(msg) = next else {
+            subscription_ended = true;
+            continue;
+        };
 
-    while let Some(msg) = sub.next().await {
         let mut execution = match ActionExecutionRequest::decode(msg.payload.as_ref()) {
             Ok(req) => req,
             Err(err) => {
@@ -642,8 +1635,20 @@ pub(super) async fn forward_nats_to_action(
         }
 
         pending_replies
-            .insert(reply_subject.clone(), keys.clone())
+            .insert(
+                reply_subject.clone(),
+                keys.clone(),
+                execution.function_identifier.clone(),
+                limits.timeout,
+                TrackedExecution {
+                    origin: ExecutionOrigin::ActionExecution,
+                    flow_id: None,
+                    project_id: Some(execution.project_id),
+                    action_identifier: Some(action_identifier.clone()),
+                },
+            )
             .await;
+        report_in_flight(&action_identifier, &connection).await;
 
         log::debug!(
             "Stored reply subject reply_subject={} execution_id={} keys={:?}",
@@ -653,9 +1658,10 @@ pub(super) async fn forward_nats_to_action(
         );
 
         log::debug!(
-            "Forwarding execution request to action execution_id={} subject={}",
+            "Forwarding execution request to action execution_id={} subject={} instance_id={}",
             execution_id,
-            msg.subject
+            msg.subject,
+            instance_id
         );
 
         let resp = ActionTransferResponse {
@@ -669,15 +1675,54 @@ pub(super) async fn forward_nats_to_action(
             metrics::action_failure(&action_identifier, "execution_forward");
             log::debug!("Execution forwarder channel closed");
 
-            // cleanup, since the request can no longer be delivered to the action
+            // The action never saw this request, so another replica can take it.
             pending_replies.remove(&execution_id).await;
+            report_in_flight(&action_identifier, &connection).await;
+            requeue(&action_identifier, &client, msg).await;
 
             break;
         }
+        connection.counters.execution();
         metrics::action_execution(&action_identifier, "forwarded");
+
+        tokio::spawn(expire_pending_reply(
+            action_identifier.clone(),
+            execution_id,
+            limits.timeout,
+            client.clone(),
+            connection.clone(),
+        ));
+    }
+
+    if !subscription_ended {
+        let receiver_closed = unsubscribed
+            || match sub.unsubscribe().await {
+                Ok(()) => true,
+                Err(err) => {
+                    errors::record(
+                        "messaging",
+                        "action.unsubscribe",
+                        &err,
+                        format!("action.identifier={action_identifier} instance_id={instance_id}"),
+                    );
+                    false
+                }
+            };
+
+        // Once unsubscribed, the subscriber only yields what was already
+        // buffered before ending.
+        if receiver_closed {
+            while let Some(msg) = sub.next().await {
+                requeue(&action_identifier, &client, msg).await;
+            }
+        }
     }
 
-    log::debug!("Execution forwarder stopped");
+    log::debug!(
+        "Execution forwarder stopped action={} instance_id={}",
+        action_identifier,
+        instance_id
+    );
 }
 
 #[cfg(test)]
@@ -722,4 +1767,109 @@ mod tests {
             "missing"
         );
     }
+
+    #[tokio::test]
+    async fn fan_out_requests_at_most_as_many_items_as_permits_at_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let permits = Semaphore::new(2);
+        let running = AtomicUsize::new(0);
+        let most_running = AtomicUsize::new(0);
+
+        let outcomes = fan_out((0..5).collect(), &permits, None, |item| {
+            let (running, most_running) = (&running, &most_running);
+            async move {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most_running.fetch_max(now_running, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                item
+            }
+        })
+        .await;
+
+        assert_eq!(outcomes, vec![Some(0), Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(most_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn fan_out_gives_up_on_slow_items_at_the_shared_deadline() {
+        let permits = Semaphore::new(3);
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(100);
+
+        let outcomes = fan_out(
+            vec![10, 1_000, 20],
+            &permits,
+            Some(deadline),
+            |millis| async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                millis
+            },
+        )
+        .await;
+
+        // The slow item doesn't hold up the others, which run alongside it.
+        assert_eq!(outcomes, vec![Some(10), None, Some(20)]);
+        assert!(tokio::time::Instant::now() < deadline + Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn fan_out_skips_items_still_waiting_for_a_permit_at_the_deadline() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let permits = Semaphore::new(1);
+        let _held = permits.acquire().await.expect("semaphore is open");
+        let requested = AtomicUsize::new(0);
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(20);
+
+        let outcomes = fan_out(vec![1, 2], &permits, Some(deadline), |item| {
+            let requested = &requested;
+            async move {
+                requested.fetch_add(1, Ordering::SeqCst);
+                item
+            }
+        })
+        .await;
+
+        assert_eq!(outcomes, vec![None, None]);
+        assert_eq!(requested.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn events_beyond_the_in_flight_limit_wait_until_the_queue_is_full() {
+        let admission = EventAdmission::new(1, 1);
+
+        let first = admission
+            .start(admission.admit().expect("first event should be admitted"))
+            .await
+            .expect("first event should start");
+        let queued = admission.admit().expect("second event should be queued");
+        assert!(admission.admit().is_none());
+
+        let second = admission.start(queued);
+        futures::pin_mut!(second);
+        assert!(futures::poll!(second.as_mut()).is_pending());
+
+        drop(first);
+        let second = second.await.expect("queued event should start");
+        assert!(admission.admit().is_some());
+
+        let settled = admission.settled();
+        futures::pin_mut!(settled);
+        assert!(futures::poll!(settled.as_mut()).is_pending());
+        drop(second);
+        settled.await;
+    }
+
+    #[test]
+    fn reached_runtime_counts_timeouts_but_not_undelivered_requests() {
+        assert!(reached_runtime(&None));
+        assert!(reached_runtime(&Some(EventReply::Cancelled)));
+        assert!(reached_runtime(&Some(EventReply::Failed(
+            RequestErrorKind::TimedOut
+        ))));
+        assert!(!reached_runtime(&Some(EventReply::Failed(
+            RequestErrorKind::NoResponders
+        ))));
+    }
 }
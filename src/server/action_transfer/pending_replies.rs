@@ -1,9 +1,28 @@
 //! Tracks in-flight action executions so their NATS reply subject can be
-//! located again once the connected action sends back a result.
+//! located again once the connected action sends back a result. The reply
+//! subject lives in the execution's entry in the shared
+//! [`ExecutionRegistry`]; each connection's store is a view onto the
+//! `action_execution` entries it filed there.
 
 use async_nats::Subject;
-use std::{collections::HashMap, sync::Arc, time::Instant};
-use tokio::sync::Mutex;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{Notify, futures::Notified};
+
+use crate::flow::executions::{
+    ExecutionOrigin, ExecutionRegistry, ExecutionRoute, ExecutionState, FinishedExecution,
+    TrackedExecution,
+};
+
+const ORIGIN: ExecutionOrigin = ExecutionOrigin::ActionExecution;
+
+/// Tells the stores of different connections apart in the shared registry.
+static NEXT_STORE_ID: AtomicU64 = AtomicU64::new(0);
 
 /// A single execution awaiting a result, plus everything needed to route
 /// that result and measure how long it took.
@@ -11,56 +30,173 @@ use tokio::sync::Mutex;
 pub(super) struct PendingReply {
     /// Where to publish the `ActionExecutionResponse` once it arrives.
     pub(super) reply_subject: Subject,
-    /// Every key this entry is filed under in the store, so removal by any
-    /// one alias can also clean up the others.
-    keys: Vec<String>,
+    /// The function the action was asked to run, reported back if Aquila
+    /// has to answer in the action's place.
+    pub(super) function_identifier: String,
     /// When the request was forwarded to the action, for execution-duration metrics.
     pub(super) started_at: Instant,
 }
 
-/// Shared, lock-protected registry mapping execution identifiers to the NATS
-/// reply subject a result must eventually be published to.
-#[derive(Clone, Default)]
+/// One connection's executions awaiting a result, each filed under the
+/// execution identifiers its result may come back with.
+#[derive(Clone)]
 pub(super) struct PendingReplyStore {
-    inner: Arc<Mutex<HashMap<String, PendingReply>>>,
+    id: u64,
+    /// Woken whenever an execution leaves the store, for [`Self::below`]
+    /// and [`Self::released`].
+    released: Arc<Notify>,
+    /// The [`Self::len`] last handed out by [`Self::unreported_change`].
+    reported: Arc<AtomicUsize>,
+    executions: ExecutionRegistry,
 }
 
 impl PendingReplyStore {
-    pub(super) fn new() -> Self {
-        Self::default()
+    pub(super) fn new(executions: ExecutionRegistry) -> Self {
+        Self {
+            id: NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed),
+            released: Arc::default(),
+            reported: Arc::default(),
+            executions,
+        }
     }
 
     /// Registers `reply_subject` under every alias in `keys` (typically the
-    /// payload execution id and the one derived from the NATS subject).
-    pub(super) async fn insert(&self, reply_subject: Subject, keys: Vec<String>) {
-        let pending_reply = PendingReply {
-            reply_subject,
-            keys: keys.clone(),
-            started_at: Instant::now(),
+    /// payload execution id and the one derived from the NATS subject),
+    /// expiring once `timeout` has passed. `execution` is tracked under the
+    /// first key.
+    pub(super) async fn insert(
+        &self,
+        reply_subject: Subject,
+        keys: Vec<String>,
+        function_identifier: String,
+        timeout: Duration,
+        execution: TrackedExecution,
+    ) {
+        let mut keys = keys.into_iter();
+        let Some(primary_key) = keys.next() else {
+            return;
         };
 
-        let mut pending = self.inner.lock().await;
-        for key in keys {
-            pending.insert(key, pending_reply.clone());
-        }
+        self.executions
+            .track_routed(
+                &primary_key,
+                keys.collect(),
+                execution,
+                ExecutionState::AwaitingAction,
+                ExecutionRoute::Reply {
+                    reply_subject,
+                    function_identifier,
+                    deadline: Instant::now() + timeout,
+                    store: self.id,
+                },
+            )
+            .await;
     }
 
     /// Removes and returns the reply registered under `execution_id`, along
     /// with any of its aliases.
     pub(super) async fn remove(&self, execution_id: &str) -> Option<PendingReply> {
-        let mut pending = self.inner.lock().await;
-        let pending_reply = pending.remove(execution_id)?;
+        self.take_if(execution_id, |_| true).await
+    }
+
+    /// Removes the reply registered under `execution_id` only if its
+    /// deadline has passed by `now`. An execution id that was answered and
+    /// then reused for a newer request is left alone.
+    pub(super) async fn expire(&self, execution_id: &str, now: Instant) -> Option<PendingReply> {
+        self.take_if(execution_id, |deadline| deadline <= now).await
+    }
+
+    async fn take_if(
+        &self,
+        execution_id: &str,
+        take: impl FnOnce(Instant) -> bool,
+    ) -> Option<PendingReply> {
+        let finished = self
+            .executions
+            .take_if(ORIGIN, execution_id, |_, route| match route {
+                ExecutionRoute::Reply {
+                    deadline, store, ..
+                } if *store == self.id => take(*deadline),
+                _ => false,
+            })
+            .await?;
+        self.released.notify_waiters();
+        pending_reply(finished).map(|(_, pending_reply)| pending_reply)
+    }
 
-        for key in &pending_reply.keys {
-            if key != execution_id {
-                pending.remove(key);
+    /// Removes every pending reply, returning each execution once under its
+    /// primary key.
+    pub(super) async fn take_all(&self) -> Vec<(String, PendingReply)> {
+        let taken = self
+            .executions
+            .take_all(ORIGIN, |route| self.holds(route))
+            .await;
+        self.released.notify_waiters();
+        taken.into_iter().filter_map(pending_reply).collect()
+    }
+
+    /// Resolves once fewer than `max` executions are pending.
+    pub(super) async fn below(&self, max: usize) {
+        loop {
+            // Registered before checking, so a release in between isn't missed.
+            let released = self.released.notified();
+            if self.len().await < max {
+                return;
             }
+            released.await;
         }
+    }
+
+    /// Resolves the next time an execution leaves the store, counting from
+    /// when this is called rather than first polled.
+    pub(super) fn released(&self) -> Notified<'_> {
+        self.released.notified()
+    }
+
+    pub(super) async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Number of pending executions, counting each one once regardless of
+    /// how many aliases it's filed under.
+    pub(super) async fn len(&self) -> usize {
+        self.executions
+            .count(ORIGIN, |route| self.holds(route))
+            .await
+    }
+
+    /// How much [`Self::len`] changed since the last call, so the in-flight
+    /// metric can be summed over every connection of an action.
+    pub(super) async fn unreported_change(&self) -> i64 {
+        let len = self.len().await;
+        let reported = self.reported.swap(len, Ordering::Relaxed);
+        len as i64 - reported as i64
+    }
 
-        Some(pending_reply)
+    fn holds(&self, route: &ExecutionRoute) -> bool {
+        matches!(route, ExecutionRoute::Reply { store, .. } if *store == self.id)
     }
 }
 
+fn pending_reply(finished: FinishedExecution) -> Option<(String, PendingReply)> {
+    let ExecutionRoute::Reply {
+        reply_subject,
+        function_identifier,
+        ..
+    } = finished.route
+    else {
+        return None;
+    };
+    Some((
+        finished.execution_id,
+        PendingReply {
+            reply_subject,
+            function_identifier,
+            started_at: finished.started,
+        },
+    ))
+}
+
 /// Determines which keys a pending reply should be filed under: the
 /// execution id from the request payload and, if different, the one derived
 /// from the NATS subject it arrived on.
@@ -87,6 +223,16 @@ pub(super) fn pending_reply_keys(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::flow::executions::ExecutionOrigin;
+
+    fn action_execution() -> TrackedExecution {
+        TrackedExecution {
+            origin: ExecutionOrigin::ActionExecution,
+            flow_id: None,
+            project_id: Some(1),
+            action_identifier: Some("action".to_string()),
+        }
+    }
 
     #[test]
     fn pending_reply_keys_include_payload_and_subject_ids_once() {
@@ -108,12 +254,15 @@ mod tests {
     fn remove_removes_all_aliases() {
         futures::executor::block_on(async {
             let reply_subject = Subject::from("_INBOX.reply");
-            let store = PendingReplyStore::new();
+            let store = PendingReplyStore::new(ExecutionRegistry::new());
 
             store
                 .insert(
                     reply_subject.clone(),
                     vec!["payload-id".to_string(), "subject-id".to_string()],
+                    "send_email".to_string(),
+                    Duration::from_secs(60),
+                    action_execution(),
                 )
                 .await;
 
@@ -126,4 +275,123 @@ mod tests {
             assert!(store.remove("payload-id").await.is_none());
         });
     }
+
+    #[test]
+    fn executions_are_tracked_once_until_removed() {
+        futures::executor::block_on(async {
+            let executions = ExecutionRegistry::new();
+            let store = PendingReplyStore::new(executions.clone());
+            store
+                .insert(
+                    Subject::from("_INBOX.reply"),
+                    vec!["payload-id".to_string(), "subject-id".to_string()],
+                    "send_email".to_string(),
+                    Duration::from_secs(60),
+                    action_execution(),
+                )
+                .await;
+
+            let tracked = executions.snapshot().await;
+
+            assert_eq!(store.len().await, 1);
+            assert_eq!(tracked.len(), 1);
+            assert_eq!(tracked[0].execution_id, "payload-id");
+            assert_eq!(tracked[0].state, "awaiting_action");
+
+            store.remove("subject-id").await;
+            assert!(executions.snapshot().await.is_empty());
+        });
+    }
+
+    #[test]
+    fn expire_only_removes_entries_past_their_deadline() {
+        futures::executor::block_on(async {
+            let store = PendingReplyStore::new(ExecutionRegistry::new());
+            store
+                .insert(
+                    Subject::from("_INBOX.reply"),
+                    vec!["payload-id".to_string(), "subject-id".to_string()],
+                    "send_email".to_string(),
+                    Duration::from_secs(60),
+                    action_execution(),
+                )
+                .await;
+
+            assert!(store.expire("payload-id", Instant::now()).await.is_none());
+            assert_eq!(store.len().await, 1);
+
+            let later = Instant::now() + Duration::from_secs(61);
+            assert!(store.expire("subject-id", later).await.is_some());
+            assert!(store.is_empty().await);
+        });
+    }
+
+    #[test]
+    fn take_all_returns_each_execution_once() {
+        futures::executor::block_on(async {
+            let store = PendingReplyStore::new(ExecutionRegistry::new());
+            store
+                .insert(
+                    Subject::from("_INBOX.reply"),
+                    vec!["payload-id".to_string(), "subject-id".to_string()],
+                    "send_email".to_string(),
+                    Duration::from_secs(60),
+                    action_execution(),
+                )
+                .await;
+
+            let taken = store.take_all().await;
+
+            assert_eq!(taken.len(), 1);
+            assert_eq!(taken[0].0, "payload-id");
+            assert_eq!(taken[0].1.function_identifier, "send_email");
+            assert!(store.is_empty().await);
+        });
+    }
+
+    #[test]
+    fn unreported_change_is_relative_to_the_last_report() {
+        futures::executor::block_on(async {
+            let store = PendingReplyStore::new(ExecutionRegistry::new());
+            store
+                .insert(
+                    Subject::from("_INBOX.reply"),
+                    vec!["payload-id".to_string(), "subject-id".to_string()],
+                    "send_email".to_string(),
+                    Duration::from_secs(60),
+                    action_execution(),
+                )
+                .await;
+
+            assert_eq!(store.unreported_change().await, 1);
+            assert_eq!(store.unreported_change().await, 0);
+
+            store.take_all().await;
+
+            assert_eq!(store.unreported_change().await, -1);
+        });
+    }
+
+    #[test]
+    fn below_resolves_once_an_execution_is_released() {
+        futures::executor::block_on(async {
+            let store = PendingReplyStore::new(ExecutionRegistry::new());
+            store
+                .insert(
+                    Subject::from("_INBOX.reply"),
+                    vec!["payload-id".to_string()],
+                    "send_email".to_string(),
+                    Duration::from_secs(60),
+                    action_execution(),
+                )
+                .await;
+
+            let below = store.below(1);
+            futures::pin_mut!(below);
+            assert!(futures::poll!(below.as_mut()).is_pending());
+
+            store.remove("payload-id").await;
+            below.await;
+        });
+    }
 }
@@ -0,0 +1,281 @@
+//! Lets an action that reconnects pick up where its previous stream left
+//! off. Every stream is issued a session id; a reconnect presenting the id
+//! of a recent stream of the same action takes over that stream's pending
+//! replies and flow update cursor instead of logging on from scratch.
+//!
+//! A session is either attached to a live stream or detached, waiting out
+//! its grace period after the stream ended. Resuming an attached session
+//! closes its old stream first, since a client usually reconnects before
+//! Aquila notices the old connection is gone.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{Mutex, Notify, broadcast};
+
+use crate::flow::FlowChange;
+
+use super::connections::{ActionConnection, CloseReason};
+
+/// Request metadata carrying the session to resume, and response metadata
+/// carrying the id issued to the new stream.
+pub(super) const SESSION_ID_HEADER: &str = "aquila-session-id";
+
+/// How long a resume waits for the stream it replaces to wind down.
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where an action's flow forwarder stopped: the broadcast receiver keeps
+/// buffering changes while the action is away.
+pub(super) struct FlowCursor {
+    pub(super) receiver: broadcast::Receiver<FlowChange>,
+    /// A change that was taken off the receiver but couldn't be sent.
+    pub(super) undelivered: Option<FlowChange>,
+    /// The flows the action was sent and not told were deleted since.
+    pub(super) known: HashSet<i64>,
+    /// Whether the action may have missed changes and has to be re-synced
+    /// from the flow store before anything else is forwarded.
+    pub(super) out_of_sync: bool,
+}
+
+/// Everything a resumed stream carries over from the one it replaces.
+pub(super) struct SessionState {
+    pub(super) identifier: String,
+    token: String,
+    /// The module as registered with Sagittarius on the original logon.
+    pub(super) module: Option<tucana::shared::Module>,
+    /// The previous stream's connection; its pending replies move to the new one.
+    pub(super) connection: ActionConnection,
+    /// `None` if the flow forwarder didn't hand its cursor back.
+    pub(super) flows: Option<FlowCursor>,
+}
+
+enum Session {
+    Attached {
+        identifier: String,
+        token: String,
+        connection: ActionConnection,
+    },
+    Detached {
+        state: Box<SessionState>,
+        detached_at: Instant,
+    },
+}
+
+/// Every session that can still be resumed, keyed by session id.
+#[derive(Clone)]
+pub struct ActionSessionRegistry {
+    grace_period: Duration,
+    inner: Arc<Mutex<HashMap<String, Session>>>,
+    /// Woken whenever a session detaches, for a resume waiting on a takeover.
+    detached: Arc<Notify>,
+}
+
+impl ActionSessionRegistry {
+    /// A `grace_period` of zero disables resumption.
+    pub fn new(grace_period: Duration) -> Self {
+        Self {
+            grace_period,
+            inner: Arc::default(),
+            detached: Arc::default(),
+        }
+    }
+
+    pub(super) fn enabled(&self) -> bool {
+        !self.grace_period.is_zero()
+    }
+
+    pub(super) fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
+
+    /// Records a stream that completed its logon under `session_id`.
+    pub(super) async fn attach(
+        &self,
+        session_id: String,
+        identifier: String,
+        token: String,
+        connection: ActionConnection,
+    ) {
+        self.inner.lock().await.insert(
+            session_id,
+            Session::Attached {
+                identifier,
+                token,
+                connection,
+            },
+        );
+    }
+
+    /// Parks an ended stream's state until it's resumed or expires.
+    pub(super) async fn detach(&self, session_id: String, state: SessionState) {
+        self.inner.lock().await.insert(
+            session_id,
+            Session::Detached {
+                state: Box::new(state),
+                detached_at: Instant::now(),
+            },
+        );
+        self.detached.notify_waiters();
+    }
+
+    /// Drops an attached session without keeping anything to resume.
+    pub(super) async fn forget(&self, session_id: &str) {
+        let mut sessions = self.inner.lock().await;
+        if matches!(sessions.get(session_id), Some(Session::Attached { .. })) {
+            sessions.remove(session_id);
+        }
+        self.detached.notify_waiters();
+    }
+
+    /// Removes `session_id` if it's still detached and its grace period has
+    /// passed by `now`.
+    pub(super) async fn expire(&self, session_id: &str, now: Instant) -> Option<SessionState> {
+        let mut sessions = self.inner.lock().await;
+        match sessions.get(session_id) {
+            Some(Session::Detached { detached_at, .. })
+                if now.duration_since(*detached_at) >= self.grace_period => {}
+            _ => return None,
+        }
+
+        match sessions.remove(session_id) {
+            Some(Session::Detached { state, .. }) => Some(*state),
+            _ => None,
+        }
+    }
+
+    /// Takes over the session `session_id` for a new stream of the same
+    /// action authenticated with the same token. An attached session's
+    /// stream is closed and given [`TAKEOVER_TIMEOUT`] to detach.
+    pub(super) async fn resume(
+        &self,
+        session_id: &str,
+        token: &str,
+        identifier: &str,
+    ) -> Option<SessionState> {
+        let deadline = tokio::time::Instant::now() + TAKEOVER_TIMEOUT;
+        loop {
+            // Registered before checking, so a detach in between isn't missed.
+            let detached = self.detached.notified();
+            {
+                let mut sessions = self.inner.lock().await;
+                match sessions.get(session_id)? {
+                    Session::Attached {
+                        identifier: session_identifier,
+                        token: session_token,
+                        connection,
+                    } => {
+                        if session_identifier != identifier || session_token != token {
+                            return None;
+                        }
+                        connection.close(CloseReason::Replaced);
+                    }
+                    Session::Detached { state, .. } => {
+                        if state.identifier != identifier || state.token != token {
+                            return None;
+                        }
+                        return match sessions.remove(session_id) {
+                            Some(Session::Detached { state, .. }) => Some(*state),
+                            _ => None,
+                        };
+                    }
+                }
+            }
+
+            if tokio::time::timeout_at(deadline, detached).await.is_err() {
+                log::warn!(
+                    "Timed out waiting for replaced action stream to close session_id={}",
+                    session_id
+                );
+                return None;
+            }
+        }
+    }
+}
+
+impl SessionState {
+    pub(super) fn new(
+        identifier: String,
+        token: String,
+        module: Option<tucana::shared::Module>,
+        connection: ActionConnection,
+        flows: Option<FlowCursor>,
+    ) -> Self {
+        Self {
+            identifier,
+            token,
+            module,
+            connection,
+            flows,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::executions::ExecutionRegistry;
+
+    fn state(identifier: &str, token: &str) -> SessionState {
+        SessionState::new(
+            identifier.to_string(),
+            token.to_string(),
+            None,
+            ActionConnection::new(ExecutionRegistry::new()),
+            None,
+        )
+    }
+
+    #[test]
+    fn detached_session_resumes_once_for_same_action_and_token() {
+        futures::executor::block_on(async {
+            let sessions = ActionSessionRegistry::new(Duration::from_secs(30));
+            sessions
+                .detach("session".to_string(), state("send-email", "token"))
+                .await;
+
+            assert!(
+                sessions
+                    .resume("session", "other", "send-email")
+                    .await
+                    .is_none()
+            );
+            assert!(sessions.resume("session", "token", "other").await.is_none());
+
+            let resumed = sessions
+                .resume("session", "token", "send-email")
+                .await
+                .expect("session should resume");
+            assert_eq!(resumed.identifier, "send-email");
+            assert!(
+                sessions
+                    .resume("session", "token", "send-email")
+                    .await
+                    .is_none()
+            );
+        });
+    }
+
+    #[test]
+    fn expire_waits_for_grace_period() {
+        futures::executor::block_on(async {
+            let sessions = ActionSessionRegistry::new(Duration::from_secs(30));
+            sessions
+                .detach("session".to_string(), state("send-email", "token"))
+                .await;
+
+            assert!(sessions.expire("session", Instant::now()).await.is_none());
+
+            let later = Instant::now() + Duration::from_secs(31);
+            assert!(sessions.expire("session", later).await.is_some());
+            assert!(
+                sessions
+                    .resume("session", "token", "send-email")
+                    .await
+                    .is_none()
+            );
+        });
+    }
+}
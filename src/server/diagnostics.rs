@@ -0,0 +1,40 @@
+//! Read-only handles onto the gRPC server's in-memory bookkeeping, handed to
+//! the admin API so it can report what a running Aquila is doing without
+//! reaching into the individual services.
+
+use crate::flow::executions::{ExecutionRegistry, ExecutionSnapshot};
+
+use super::{
+    action_transfer::{ActionConnectionRegistry, ActionConnectionSnapshot},
+    runtime_status_service_server_impl::{TrackedRuntimeRegistry, TrackedRuntimeSnapshot},
+};
+
+#[derive(Clone)]
+pub struct ServerDiagnostics {
+    pub(super) connections: ActionConnectionRegistry,
+    pub(super) executions: ExecutionRegistry,
+    /// `None` in static mode, where there is no `RuntimeStatusService`.
+    pub(super) runtimes: Option<TrackedRuntimeRegistry>,
+}
+
+impl ServerDiagnostics {
+    pub async fn actions(&self) -> Vec<ActionConnectionSnapshot> {
+        self.connections.snapshot().await
+    }
+
+    pub async fn runtimes(&self) -> Option<Vec<TrackedRuntimeSnapshot>> {
+        match &self.runtimes {
+            Some(runtimes) => Some(runtimes.snapshot().await),
+            None => None,
+        }
+    }
+
+    /// Every execution currently in flight, oldest first.
+    pub async fn executions(&self) -> Vec<ExecutionSnapshot> {
+        self.executions.snapshot().await
+    }
+
+    pub async fn execution(&self, execution_id: &str) -> Option<ExecutionSnapshot> {
+        self.executions.get(execution_id).await
+    }
+}
@@ -0,0 +1,152 @@
+//! Coordinates Aquila's graceful shutdown across every long-lived task.
+//!
+//! Shutdown happens in two phases so executions already in flight can still
+//! complete: while *draining*, the gRPC server keeps running (runtimes still
+//! need `ExecutionService.Update` to report results) but action streams stop
+//! taking on new work and close themselves once their in-flight executions
+//! have settled. Only once every action stream is gone (or the deadline has
+//! passed) does the coordinator move to *closed*, which stops the gRPC
+//! server and the Sagittarius stream loops.
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Running,
+    Draining,
+    Closed,
+}
+
+/// Shared handle every task consults to learn when to stop taking work and
+/// when to exit. Cloning is cheap; every clone observes the same phase.
+#[derive(Clone)]
+pub struct Drain {
+    phase: Arc<watch::Sender<Phase>>,
+    active_streams: Arc<watch::Sender<usize>>,
+    timeout: Duration,
+}
+
+impl Drain {
+    /// `timeout` bounds how long an action stream waits for its in-flight
+    /// executions once draining has started.
+    pub fn new(timeout: Duration) -> Self {
+        let (phase, _) = watch::channel(Phase::Running);
+        let (active_streams, _) = watch::channel(0);
+
+        Self {
+            phase: Arc::new(phase),
+            active_streams: Arc::new(active_streams),
+            timeout,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Moves from running to draining. A no-op once already draining or closed.
+    pub fn start(&self) {
+        self.phase.send_if_modified(|phase| {
+            if *phase != Phase::Running {
+                return false;
+            }
+            *phase = Phase::Draining;
+            true
+        });
+    }
+
+    /// Moves to closed, regardless of the current phase.
+    pub fn close(&self) {
+        self.phase.send_replace(Phase::Closed);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.phase.borrow() != Phase::Running
+    }
+
+    /// Resolves once draining has started (or immediately, if it already has).
+    pub async fn draining(&self) {
+        let mut phase = self.phase.subscribe();
+        let _ = phase.wait_for(|phase| *phase != Phase::Running).await;
+    }
+
+    /// Resolves once the coordinator is closed (or immediately, if it already is).
+    pub async fn closed(&self) {
+        let mut phase = self.phase.subscribe();
+        let _ = phase.wait_for(|phase| *phase == Phase::Closed).await;
+    }
+
+    /// Counts an action stream as active until the returned guard is dropped.
+    pub(crate) fn track_stream(&self) -> StreamGuard {
+        self.active_streams.send_modify(|count| *count += 1);
+        StreamGuard {
+            active_streams: self.active_streams.clone(),
+        }
+    }
+
+    pub fn active_streams(&self) -> usize {
+        *self.active_streams.borrow()
+    }
+
+    /// Resolves once no tracked action stream is left.
+    pub async fn streams_closed(&self) {
+        let mut active_streams = self.active_streams.subscribe();
+        let _ = active_streams.wait_for(|count| *count == 0).await;
+    }
+}
+
+/// Keeps an action stream counted by [`Drain::streams_closed`] for as long as
+/// it lives.
+pub(crate) struct StreamGuard {
+    active_streams: Arc<watch::Sender<usize>>,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.active_streams
+            .send_modify(|count| *count = count.saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_only_move_forward() {
+        futures::executor::block_on(async {
+            let drain = Drain::new(Duration::from_secs(1));
+            assert!(!drain.is_draining());
+
+            drain.start();
+            drain.draining().await;
+            assert!(drain.is_draining());
+
+            drain.close();
+            drain.closed().await;
+
+            drain.start();
+            drain.closed().await;
+            assert!(drain.is_draining());
+        });
+    }
+
+    #[test]
+    fn streams_closed_waits_for_every_guard() {
+        futures::executor::block_on(async {
+            let drain = Drain::new(Duration::from_secs(1));
+            let first = drain.track_stream();
+            let second = drain.track_stream();
+            assert_eq!(drain.active_streams(), 2);
+
+            drop(first);
+            assert_eq!(drain.active_streams(), 1);
+
+            drop(second);
+            drain.streams_closed().await;
+            assert_eq!(drain.active_streams(), 0);
+        });
+    }
+}
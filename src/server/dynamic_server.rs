@@ -3,31 +3,49 @@
 //! runtime status, all gated behind the Sagittarius readiness interceptor.
 
 use crate::{
-    configuration::{config::Config, service::ServiceConfiguration, state::AppReadiness},
+    configuration::{
+        config::{Config, OutputValidationMode},
+        module_configurations::LatestModuleConfigurations,
+        service::ServiceConfiguration,
+        state::AppReadiness,
+    },
+    flow::{
+        dead_letters::DeadLetters, dispatch::ExecutionDispatcher, executions::ExecutionRegistry,
+        output_schemas::OutputSchemas,
+    },
     sagittarius::{
         module_service_client_impl::SagittariusModuleServiceClient,
         runtime_status_service_client_impl::SagittariusRuntimeStatusServiceClient,
         test_execution_client_impl::SagittariusExecutionResponseSender,
     },
     server::{
+        Drain,
         action_transfer::{
-            ActionFlowExecutionRegistry, ActionTransferContext, AquilaActionTransferServiceServer,
+            ActionConnectionRegistry, ActionFlowExecutionRegistry, ActionSessionRegistry,
+            ActionTransferContext, AquilaActionTransferServiceServer, ConfigurationRetry,
+            ConfigurationVersions, EventKeys, ModuleUpdateCache,
         },
         create_readiness_interceptor,
+        diagnostics::ServerDiagnostics,
+        health::{self, Dependencies},
+        keepalive::Keepalive,
         module_service_server_impl::AquilaModuleServiceServer,
         runtime_execution_service_server_impl::AquilaExecutionServiceServer,
-        runtime_status_service_server_impl::AquilaRuntimeStatusServiceServer,
+        runtime_status_service_server_impl::{
+            AquilaRuntimeStatusServiceServer, TrackedRuntimeRegistry,
+        },
     },
 };
 use async_nats::jetstream::kv::Store;
 use log::info;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::sync::Mutex;
-use tonic::transport::{Channel, Server};
+use tonic::transport::Channel;
 use tucana::aquila::{
-    action_transfer_service_server::ActionTransferServiceServer,
-    execution_service_server::ExecutionServiceServer, module_service_server::ModuleServiceServer,
-    runtime_status_service_server::RuntimeStatusServiceServer,
+    action_transfer_service_server::{self, ActionTransferServiceServer},
+    execution_service_server::{self, ExecutionServiceServer},
+    module_service_server::{self, ModuleServiceServer},
+    runtime_status_service_server::{self, RuntimeStatusServiceServer},
 };
 
 /// Every collaborator `AquilaDynamicServer` needs that isn't derived from
@@ -41,15 +59,28 @@ pub struct DynamicServerDependencies {
     pub kv_store: Arc<Store>,
     pub action_config_tx: tokio::sync::broadcast::Sender<tucana::shared::ModuleConfigurations>,
     pub action_flow_tx: tokio::sync::broadcast::Sender<crate::flow::FlowChange>,
+    /// The latest configuration pushed per module, for re-syncing actions.
+    pub module_configurations: LatestModuleConfigurations,
     pub execution_response_sender: SagittariusExecutionResponseSender,
+    /// The KV bucket remembering which modules Sagittarius already has;
+    /// `None` if it couldn't be opened.
+    pub module_kv_store: Option<Store>,
+    /// Idempotency keys of recent action events.
+    pub event_keys: EventKeys,
+    pub dead_letters: DeadLetters,
+    pub dispatcher: ExecutionDispatcher,
+    /// Every execution in flight, whatever asked for it. Shared with
+    /// `execution_response_sender`.
+    pub executions: ExecutionRegistry,
+    pub drain: Drain,
 }
 
 pub struct AquilaDynamicServer {
     // Token of Sagittarius
     token: String,
-    nats_url: String,
     address: SocketAddr,
     with_health_service: bool,
+    keepalive: Keepalive,
     app_readiness: AppReadiness,
     channel: Channel,
     service_configuration: ServiceConfiguration,
@@ -57,8 +88,25 @@ pub struct AquilaDynamicServer {
     kv_store: Arc<Store>,
     action_config_tx: tokio::sync::broadcast::Sender<tucana::shared::ModuleConfigurations>,
     action_flow_tx: tokio::sync::broadcast::Sender<crate::flow::FlowChange>,
+    module_configurations: LatestModuleConfigurations,
     flow_execution_registry: ActionFlowExecutionRegistry,
+    executions: ExecutionRegistry,
+    connections: ActionConnectionRegistry,
+    sessions: ActionSessionRegistry,
+    module_updates: ModuleUpdateCache,
+    tracked_runtimes: TrackedRuntimeRegistry,
     execution_response_sender: SagittariusExecutionResponseSender,
+    drain: Drain,
+    action_execution_timeout: Duration,
+    configuration_retry: ConfigurationRetry,
+    event_result_timeout: Duration,
+    event_fan_out_limit: usize,
+    event_max_in_flight: usize,
+    event_max_queued: usize,
+    event_keys: EventKeys,
+    dead_letters: DeadLetters,
+    dispatcher: ExecutionDispatcher,
+    output_validation: OutputValidationMode,
 
     runtime_status_not_responding_after_secs: u64,
     runtime_status_stopped_after_not_responding_secs: u64,
@@ -76,7 +124,14 @@ impl AquilaDynamicServer {
             kv_store,
             action_config_tx,
             action_flow_tx,
+            module_configurations,
             execution_response_sender,
+            module_kv_store,
+            event_keys,
+            dead_letters,
+            dispatcher,
+            executions,
+            drain,
         } = deps;
 
         let address = match format!("{}:{}", config.grpc.host, config.grpc.port).parse() {
@@ -89,8 +144,8 @@ impl AquilaDynamicServer {
 
         AquilaDynamicServer {
             token: config.dynamic_config.backend_token.clone(),
-            nats_url: config.nats.url.clone(),
             with_health_service: config.grpc.health_service,
+            keepalive: Keepalive::from_config(&config.grpc),
             address,
             app_readiness,
             channel,
@@ -99,8 +154,33 @@ impl AquilaDynamicServer {
             kv_store,
             action_config_tx,
             action_flow_tx,
-            flow_execution_registry: ActionFlowExecutionRegistry::new(),
+            module_configurations,
+            flow_execution_registry: ActionFlowExecutionRegistry::new(
+                executions.clone(),
+                Duration::from_secs(config.action_execution.flow_timeout_secs),
+            ),
+            executions,
+            connections: ActionConnectionRegistry::new(),
+            module_updates: ModuleUpdateCache::new(
+                module_kv_store,
+                Duration::from_secs(config.module_update.force_refresh_interval_secs),
+            ),
+            sessions: ActionSessionRegistry::new(Duration::from_secs(
+                config.action_session.resume_grace_period_secs,
+            )),
+            tracked_runtimes: TrackedRuntimeRegistry::default(),
             execution_response_sender,
+            drain,
+            action_execution_timeout: Duration::from_secs(config.action_execution.timeout_secs),
+            configuration_retry: ConfigurationRetry::from_config(&config.action_updates),
+            event_result_timeout: Duration::from_secs(config.action_events.result_timeout_secs),
+            event_fan_out_limit: config.action_events.fan_out(),
+            event_max_in_flight: config.action_events.in_flight(),
+            event_max_queued: config.action_events.max_queued,
+            event_keys,
+            dead_letters,
+            dispatcher,
+            output_validation: config.output_validation.mode,
             runtime_status_not_responding_after_secs: config
                 .runtime_status
                 .not_responding_after_secs,
@@ -115,8 +195,17 @@ impl AquilaDynamicServer {
         }
     }
 
+    /// Handles onto the server's in-memory bookkeeping for the admin API.
+    pub fn diagnostics(&self) -> ServerDiagnostics {
+        ServerDiagnostics {
+            connections: self.connections.clone(),
+            executions: self.executions.clone(),
+            runtimes: Some(self.tracked_runtimes.clone()),
+        }
+    }
+
     /// Builds every service and blocks serving them until the listener
-    /// shuts down. Each service gets its own Sagittarius-backed client
+    /// shuts down, which happens once [`Drain`] is closed. Each service gets its own Sagittarius-backed client
     /// (module updates, runtime status) so a slow or failing call on one
     /// service can't stall another.
     pub async fn start(&self) -> Result<(), tonic::transport::Error> {
@@ -137,10 +226,16 @@ impl AquilaDynamicServer {
 
         info!("RuntimeStatusService started");
 
+        let output_schemas = OutputSchemas::new();
+        output_schemas.follow(self.action_flow_tx.subscribe());
         let execution_server = AquilaExecutionServiceServer::new(
             self.service_configuration.clone(),
             self.execution_response_sender.clone(),
             self.flow_execution_registry.clone(),
+            self.executions.clone(),
+            self.kv_store.as_ref().clone(),
+            output_schemas,
+            self.output_validation,
         );
         let module_server = AquilaModuleServiceServer::new(
             module_service.clone(),
@@ -149,6 +244,8 @@ impl AquilaDynamicServer {
         let runtime_status_server = AquilaRuntimeStatusServiceServer::new(
             runtime_status_service.clone(),
             self.service_configuration.clone(),
+            self.tracked_runtimes.clone(),
+            self.nats_client.clone(),
             Duration::from_secs(self.runtime_status_not_responding_after_secs),
             Duration::from_secs(self.runtime_status_stopped_after_not_responding_secs),
             Duration::from_secs(self.runtime_status_monitor_interval_secs),
@@ -160,10 +257,27 @@ impl AquilaDynamicServer {
                 kv: self.kv_store.as_ref().clone(),
                 actions: self.service_configuration.clone(),
                 module_service: Some(module_service.clone()),
+                module_updates: self.module_updates.clone(),
                 action_config_tx: self.action_config_tx.clone(),
                 action_flow_tx: self.action_flow_tx.clone(),
+                module_configurations: self.module_configurations.clone(),
                 flow_execution_registry: self.flow_execution_registry.clone(),
+                executions: self.executions.clone(),
+                connections: self.connections.clone(),
+                sessions: self.sessions.clone(),
+                execution_timeout: self.action_execution_timeout,
+                configuration_retry: self.configuration_retry,
+                configuration_versions: ConfigurationVersions::default(),
+                event_result_timeout: self.event_result_timeout,
+                event_fan_out_limit: self.event_fan_out_limit,
+                event_max_in_flight: self.event_max_in_flight,
+                event_max_queued: self.event_max_queued,
+                output_validation: self.output_validation,
+                event_keys: self.event_keys.clone(),
+                dead_letters: self.dead_letters.clone(),
+                dispatcher: self.dispatcher.clone(),
                 is_static: false,
+                drain: self.drain.clone(),
             });
 
         info!("Starting dynamic gRPC Server...");
@@ -173,12 +287,34 @@ impl AquilaDynamicServer {
 
         if self.with_health_service {
             info!("Starting with HealthService");
-            let health_service = code0_flow::flow_health::HealthService::new(self.nats_url.clone());
+            let health_server = health::serve(
+                vec![
+                    (
+                        execution_service_server::SERVICE_NAME,
+                        Dependencies::MessagingAndSagittarius,
+                    ),
+                    (
+                        module_service_server::SERVICE_NAME,
+                        Dependencies::MessagingAndSagittarius,
+                    ),
+                    (
+                        runtime_status_service_server::SERVICE_NAME,
+                        Dependencies::MessagingAndSagittarius,
+                    ),
+                    (
+                        action_transfer_service_server::SERVICE_NAME,
+                        Dependencies::Messaging,
+                    ),
+                ],
+                self.app_readiness.clone(),
+                self.nats_client.clone(),
+                self.kv_store.as_ref().clone(),
+                self.drain.clone(),
+            );
 
-            Server::builder()
-                .add_service(tonic_health::pb::health_server::HealthServer::new(
-                    health_service,
-                ))
+            self.keepalive
+                .server()
+                .add_service(health_server)
                 .add_service(ExecutionServiceServer::with_interceptor(
                     execution_server,
                     intercept.clone(),
@@ -195,10 +331,11 @@ impl AquilaDynamicServer {
                     action_transfer_server,
                     intercept.clone(),
                 ))
-                .serve(self.address)
+                .serve_with_shutdown(self.address, self.drain.closed())
                 .await
         } else {
-            Server::builder()
+            self.keepalive
+                .server()
                 .add_service(ExecutionServiceServer::with_interceptor(
                     execution_server,
                     intercept.clone(),
@@ -215,7 +352,7 @@ impl AquilaDynamicServer {
                     action_transfer_server,
                     intercept.clone(),
                 ))
-                .serve(self.address)
+                .serve_with_shutdown(self.address, self.drain.closed())
                 .await
         }
     }
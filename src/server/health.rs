@@ -0,0 +1,228 @@
+//! Per-service gRPC health statuses derived from [`AppReadiness`].
+//!
+//! Every service Aquila serves is registered with `grpc.health.v1.Health`
+//! under its fully qualified name (e.g. `aquila.ModuleService`), next to the
+//! `liveness`/`readiness` names the Kubernetes probes already check and the
+//! empty overall name. A background task re-probes NATS and the flow KV
+//! bucket and flips each status whenever one of its dependencies changes, so
+//! a load balancer can stop routing to a single service without the whole
+//! instance being marked unhealthy.
+
+use std::{collections::HashMap, sync::atomic::Ordering, time::Duration};
+
+use async_nats::{Client, connection::State, jetstream::kv::Store};
+use tonic_health::{
+    ServingStatus,
+    pb::health_server::{Health, HealthServer},
+    server::HealthReporter,
+};
+
+use crate::{configuration::state::AppReadiness, server::Drain};
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+const KV_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Looked up on every probe; it never exists, all that matters is whether
+/// the bucket answers.
+const KV_PROBE_KEY: &str = "aquila.health";
+
+const LIVENESS: &str = "liveness";
+const READINESS: &str = "readiness";
+const OVERALL: &str = "";
+
+/// What a gRPC service needs to be able to serve requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Dependencies {
+    /// NATS and the flow KV bucket.
+    Messaging,
+    /// NATS, the flow KV bucket and the Sagittarius streams.
+    MessagingAndSagittarius,
+}
+
+impl Dependencies {
+    fn are_ready(self, readiness: &AppReadiness) -> bool {
+        match self {
+            Dependencies::Messaging => readiness.is_messaging_ready(),
+            Dependencies::MessagingAndSagittarius => {
+                readiness.is_messaging_ready() && readiness.is_ready()
+            }
+        }
+    }
+}
+
+/// Builds the health server to register next to `services` and spawns the
+/// task keeping its statuses current. Once draining starts, every status
+/// except `liveness` is set to `NOT_SERVING` for good and the task exits.
+pub(super) fn serve(
+    services: Vec<(&'static str, Dependencies)>,
+    app_readiness: AppReadiness,
+    nats_client: Client,
+    kv: Store,
+    drain: Drain,
+) -> HealthServer<impl Health> {
+    let (reporter, server) = tonic_health::server::health_reporter();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PROBE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut current = HashMap::new();
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = drain.draining() => {
+                    mark_draining(&reporter, &services).await;
+                    return;
+                }
+            }
+
+            probe_messaging(&app_readiness, &nats_client, &kv).await;
+
+            for (service, status) in statuses(&services, &app_readiness) {
+                if current.insert(service, status) == Some(status) {
+                    continue;
+                }
+
+                reporter.set_service_status(service, status).await;
+                if status == ServingStatus::Serving {
+                    log::info!(
+                        "gRPC health status changed service={} status={}",
+                        service,
+                        status
+                    );
+                } else {
+                    log::warn!(
+                        "gRPC health status changed service={} status={}",
+                        service,
+                        status
+                    );
+                }
+            }
+        }
+    });
+
+    server
+}
+
+/// Computes the status of every service plus the aggregate names: the
+/// overall and `readiness` statuses only serve while every service does,
+/// `liveness` always serves.
+fn statuses(
+    services: &[(&'static str, Dependencies)],
+    readiness: &AppReadiness,
+) -> Vec<(&'static str, ServingStatus)> {
+    let mut statuses: Vec<_> = services
+        .iter()
+        .map(|(service, dependencies)| {
+            (*service, serving_status(dependencies.are_ready(readiness)))
+        })
+        .collect();
+
+    let all_serving = statuses
+        .iter()
+        .all(|(_, status)| *status == ServingStatus::Serving);
+    statuses.push((OVERALL, serving_status(all_serving)));
+    statuses.push((READINESS, serving_status(all_serving)));
+    statuses.push((LIVENESS, ServingStatus::Serving));
+
+    statuses
+}
+
+fn serving_status(ready: bool) -> ServingStatus {
+    if ready {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    }
+}
+
+/// Refreshes the NATS and KV flags. The Sagittarius flag is maintained by
+/// the flow stream itself.
+async fn probe_messaging(app_readiness: &AppReadiness, nats_client: &Client, kv: &Store) {
+    let nats_ready = nats_client.connection_state() == State::Connected;
+    let kv_ready = nats_ready
+        && matches!(
+            tokio::time::timeout(KV_PROBE_TIMEOUT, kv.get(KV_PROBE_KEY)).await,
+            Ok(Ok(_))
+        );
+
+    app_readiness.nats_ready.store(nats_ready, Ordering::SeqCst);
+    app_readiness.kv_ready.store(kv_ready, Ordering::SeqCst);
+}
+
+async fn mark_draining(reporter: &HealthReporter, services: &[(&'static str, Dependencies)]) {
+    for service in services
+        .iter()
+        .map(|(service, _)| *service)
+        .chain([OVERALL, READINESS])
+    {
+        reporter
+            .set_service_status(service, ServingStatus::NotServing)
+            .await;
+    }
+    log::info!("Marked gRPC services not serving while draining");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICES: [(&str, Dependencies); 2] = [
+        ("aquila.ActionTransferService", Dependencies::Messaging),
+        (
+            "aquila.ModuleService",
+            Dependencies::MessagingAndSagittarius,
+        ),
+    ];
+
+    fn status_of(statuses: &[(&'static str, ServingStatus)], service: &str) -> ServingStatus {
+        statuses
+            .iter()
+            .find(|(name, _)| *name == service)
+            .map(|(_, status)| *status)
+            .expect("service should have a status")
+    }
+
+    #[test]
+    fn sagittarius_outage_only_affects_services_that_depend_on_it() {
+        let readiness = AppReadiness::new();
+        readiness.nats_ready.store(true, Ordering::SeqCst);
+        readiness.kv_ready.store(true, Ordering::SeqCst);
+
+        let current = statuses(&SERVICES, &readiness);
+
+        assert_eq!(
+            status_of(&current, "aquila.ActionTransferService"),
+            ServingStatus::Serving
+        );
+        assert_eq!(
+            status_of(&current, "aquila.ModuleService"),
+            ServingStatus::NotServing
+        );
+        assert_eq!(status_of(&current, READINESS), ServingStatus::NotServing);
+        assert_eq!(status_of(&current, OVERALL), ServingStatus::NotServing);
+        assert_eq!(status_of(&current, LIVENESS), ServingStatus::Serving);
+    }
+
+    #[test]
+    fn kv_outage_takes_every_service_down() {
+        let readiness = AppReadiness::new();
+        readiness.sagittarius_ready.store(true, Ordering::SeqCst);
+        readiness.nats_ready.store(true, Ordering::SeqCst);
+
+        let current = statuses(&SERVICES, &readiness);
+
+        assert_eq!(
+            status_of(&current, "aquila.ActionTransferService"),
+            ServingStatus::NotServing
+        );
+        assert_eq!(
+            status_of(&current, "aquila.ModuleService"),
+            ServingStatus::NotServing
+        );
+
+        readiness.kv_ready.store(true, Ordering::SeqCst);
+        let current = statuses(&SERVICES, &readiness);
+        assert_eq!(status_of(&current, READINESS), ServingStatus::Serving);
+    }
+}
@@ -0,0 +1,54 @@
+//! HTTP/2 keepalive for every gRPC connection Aquila accepts. An action
+//! whose host vanished without a TCP reset would otherwise keep its
+//! `ActionTransfer` stream, and the execution requests routed to it, until
+//! the OS gives up on the socket. With keepalive on, a connection that
+//! leaves a ping unanswered is closed, which ends its streams with an error.
+
+use std::time::Duration;
+
+use tonic::transport::Server;
+
+use crate::configuration::config::Grpc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Keepalive {
+    /// `None` disables keepalive pings.
+    interval: Option<Duration>,
+    timeout: Duration,
+}
+
+impl Keepalive {
+    pub(super) fn from_config(grpc: &Grpc) -> Self {
+        Self {
+            interval: (grpc.keepalive_interval_secs > 0)
+                .then(|| Duration::from_secs(grpc.keepalive_interval_secs)),
+            timeout: Duration::from_secs(grpc.keepalive_timeout_secs),
+        }
+    }
+
+    /// A server builder with these keepalive settings applied.
+    pub(super) fn server(self) -> Server {
+        Server::builder()
+            .http2_keepalive_interval(self.interval)
+            .http2_keepalive_timeout(Some(self.timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_interval_disables_pings() {
+        let grpc = Grpc {
+            keepalive_interval_secs: 0,
+            ..Grpc::default()
+        };
+
+        assert_eq!(Keepalive::from_config(&grpc).interval, None);
+        assert_eq!(
+            Keepalive::from_config(&Grpc::default()).interval,
+            Some(Duration::from_secs(30))
+        );
+    }
+}
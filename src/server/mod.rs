@@ -3,7 +3,11 @@
 //! for each run mode from the individual `*_service_server_impl` modules.
 
 mod action_transfer;
+mod diagnostics;
+mod drain;
+mod health;
 mod interceptor;
+mod keepalive;
 mod module_service_server_impl;
 mod runtime_execution_service_server_impl;
 mod runtime_status_service_server_impl;
@@ -11,4 +15,8 @@ mod runtime_status_service_server_impl;
 pub mod dynamic_server;
 pub mod static_server;
 
+pub use action_transfer::{ActionConnectionSnapshot, EventKeys};
+pub use diagnostics::ServerDiagnostics;
+pub use drain::Drain;
 pub use interceptor::create_readiness_interceptor;
+pub use runtime_status_service_server_impl::TrackedRuntimeSnapshot;
@@ -3,10 +3,18 @@
 //! Sagittarius execution stream via [`SagittariusExecutionResponseSender`].
 
 use crate::{
-    authorization::authorization::extract_token, configuration::service::ServiceConfiguration,
+    authorization::authorization::extract_token,
+    configuration::{config::OutputValidationMode, service::ServiceConfiguration},
+    flow::{
+        executions::ExecutionRegistry,
+        output_schemas::{OutputSchema, OutputSchemas},
+    },
     sagittarius::test_execution_client_impl::SagittariusExecutionResponseSender,
     server::action_transfer::ActionFlowExecutionRegistry,
+    telemetry::errors,
+    validation::{self, ValidatedResult},
 };
+use async_nats::jetstream::kv::Store;
 use tonic::Status;
 use tucana::aquila::execution_service_server::ExecutionService;
 use tucana::aquila::{
@@ -22,6 +30,12 @@ pub struct AquilaExecutionServiceServer {
     /// action, so their result is routed back to that action's stream
     /// instead of Sagittarius' test execution stream.
     flow_execution_registry: ActionFlowExecutionRegistry,
+    /// Recovers the flow of a result whose runtime didn't echo its `flow_id`.
+    executions: ExecutionRegistry,
+    /// Where the output schema of a flow not in `output_schemas` yet is read from.
+    kv: Store,
+    output_schemas: OutputSchemas,
+    output_validation: OutputValidationMode,
 }
 
 impl AquilaExecutionServiceServer {
@@ -29,13 +43,98 @@ impl AquilaExecutionServiceServer {
         service_configuration: ServiceConfiguration,
         execution_response_sender: SagittariusExecutionResponseSender,
         flow_execution_registry: ActionFlowExecutionRegistry,
+        executions: ExecutionRegistry,
+        kv: Store,
+        output_schemas: OutputSchemas,
+        output_validation: OutputValidationMode,
     ) -> Self {
         Self {
             service_configuration,
             execution_response_sender,
             flow_execution_registry,
+            executions,
+            kv,
+            output_schemas,
+            output_validation,
         }
     }
+
+    /// Checks a successful result against its flow's output schema before
+    /// it's delivered anywhere. A result whose flow can't be told or isn't
+    /// stored any more isn't validated.
+    async fn validate_output(&self, execution_result: ExecutionResult) -> ValidatedResult {
+        if self.output_validation == OutputValidationMode::Off
+            || !matches!(
+                execution_result.result,
+                Some(execution_result::Result::Success(_))
+            )
+        {
+            return unvalidated(execution_result);
+        }
+
+        let flow_id = match execution_result.flow_id {
+            0 => self
+                .executions
+                .get(&execution_result.execution_identifier)
+                .await
+                .and_then(|execution| execution.flow_id),
+            flow_id => Some(flow_id),
+        };
+        let Some(flow_id) = flow_id else {
+            self.skip_output_validation("the flow is unknown", &execution_result);
+            return unvalidated(execution_result);
+        };
+
+        let output_schema = match self.output_schemas.get(&self.kv, flow_id).await {
+            Ok(OutputSchema::Declared(schema)) => Some(schema),
+            Ok(OutputSchema::Undeclared) => None,
+            Ok(OutputSchema::UnknownFlow) => {
+                self.skip_output_validation("the flow is not stored", &execution_result);
+                return unvalidated(execution_result);
+            }
+            Err(err) => {
+                errors::record_message(
+                    "flow_storage",
+                    "output_schema.lookup",
+                    err,
+                    format!(
+                        "execution_id={} flow_id={}",
+                        execution_result.execution_identifier, flow_id
+                    ),
+                );
+                return unvalidated(execution_result);
+            }
+        };
+        validation::apply_output_validation(
+            self.output_validation,
+            output_schema.as_deref(),
+            execution_result,
+        )
+    }
+
+    /// Logs a result delivered without validation: a warning where every
+    /// result is supposed to be enforced, a debug message otherwise.
+    fn skip_output_validation(&self, reason: &str, execution_result: &ExecutionResult) {
+        let level = match self.output_validation {
+            OutputValidationMode::Enforce => log::Level::Warn,
+            _ => log::Level::Debug,
+        };
+        log::log!(
+            level,
+            "Skipped output validation because {} execution_id={} flow_id={} mode={}",
+            reason,
+            execution_result.execution_identifier,
+            execution_result.flow_id,
+            self.output_validation
+        );
+    }
+}
+
+fn unvalidated(result: ExecutionResult) -> ValidatedResult {
+    ValidatedResult {
+        result,
+        output_mismatch: None,
+    }
 }
 
 /// Converts a runtime's `ExecutionResult` into the `ActionFlowExecutionResponse`
@@ -103,6 +202,13 @@ impl ExecutionService for AquilaExecutionServiceServer {
             Status::invalid_argument("missing execution result")
         })?;
 
+        let ValidatedResult {
+            result: execution_result,
+            output_mismatch,
+        } = self.validate_output(execution_result).await;
+        // Neither message a result is delivered in has room for the
+        // annotation, so it goes along in the delivery logs.
+        let output_mismatch = output_mismatch.unwrap_or_default();
         let execution_id = execution_result.execution_identifier.clone();
         let flow_id = execution_result.flow_id;
         let result_status = execution_result_status(&execution_result);
@@ -124,11 +230,12 @@ impl ExecutionService for AquilaExecutionServiceServer {
         {
             Ok(forwarded_flow_id) => {
                 log::info!(
-                    "Forwarded execution result into Sagittarius stream execution_id={} flow_id={} runtime_flow_id={} result_status={}",
+                    "Forwarded execution result into Sagittarius stream execution_id={} flow_id={} runtime_flow_id={} result_status={} output_mismatch={:?}",
                     execution_id,
                     forwarded_flow_id,
                     flow_id,
-                    result_status
+                    result_status,
+                    output_mismatch
                 );
             }
             Err(err) => {
@@ -163,10 +270,11 @@ impl ExecutionService for AquilaExecutionServiceServer {
             }
 
             log::info!(
-                "Delivered execution result to action stream execution_id={} flow_id={} result_status={}",
+                "Delivered execution result to action stream execution_id={} flow_id={} result_status={} output_mismatch={:?}",
                 execution_id,
                 flow_id,
-                result_status
+                result_status,
+                output_mismatch
             );
         }
 
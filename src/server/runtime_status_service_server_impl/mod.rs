@@ -4,10 +4,12 @@
 //! different lifetimes: the RPC handler below only needs to authenticate
 //! and record the heartbeat, while a background tick (spawned once at
 //! construction, see [`monitor`]) is what actually notices when a runtime
-//! stops sending them. Both share the same [`registry::TrackedRuntimeRegistry`].
+//! stops sending them. Both share the same [`registry::TrackedRuntimeRegistry`]
+//! and publish the status transitions it reports (see [`transition`]).
 
 mod monitor;
 mod registry;
+mod transition;
 
 use std::{sync::Arc, time::Duration};
 
@@ -20,12 +22,13 @@ use crate::{
     sagittarius::runtime_status_service_client_impl::SagittariusRuntimeStatusServiceClient,
 };
 
-use registry::TrackedRuntimeRegistry;
+pub use registry::{TrackedRuntimeRegistry, TrackedRuntimeSnapshot};
 
 pub struct AquilaRuntimeStatusServiceServer {
     client: Arc<Mutex<SagittariusRuntimeStatusServiceClient>>,
     service_configuration: ServiceConfiguration,
     tracked_runtimes: TrackedRuntimeRegistry,
+    nats_client: async_nats::Client,
     not_responding_after: Duration,
     stopped_after_not_responding: Duration,
 }
@@ -34,6 +37,8 @@ impl AquilaRuntimeStatusServiceServer {
     pub fn new(
         client: Arc<Mutex<SagittariusRuntimeStatusServiceClient>>,
         service_configuration: ServiceConfiguration,
+        tracked_runtimes: TrackedRuntimeRegistry,
+        nats_client: async_nats::Client,
         not_responding_after: Duration,
         stopped_after_not_responding: Duration,
         monitor_interval: Duration,
@@ -41,7 +46,8 @@ impl AquilaRuntimeStatusServiceServer {
         let server = Self {
             client,
             service_configuration,
-            tracked_runtimes: TrackedRuntimeRegistry::default(),
+            tracked_runtimes,
+            nats_client,
             not_responding_after,
             stopped_after_not_responding,
         };
@@ -49,6 +55,7 @@ impl AquilaRuntimeStatusServiceServer {
         monitor::spawn(
             server.tracked_runtimes.clone(),
             server.client.clone(),
+            server.nats_client.clone(),
             server.not_responding_after,
             server.stopped_after_not_responding,
             monitor_interval,
@@ -97,9 +104,13 @@ impl RuntimeStatusService for AquilaRuntimeStatusServiceServer {
             );
             return Err(Status::unauthenticated("token is not valid"));
         }
-        self.tracked_runtimes
+        if let Some(transition) = self
+            .tracked_runtimes
             .record_heartbeat(&runtime_status_update_request)
-            .await;
+            .await
+        {
+            transition.publish(&self.nats_client).await;
+        }
 
         log::debug!(
             "Received runtime status update runtime_identifier={}",
@@ -3,7 +3,8 @@
 //!
 //! Forwarding is currently disabled pending #360 — the tick still runs and
 //! computes the updates so the logic stays exercised, it just doesn't send
-//! them anywhere yet.
+//! them anywhere yet. The transitions are published on NATS regardless, and
+//! each tick refreshes the per-status gauge and records heartbeat ages.
 
 use std::{
     sync::Arc,
@@ -11,16 +12,29 @@ use std::{
 };
 
 use tokio::sync::Mutex;
+use tucana::shared::module_status::StatusVariant;
 
-use crate::sagittarius::runtime_status_service_client_impl::SagittariusRuntimeStatusServiceClient;
+use crate::{
+    sagittarius::runtime_status_service_client_impl::SagittariusRuntimeStatusServiceClient,
+    telemetry::metrics,
+};
 
 use super::registry::TrackedRuntimeRegistry;
 
+const REPORTED_STATUSES: [StatusVariant; 5] = [
+    StatusVariant::Unknown,
+    StatusVariant::NotResponding,
+    StatusVariant::NotReady,
+    StatusVariant::Running,
+    StatusVariant::Stopped,
+];
+
 /// Spawns the periodic timeout check. Runs until the process exits; there is
 /// no cancellation handle because the server itself never tears this down.
 pub(super) fn spawn(
     registry: TrackedRuntimeRegistry,
     _client: Arc<Mutex<SagittariusRuntimeStatusServiceClient>>,
+    nats_client: async_nats::Client,
     not_responding_after: Duration,
     stopped_after_not_responding: Duration,
     monitor_interval: Duration,
@@ -40,14 +54,40 @@ pub(super) fn spawn(
                 )
                 .await;
 
+            report_metrics(&registry).await;
+
             if timeout_updates.is_empty() {
                 continue;
             }
 
+            for timeout_update in &timeout_updates {
+                timeout_update.transition.publish(&nats_client).await;
+            }
+
             let mut client = _client.lock().await;
             for timeout_update in timeout_updates {
-                let _ = client.update_runtime_status(timeout_update).await;
+                let _ = client.update_runtime_status(timeout_update.request).await;
             }
         }
     });
 }
+
+/// Records how many runtimes are in each status (including zero counts, so
+/// a status nobody is in anymore drops back down) and every runtime's time
+/// since its last heartbeat.
+async fn report_metrics(registry: &TrackedRuntimeRegistry) {
+    let runtimes = registry.snapshot().await;
+
+    for status in REPORTED_STATUSES {
+        let name = status.as_str_name();
+        let count = runtimes
+            .iter()
+            .filter(|runtime| runtime.status == name)
+            .count();
+        metrics::runtime_statuses(name, count as u64);
+    }
+
+    for runtime in &runtimes {
+        metrics::runtime_heartbeat_age(runtime.last_seen_secs_ago as f64);
+    }
+}
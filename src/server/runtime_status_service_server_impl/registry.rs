@@ -1,7 +1,8 @@
 //! Tracks the last known status of every runtime that has sent a heartbeat,
 //! and derives NOT_RESPONDING / STOPPED transitions from how long a runtime
 //! has gone silent. This is pure bookkeeping — actually forwarding those
-//! transitions to Sagittarius is [`super::monitor`]'s job.
+//! transitions to Sagittarius and publishing them is [`super::monitor`]'s
+//! and the RPC handler's job.
 
 use std::{
     collections::HashMap,
@@ -9,9 +10,14 @@ use std::{
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use serde::Serialize;
 use tokio::sync::Mutex;
 use tucana::{aquila::RuntimeStatusUpdateRequest, shared::ModuleStatus, shared::module_status};
 
+use crate::telemetry::metrics;
+
+use super::transition::{StatusTransition, TransitionCause};
+
 #[derive(Clone)]
 pub(super) struct RuntimeStatusSnapshot {
     status: ModuleStatus,
@@ -38,8 +44,13 @@ impl RuntimeStatusSnapshot {
         &self.status.identifier
     }
 
+    fn variant(&self) -> module_status::StatusVariant {
+        module_status::StatusVariant::try_from(self.status.status)
+            .unwrap_or(module_status::StatusVariant::Unknown)
+    }
+
     fn is_stopped(&self) -> bool {
-        self.status.status == module_status::StatusVariant::Stopped as i32
+        self.variant() == module_status::StatusVariant::Stopped
     }
 
     fn not_responding_update(&self) -> RuntimeStatusUpdateRequest {
@@ -71,31 +82,88 @@ struct TrackedRuntime {
     not_responding_since: Option<Instant>,
 }
 
+impl TrackedRuntime {
+    /// The status last reported, or NOT_RESPONDING once flagged as silent.
+    fn effective_status(&self) -> module_status::StatusVariant {
+        if self.not_responding_since.is_some() {
+            module_status::StatusVariant::NotResponding
+        } else {
+            self.last_status.variant()
+        }
+    }
+
+    fn transition_to(
+        &self,
+        to: module_status::StatusVariant,
+        cause: TransitionCause,
+    ) -> StatusTransition {
+        StatusTransition {
+            identifier: self.last_status.identifier().to_string(),
+            from: Some(self.effective_status()),
+            to,
+            cause,
+        }
+    }
+}
+
+/// A timeout-derived status update for Sagittarius together with the
+/// transition it represents.
+pub(super) struct TimeoutUpdate {
+    pub(super) request: RuntimeStatusUpdateRequest,
+    pub(super) transition: StatusTransition,
+}
+
+/// A tracked runtime as reported by the admin API.
+#[derive(Debug, Serialize)]
+pub struct TrackedRuntimeSnapshot {
+    pub identifier: String,
+    pub status: &'static str,
+    pub last_seen_secs_ago: u64,
+    /// Set once a timeout tick has flagged the runtime as NOT_RESPONDING.
+    pub not_responding_for_secs: Option<u64>,
+}
+
 /// Shared, lock-protected map of runtime identifier to its last known status.
 #[derive(Clone, Default)]
-pub(super) struct TrackedRuntimeRegistry {
+pub struct TrackedRuntimeRegistry {
     tracked: Arc<Mutex<HashMap<String, TrackedRuntime>>>,
 }
 
 impl TrackedRuntimeRegistry {
     /// Records a heartbeat, or drops tracking entirely if the runtime
     /// reports itself STOPPED — a stopped runtime should not later be
-    /// timed out as if it had gone silent.
-    pub(super) async fn record_heartbeat(&self, request: &RuntimeStatusUpdateRequest) {
+    /// timed out as if it had gone silent. Returns the transition if the
+    /// runtime is new or its status differs from the one tracked so far.
+    pub(super) async fn record_heartbeat(
+        &self,
+        request: &RuntimeStatusUpdateRequest,
+    ) -> Option<StatusTransition> {
         let Some(snapshot) = RuntimeStatusSnapshot::from_update(request) else {
             log::debug!(
                 "Skipping runtime heartbeat tracking because status payload is missing or identifier is empty."
             );
-            return;
+            return None;
         };
 
         let key = snapshot.key();
         let now = Instant::now();
+        let reported = snapshot.variant();
         let mut tracked = self.tracked.lock().await;
 
+        let transition = match tracked.get(&key) {
+            Some(runtime) if runtime.effective_status() == reported => None,
+            Some(runtime) => Some(runtime.transition_to(reported, TransitionCause::Heartbeat)),
+            None => Some(StatusTransition {
+                identifier: snapshot.identifier().to_string(),
+                from: None,
+                to: reported,
+                cause: TransitionCause::Heartbeat,
+            }),
+        };
+
         if snapshot.is_stopped() {
             tracked.remove(&key);
-            return;
+            return transition;
         }
 
         match tracked.get_mut(&key) {
@@ -106,6 +174,10 @@ impl TrackedRuntimeRegistry {
                         snapshot.identifier()
                     );
                 }
+                metrics::runtime_heartbeat_interval(
+                    snapshot.identifier(),
+                    now.duration_since(runtime.last_seen).as_secs_f64(),
+                );
                 runtime.last_seen = now;
                 runtime.last_status = snapshot;
                 runtime.not_responding_since = None;
@@ -121,6 +193,31 @@ impl TrackedRuntimeRegistry {
                 );
             }
         }
+
+        transition
+    }
+
+    /// Every tracked runtime with the status it last reported, or
+    /// NOT_RESPONDING once it has been flagged as silent.
+    pub async fn snapshot(&self) -> Vec<TrackedRuntimeSnapshot> {
+        let now = Instant::now();
+        let mut snapshots: Vec<_> = self
+            .tracked
+            .lock()
+            .await
+            .values()
+            .map(|runtime| TrackedRuntimeSnapshot {
+                identifier: runtime.last_status.identifier().to_string(),
+                status: runtime.effective_status().as_str_name(),
+                last_seen_secs_ago: now.duration_since(runtime.last_seen).as_secs(),
+                not_responding_for_secs: runtime
+                    .not_responding_since
+                    .map(|since| now.duration_since(since).as_secs()),
+            })
+            .collect();
+
+        snapshots.sort_by(|left, right| left.identifier.cmp(&right.identifier));
+        snapshots
     }
 
     /// Walks every tracked runtime and returns the status updates implied by
@@ -131,7 +228,7 @@ impl TrackedRuntimeRegistry {
         now: Instant,
         not_responding_after: Duration,
         stopped_after_not_responding: Duration,
-    ) -> Vec<RuntimeStatusUpdateRequest> {
+    ) -> Vec<TimeoutUpdate> {
         let mut tracked = self.tracked.lock().await;
         collect_timeout_updates(
             &mut tracked,
@@ -147,7 +244,7 @@ fn collect_timeout_updates(
     now: Instant,
     not_responding_after: Duration,
     stopped_after_not_responding: Duration,
-) -> Vec<RuntimeStatusUpdateRequest> {
+) -> Vec<TimeoutUpdate> {
     let mut timeout_updates = Vec::new();
     let mut runtimes_to_remove = Vec::new();
 
@@ -161,8 +258,15 @@ fn collect_timeout_updates(
                     runtime.last_status.identifier(),
                     silence
                 );
+                let transition = runtime.transition_to(
+                    module_status::StatusVariant::NotResponding,
+                    TransitionCause::Timeout,
+                );
                 runtime.not_responding_since = Some(now);
-                timeout_updates.push(runtime.last_status.not_responding_update());
+                timeout_updates.push(TimeoutUpdate {
+                    request: runtime.last_status.not_responding_update(),
+                    transition,
+                });
             }
             Some(since) if now.duration_since(since) >= stopped_after_not_responding => {
                 log::warn!(
@@ -170,7 +274,13 @@ fn collect_timeout_updates(
                     runtime.last_status.identifier(),
                     now.duration_since(since)
                 );
-                timeout_updates.push(runtime.last_status.stopped_update());
+                timeout_updates.push(TimeoutUpdate {
+                    request: runtime.last_status.stopped_update(),
+                    transition: runtime.transition_to(
+                        module_status::StatusVariant::Stopped,
+                        TransitionCause::Timeout,
+                    ),
+                });
                 runtimes_to_remove.push(key.clone());
             }
             _ => {}
@@ -193,3 +303,66 @@ fn epoch_seconds_now() -> i64 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use module_status::StatusVariant;
+
+    use super::*;
+
+    fn heartbeat(identifier: &str, status: StatusVariant) -> RuntimeStatusUpdateRequest {
+        RuntimeStatusUpdateRequest {
+            status: Some(ModuleStatus {
+                identifier: identifier.to_string(),
+                status: status as i32,
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn heartbeats_report_only_status_changes() {
+        let registry = TrackedRuntimeRegistry::default();
+
+        let first =
+            block_on(registry.record_heartbeat(&heartbeat("taurus", StatusVariant::NotReady)))
+                .expect("new runtime is a transition");
+        assert_eq!(first.from, None);
+        assert_eq!(first.to, StatusVariant::NotReady);
+
+        let running =
+            block_on(registry.record_heartbeat(&heartbeat("taurus", StatusVariant::Running)))
+                .expect("status change is a transition");
+        assert_eq!(running.from, Some(StatusVariant::NotReady));
+        assert_eq!(running.cause, TransitionCause::Heartbeat);
+
+        assert!(
+            block_on(registry.record_heartbeat(&heartbeat("taurus", StatusVariant::Running)))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn timeouts_report_not_responding_then_stopped() {
+        let registry = TrackedRuntimeRegistry::default();
+        block_on(registry.record_heartbeat(&heartbeat("taurus", StatusVariant::Running)));
+
+        let timeout = Duration::from_secs(10);
+        let now = Instant::now() + timeout;
+        let updates = block_on(registry.collect_timeout_updates(now, timeout, timeout));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].transition.from, Some(StatusVariant::Running));
+        assert_eq!(updates[0].transition.to, StatusVariant::NotResponding);
+        assert_eq!(updates[0].transition.cause, TransitionCause::Timeout);
+
+        let updates = block_on(registry.collect_timeout_updates(now + timeout, timeout, timeout));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(
+            updates[0].transition.from,
+            Some(StatusVariant::NotResponding)
+        );
+        assert_eq!(updates[0].transition.to, StatusVariant::Stopped);
+        assert!(block_on(registry.snapshot()).is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+//! Runtime status transitions as seen by the rest of the system: every
+//! change the registry detects (a runtime appearing, reporting a different
+//! status, going silent or stopping) is counted and published on NATS under
+//! `aquila.runtime.status.<identifier>` so other components can react
+//! without polling Sagittarius.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tucana::shared::module_status::StatusVariant;
+
+use crate::telemetry::{errors, metrics};
+
+const SUBJECT_PREFIX: &str = "aquila.runtime.status";
+
+/// What caused a transition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum TransitionCause {
+    /// The runtime reported the new status itself.
+    Heartbeat,
+    /// The monitor derived it from the runtime going silent.
+    Timeout,
+}
+
+impl TransitionCause {
+    fn as_str(self) -> &'static str {
+        match self {
+            TransitionCause::Heartbeat => "heartbeat",
+            TransitionCause::Timeout => "timeout",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct StatusTransition {
+    pub(super) identifier: String,
+    /// `None` when the runtime wasn't tracked before.
+    pub(super) from: Option<StatusVariant>,
+    pub(super) to: StatusVariant,
+    pub(super) cause: TransitionCause,
+}
+
+/// The JSON payload published for each transition.
+#[derive(Serialize)]
+struct StatusTransitionEvent<'a> {
+    identifier: &'a str,
+    from: Option<&'static str>,
+    to: &'static str,
+    cause: &'static str,
+    timestamp_unix_ms: u128,
+}
+
+impl StatusTransition {
+    /// Records the transition metric and publishes the transition event.
+    /// A failed publish is recorded and otherwise ignored; the transition
+    /// itself has already happened.
+    pub(super) async fn publish(&self, nats_client: &async_nats::Client) {
+        metrics::runtime_status_transition(
+            &self.identifier,
+            self.to.as_str_name(),
+            self.cause.as_str(),
+        );
+
+        let event = StatusTransitionEvent {
+            identifier: &self.identifier,
+            from: self.from.map(|status| status.as_str_name()),
+            to: self.to.as_str_name(),
+            cause: self.cause.as_str(),
+            timestamp_unix_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_millis())
+                .unwrap_or_default(),
+        };
+        let payload = match serde_json::to_vec(&event) {
+            Ok(payload) => payload,
+            Err(err) => {
+                errors::record(
+                    "messaging",
+                    "runtime_status.transition.encode",
+                    &err,
+                    format!("runtime_identifier={}", self.identifier),
+                );
+                return;
+            }
+        };
+
+        let subject = subject(&self.identifier);
+        if let Err(err) = nats_client.publish(subject.clone(), payload.into()).await {
+            errors::record(
+                "messaging",
+                "runtime_status.transition.publish",
+                &err,
+                format!("runtime_identifier={} subject={}", self.identifier, subject),
+            );
+        }
+    }
+}
+
+/// Builds the subject for a runtime. Characters that would split or
+/// wildcard the subject are replaced, so every runtime stays exactly one
+/// token below the prefix; the payload carries the identifier unchanged.
+fn subject(identifier: &str) -> String {
+    let token: String = identifier
+        .chars()
+        .map(|character| match character {
+            '.' | '*' | '>' => '_',
+            character if character.is_whitespace() => '_',
+            character => character,
+        })
+        .collect();
+    format!("{SUBJECT_PREFIX}.{token}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subject_keeps_identifier_in_one_token() {
+        assert_eq!(subject("taurus-1"), "aquila.runtime.status.taurus-1");
+        assert_eq!(
+            subject("eu.taurus *1>"),
+            "aquila.runtime.status.eu_taurus__1_"
+        );
+    }
+}
@@ -3,33 +3,65 @@
 //! or report runtime status to.
 
 use crate::{
-    configuration::{config::Config, service::ServiceConfiguration, state::AppReadiness},
+    configuration::{
+        config::{Config, OutputValidationMode},
+        module_configurations::LatestModuleConfigurations,
+        service::ServiceConfiguration,
+        state::AppReadiness,
+    },
+    flow::{
+        dead_letters::DeadLetters, dispatch::ExecutionDispatcher, executions::ExecutionRegistry,
+    },
     server::{
+        Drain,
         action_transfer::{
-            ActionFlowExecutionRegistry, ActionTransferContext, AquilaActionTransferServiceServer,
+            ActionConnectionRegistry, ActionFlowExecutionRegistry, ActionSessionRegistry,
+            ActionTransferContext, AquilaActionTransferServiceServer, ConfigurationRetry,
+            ConfigurationVersions, EventKeys, ModuleUpdateCache,
         },
         create_readiness_interceptor,
+        diagnostics::ServerDiagnostics,
+        health::{self, Dependencies},
+        keepalive::Keepalive,
     },
 };
 use async_nats::jetstream::kv::Store;
 use log::info;
-use std::{net::SocketAddr, sync::Arc};
-use tonic::transport::Server;
-use tucana::aquila::action_transfer_service_server::ActionTransferServiceServer;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tucana::aquila::action_transfer_service_server::{self, ActionTransferServiceServer};
 
 pub struct AquilaStaticServer {
-    nats_url: String,
     address: SocketAddr,
     with_health_service: bool,
+    keepalive: Keepalive,
     app_readiness: AppReadiness,
     service_configuration: ServiceConfiguration,
     nats_client: async_nats::Client,
     kv_store: Arc<Store>,
     action_config_tx: tokio::sync::broadcast::Sender<tucana::shared::ModuleConfigurations>,
     action_flow_tx: tokio::sync::broadcast::Sender<crate::flow::FlowChange>,
+    drain: Drain,
+    action_execution_timeout: Duration,
+    configuration_retry: ConfigurationRetry,
+    event_result_timeout: Duration,
+    event_fan_out_limit: usize,
+    event_max_in_flight: usize,
+    event_max_queued: usize,
+    event_keys: EventKeys,
+    dead_letters: DeadLetters,
+    dispatcher: ExecutionDispatcher,
+    output_validation: OutputValidationMode,
+    connections: ActionConnectionRegistry,
+    sessions: ActionSessionRegistry,
+    // Static mode has no ExecutionService for a runtime to report results
+    // to, so an action-triggered flow execution can never resolve here -
+    // this registry only exists to satisfy the shared context shape.
+    flow_execution_registry: ActionFlowExecutionRegistry,
+    executions: ExecutionRegistry,
 }
 
 impl AquilaStaticServer {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: &Config,
         app_readiness: AppReadiness,
@@ -38,6 +70,11 @@ impl AquilaStaticServer {
         kv_store: Arc<Store>,
         action_config_tx: tokio::sync::broadcast::Sender<tucana::shared::ModuleConfigurations>,
         action_flow_tx: tokio::sync::broadcast::Sender<crate::flow::FlowChange>,
+        event_keys: EventKeys,
+        dead_letters: DeadLetters,
+        dispatcher: ExecutionDispatcher,
+        executions: ExecutionRegistry,
+        drain: Drain,
     ) -> Self {
         let address = match format!("{}:{}", config.grpc.host, config.grpc.port).parse() {
             Ok(addr) => {
@@ -48,8 +85,8 @@ impl AquilaStaticServer {
         };
 
         AquilaStaticServer {
-            nats_url: config.nats.url.clone(),
             with_health_service: config.grpc.health_service,
+            keepalive: Keepalive::from_config(&config.grpc),
             address,
             app_readiness,
             service_configuration,
@@ -57,6 +94,35 @@ impl AquilaStaticServer {
             kv_store,
             action_config_tx,
             action_flow_tx,
+            drain,
+            action_execution_timeout: Duration::from_secs(config.action_execution.timeout_secs),
+            configuration_retry: ConfigurationRetry::from_config(&config.action_updates),
+            event_result_timeout: Duration::from_secs(config.action_events.result_timeout_secs),
+            event_fan_out_limit: config.action_events.fan_out(),
+            event_max_in_flight: config.action_events.in_flight(),
+            event_max_queued: config.action_events.max_queued,
+            event_keys,
+            dead_letters,
+            dispatcher,
+            output_validation: config.output_validation.mode,
+            connections: ActionConnectionRegistry::new(),
+            sessions: ActionSessionRegistry::new(Duration::from_secs(
+                config.action_session.resume_grace_period_secs,
+            )),
+            flow_execution_registry: ActionFlowExecutionRegistry::new(
+                executions.clone(),
+                Duration::from_secs(config.action_execution.flow_timeout_secs),
+            ),
+            executions,
+        }
+    }
+
+    /// Handles onto the server's in-memory bookkeeping for the admin API.
+    pub fn diagnostics(&self) -> ServerDiagnostics {
+        ServerDiagnostics {
+            connections: self.connections.clone(),
+            executions: self.executions.clone(),
+            runtimes: None,
         }
     }
 
@@ -67,13 +133,28 @@ impl AquilaStaticServer {
                 kv: self.kv_store.as_ref().clone(),
                 actions: self.service_configuration.clone(),
                 module_service: None,
+                module_updates: ModuleUpdateCache::new(None, Duration::ZERO),
                 action_config_tx: self.action_config_tx.clone(),
                 action_flow_tx: self.action_flow_tx.clone(),
-                // Static mode has no ExecutionService for a runtime to report results
-                // to, so an action-triggered flow execution can never resolve here -
-                // this registry only exists to satisfy the shared context shape.
-                flow_execution_registry: ActionFlowExecutionRegistry::new(),
+                // Static mode re-syncs from the service configuration instead.
+                module_configurations: LatestModuleConfigurations::new(None),
+                flow_execution_registry: self.flow_execution_registry.clone(),
+                executions: self.executions.clone(),
+                connections: self.connections.clone(),
+                sessions: self.sessions.clone(),
+                execution_timeout: self.action_execution_timeout,
+                configuration_retry: self.configuration_retry,
+                configuration_versions: ConfigurationVersions::default(),
+                event_result_timeout: self.event_result_timeout,
+                event_fan_out_limit: self.event_fan_out_limit,
+                event_max_in_flight: self.event_max_in_flight,
+                event_max_queued: self.event_max_queued,
+                output_validation: self.output_validation,
+                event_keys: self.event_keys.clone(),
+                dead_letters: self.dead_letters.clone(),
+                dispatcher: self.dispatcher.clone(),
                 is_static: true,
+                drain: self.drain.clone(),
             });
 
         info!("Starting static gRPC Server...");
@@ -83,25 +164,34 @@ impl AquilaStaticServer {
 
         if self.with_health_service {
             info!("Starting with HealthService");
-            let health_service = code0_flow::flow_health::HealthService::new(self.nats_url.clone());
+            let health_server = health::serve(
+                vec![(
+                    action_transfer_service_server::SERVICE_NAME,
+                    Dependencies::Messaging,
+                )],
+                self.app_readiness.clone(),
+                self.nats_client.clone(),
+                self.kv_store.as_ref().clone(),
+                self.drain.clone(),
+            );
 
-            Server::builder()
-                .add_service(tonic_health::pb::health_server::HealthServer::new(
-                    health_service,
-                ))
+            self.keepalive
+                .server()
+                .add_service(health_server)
                 .add_service(ActionTransferServiceServer::with_interceptor(
                     action_transfer_server,
                     intercept.clone(),
                 ))
-                .serve(self.address)
+                .serve_with_shutdown(self.address, self.drain.closed())
                 .await
         } else {
-            Server::builder()
+            self.keepalive
+                .server()
                 .add_service(ActionTransferServiceServer::with_interceptor(
                     action_transfer_server,
                     intercept.clone(),
                 ))
-                .serve(self.address)
+                .serve_with_shutdown(self.address, self.drain.closed())
                 .await
         }
     }
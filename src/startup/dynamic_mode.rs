@@ -3,13 +3,20 @@
 //! execution), and Aquila's own heartbeat to Sagittarius all run as
 //! separate tasks supervised by a single `select!` — if any one of them
 //! exits or panics, the others are aborted and Aquila shuts down rather
-//! than continuing in a partially working state.
+//! than continuing in a partially working state. A shutdown signal instead
+//! drains action streams first (see [`Drain`]) and only then stops the
+//! server and the Sagittarius streams.
 
 use async_nats::Client;
 
 use crate::{
+    admin::{self, AdminState},
     configuration::{
-        config::Config as AquilaConfig, service::ServiceConfiguration, state::AppReadiness,
+        config::Config as AquilaConfig, module_configurations::LatestModuleConfigurations,
+        service::ServiceConfiguration, state::AppReadiness,
+    },
+    flow::{
+        dead_letters::DeadLetters, dispatch::ExecutionDispatcher, executions::ExecutionRegistry,
     },
     sagittarius::{
         flow_service_client_impl::SagittariusFlowClient,
@@ -17,15 +24,26 @@ use crate::{
         retry::create_channel_with_retry,
         runtime_status_heartbeat,
         runtime_status_service_client_impl::SagittariusRuntimeStatusServiceClient,
+        stream_state::{
+            EXECUTION_STREAM, FLOW_STREAM, MODULE_CONFIGURATION_STREAM, SagittariusStreamStates,
+        },
         test_execution_client_impl::{
             SagittariusExecutionResponseSender, SagittariusTestExecutionServiceClient,
         },
     },
-    server::dynamic_server::{AquilaDynamicServer, DynamicServerDependencies},
+    server::{
+        Drain,
+        dynamic_server::{AquilaDynamicServer, DynamicServerDependencies},
+    },
+    startup::{bucket_config, open_event_keys, open_optional_bucket, shutdown},
     telemetry::errors,
 };
 use std::{sync::Arc, time::Duration};
 
+/// How long each Sagittarius stream task gets to wind down once the drain
+/// coordinator is closed.
+const SAGITTARIUS_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// Starts the gRPC server plus the flow-sync and test-execution stream
 /// tasks, and blocks until one of them exits, panics, or a shutdown signal
 /// arrives.
@@ -51,10 +69,21 @@ pub async fn run(
     )
     .await;
 
+    let capacity = config.action_updates.channel_capacity();
     let (action_config_tx, _) =
-        tokio::sync::broadcast::channel::<tucana::shared::ModuleConfigurations>(64);
-    let (action_flow_tx, _) = tokio::sync::broadcast::channel::<crate::flow::FlowChange>(64);
-    let execution_response_sender = SagittariusExecutionResponseSender::new();
+        tokio::sync::broadcast::channel::<tucana::shared::ModuleConfigurations>(capacity);
+    let (action_flow_tx, _) = tokio::sync::broadcast::channel::<crate::flow::FlowChange>(capacity);
+    let module_configurations = LatestModuleConfigurations::new(
+        open_optional_bucket(&client, bucket_config(&config.nats.configuration_bucket)).await,
+    );
+    let executions = ExecutionRegistry::new();
+    let execution_response_sender = SagittariusExecutionResponseSender::new(executions.clone());
+    let module_kv_store =
+        open_optional_bucket(&client, bucket_config(&config.nats.module_bucket)).await;
+    let event_keys = open_event_keys(&client, &config).await;
+    let dispatcher = ExecutionDispatcher::open(&client, &config.execution_dispatch).await;
+    let dead_letters = DeadLetters::open(&client, &config.nats, dispatcher.clone()).await;
+    let drain = Drain::new(Duration::from_secs(config.shutdown.drain_timeout_secs));
 
     let server = AquilaDynamicServer::new(
         &config,
@@ -66,8 +95,28 @@ pub async fn run(
             kv_store: kv_store.clone(),
             action_config_tx: action_config_tx.clone(),
             action_flow_tx: action_flow_tx.clone(),
+            module_configurations: module_configurations.clone(),
             execution_response_sender: execution_response_sender.clone(),
+            module_kv_store,
+            event_keys,
+            dead_letters: dead_letters.clone(),
+            dispatcher: dispatcher.clone(),
+            executions,
+            drain: drain.clone(),
+        },
+    );
+
+    let stream_states = SagittariusStreamStates::new();
+    admin::spawn(
+        &config,
+        AdminState {
+            server: server.diagnostics(),
+            kv: kv_store.as_ref().clone(),
+            sagittarius_streams: Some(stream_states.clone()),
+            dead_letters: dead_letters.clone(),
+            config: config.to_string(),
         },
+        drain.clone(),
     );
 
     let mut server_task = tokio::spawn(async move {
@@ -78,13 +127,18 @@ pub async fn run(
         }
     });
 
+    let stream_states_for_test_execution = stream_states.clone();
+    let stream_states_for_flow = stream_states.clone();
+    let stream_states_for_module_configuration = stream_states.clone();
+
     let kv_for_test_execution = kv_store.clone();
     let kv_for_flow = kv_store.clone();
     let backend_url_for_test_execution = config.dynamic_config.backend_url.clone();
     let runtime_token_for_test_execution = config.dynamic_config.backend_token.clone();
     let sagittarius_ready_for_test_execution = app_readiness.sagittarius_ready.clone();
-    let nats_client_for_test_execution = client.clone();
+    let dispatcher_for_test_execution = dispatcher.clone();
     let execution_response_sender_for_test_execution = execution_response_sender.clone();
+    let dead_letters_for_test_execution = dead_letters.clone();
 
     let backend_url_for_flow = config.dynamic_config.backend_url.clone();
     let runtime_token_for_flow = config.dynamic_config.backend_token.clone();
@@ -106,135 +160,180 @@ pub async fn run(
     // Both stream tasks below share the same shape: connect, run the stream
     // until it ends (which `logon`/`init_flow_stream` always eventually do,
     // since Sagittarius connections aren't permanent), then reconnect with
-    // growing backoff. The task itself never exits on its own.
+    // growing backoff. The task itself never exits on its own; it only stops
+    // once the drain coordinator is closed during a graceful shutdown.
+    let drain_for_test_execution = drain.clone();
     let mut test_execution_task = tokio::spawn(async move {
-        let mut backoff = Duration::from_millis(200);
-        let max_backoff = Duration::from_secs(10);
-
-        loop {
-            log::debug!(
-                "Attempting to initialize Sagittarius execution stream backoff_ms={}",
-                backoff.as_millis()
-            );
-            let ch = create_channel_with_retry(
-                "Sagittarius Execution Stream",
-                backend_url_for_test_execution.clone(),
-                sagittarius_ready_for_test_execution.clone(),
-            )
-            .await;
-
-            let mut test_execution_client = SagittariusTestExecutionServiceClient::new(
-                nats_client_for_test_execution.clone(),
-                kv_for_test_execution.clone(),
-                ch,
-                runtime_token_for_test_execution.clone(),
-                execution_response_sender_for_test_execution.clone(),
-            );
-
-            test_execution_client.logon().await;
-            tokio::time::sleep(backoff).await;
-            backoff = std::cmp::min(backoff * 2, max_backoff);
-            log::debug!(
-                "Next execution stream reconnect backoff_ms={}",
-                backoff.as_millis()
-            );
+        let reconnect = async {
+            let mut backoff = Duration::from_millis(200);
+            let max_backoff = Duration::from_secs(10);
+
+            loop {
+                log::debug!(
+                    "Attempting to initialize Sagittarius execution stream backoff_ms={}",
+                    backoff.as_millis()
+                );
+                stream_states_for_test_execution.connecting(EXECUTION_STREAM);
+                let ch = create_channel_with_retry(
+                    "Sagittarius Execution Stream",
+                    backend_url_for_test_execution.clone(),
+                    sagittarius_ready_for_test_execution.clone(),
+                )
+                .await;
+
+                let mut test_execution_client = SagittariusTestExecutionServiceClient::new(
+                    dispatcher_for_test_execution.clone(),
+                    kv_for_test_execution.clone(),
+                    ch,
+                    runtime_token_for_test_execution.clone(),
+                    execution_response_sender_for_test_execution.clone(),
+                    dead_letters_for_test_execution.clone(),
+                );
+
+                test_execution_client
+                    .logon(&stream_states_for_test_execution)
+                    .await;
+                stream_states_for_test_execution.disconnected(EXECUTION_STREAM, None);
+                tokio::time::sleep(backoff).await;
+                backoff = std::cmp::min(backoff * 2, max_backoff);
+                log::debug!(
+                    "Next execution stream reconnect backoff_ms={}",
+                    backoff.as_millis()
+                );
+            }
+        };
+
+        tokio::select! {
+            _ = drain_for_test_execution.closed() => {
+                log::info!("Closed Sagittarius execution stream");
+            }
+            _ = reconnect => {}
         }
     });
 
+    let drain_for_flow = drain.clone();
     let mut flow_task = tokio::spawn(async move {
-        let mut backoff = Duration::from_millis(200);
-        let max_backoff = Duration::from_secs(10);
-
-        loop {
-            log::debug!(
-                "Attempting to initialize Sagittarius flow stream backoff_ms={}",
-                backoff.as_millis()
-            );
-            let ch = create_channel_with_retry(
-                "Sagittarius Stream",
-                backend_url_for_flow.clone(),
-                sagittarius_ready_for_flow.clone(),
-            )
-            .await;
-
-            let mut flow_client = SagittariusFlowClient::new(
-                kv_for_flow.clone(),
-                env.clone(),
-                runtime_token_for_flow.clone(),
-                flow_export_path_for_flow.clone(),
-                ch,
-                sagittarius_ready_for_flow.clone(),
-                action_flow_tx_for_flow.clone(),
-            );
-
-            match flow_client.init_flow_stream().await {
-                Ok(_) => {
-                    log::warn!(
-                        "Sagittarius flow synchronization stream ended normally; reconnecting"
-                    );
-                }
-                Err(e) => {
-                    log::warn!(
-                        "Sagittarius flow synchronization stream dropped; reconnecting error={:?}",
-                        e
-                    );
+        let reconnect = async {
+            let mut backoff = Duration::from_millis(200);
+            let max_backoff = Duration::from_secs(10);
+
+            loop {
+                log::debug!(
+                    "Attempting to initialize Sagittarius flow stream backoff_ms={}",
+                    backoff.as_millis()
+                );
+                stream_states_for_flow.connecting(FLOW_STREAM);
+                let ch = create_channel_with_retry(
+                    "Sagittarius Stream",
+                    backend_url_for_flow.clone(),
+                    sagittarius_ready_for_flow.clone(),
+                )
+                .await;
+
+                let mut flow_client = SagittariusFlowClient::new(
+                    kv_for_flow.clone(),
+                    env.clone(),
+                    runtime_token_for_flow.clone(),
+                    flow_export_path_for_flow.clone(),
+                    ch,
+                    sagittarius_ready_for_flow.clone(),
+                    action_flow_tx_for_flow.clone(),
+                );
+
+                match flow_client.init_flow_stream(&stream_states_for_flow).await {
+                    Ok(_) => {
+                        stream_states_for_flow.disconnected(FLOW_STREAM, None);
+                        log::warn!(
+                            "Sagittarius flow synchronization stream ended normally; reconnecting"
+                        );
+                    }
+                    Err(e) => {
+                        stream_states_for_flow.disconnected(FLOW_STREAM, Some(format!("{e:?}")));
+                        log::warn!(
+                            "Sagittarius flow synchronization stream dropped; reconnecting error={:?}",
+                            e
+                        );
+                    }
                 }
+
+                tokio::time::sleep(backoff).await;
+                backoff = std::cmp::min(backoff * 2, max_backoff);
+                log::debug!(
+                    "Next flow stream reconnect backoff_ms={}",
+                    backoff.as_millis()
+                );
             }
+        };
 
-            tokio::time::sleep(backoff).await;
-            backoff = std::cmp::min(backoff * 2, max_backoff);
-            log::debug!(
-                "Next flow stream reconnect backoff_ms={}",
-                backoff.as_millis()
-            );
+        tokio::select! {
+            _ = drain_for_flow.closed() => {
+                log::info!("Closed Sagittarius flow stream");
+            }
+            _ = reconnect => {}
         }
     });
 
+    let drain_for_module_configuration = drain.clone();
     let mut module_configuration_task = tokio::spawn(async move {
-        let mut backoff = Duration::from_millis(200);
-        let max_backoff = Duration::from_secs(10);
-
-        loop {
-            log::debug!(
-                "Attempting to initialize Sagittarius module configuration stream backoff_ms={}",
-                backoff.as_millis()
-            );
-            let ch = create_channel_with_retry(
-                "Sagittarius Module Configuration Stream",
-                backend_url_for_module_configuration.clone(),
-                sagittarius_ready_for_module_configuration.clone(),
-            )
-            .await;
-
-            let mut module_configuration_client = SagittariusModuleConfigurationClient::new(
-                ch,
-                runtime_token_for_module_configuration.clone(),
-                action_config_tx_for_module_configuration.clone(),
-            );
-
-            match module_configuration_client
-                .init_configuration_stream()
-                .await
-            {
-                Ok(_) => {
-                    log::warn!(
-                        "Sagittarius module configuration stream ended normally; reconnecting"
-                    );
-                }
-                Err(e) => {
-                    log::warn!(
-                        "Sagittarius module configuration stream dropped; reconnecting error={:?}",
-                        e
-                    );
+        let reconnect = async {
+            let mut backoff = Duration::from_millis(200);
+            let max_backoff = Duration::from_secs(10);
+
+            loop {
+                log::debug!(
+                    "Attempting to initialize Sagittarius module configuration stream backoff_ms={}",
+                    backoff.as_millis()
+                );
+                stream_states_for_module_configuration.connecting(MODULE_CONFIGURATION_STREAM);
+                let ch = create_channel_with_retry(
+                    "Sagittarius Module Configuration Stream",
+                    backend_url_for_module_configuration.clone(),
+                    sagittarius_ready_for_module_configuration.clone(),
+                )
+                .await;
+
+                let mut module_configuration_client = SagittariusModuleConfigurationClient::new(
+                    ch,
+                    runtime_token_for_module_configuration.clone(),
+                    action_config_tx_for_module_configuration.clone(),
+                    module_configurations.clone(),
+                );
+
+                match module_configuration_client
+                    .init_configuration_stream(&stream_states_for_module_configuration)
+                    .await
+                {
+                    Ok(_) => {
+                        stream_states_for_module_configuration
+                            .disconnected(MODULE_CONFIGURATION_STREAM, None);
+                        log::warn!(
+                            "Sagittarius module configuration stream ended normally; reconnecting"
+                        );
+                    }
+                    Err(e) => {
+                        stream_states_for_module_configuration
+                            .disconnected(MODULE_CONFIGURATION_STREAM, Some(format!("{e:?}")));
+                        log::warn!(
+                            "Sagittarius module configuration stream dropped; reconnecting error={:?}",
+                            e
+                        );
+                    }
                 }
+
+                tokio::time::sleep(backoff).await;
+                backoff = std::cmp::min(backoff * 2, max_backoff);
+                log::debug!(
+                    "Next module configuration stream reconnect backoff_ms={}",
+                    backoff.as_millis()
+                );
             }
+        };
 
-            tokio::time::sleep(backoff).await;
-            backoff = std::cmp::min(backoff * 2, max_backoff);
-            log::debug!(
-                "Next module configuration stream reconnect backoff_ms={}",
-                backoff.as_millis()
-            );
+        tokio::select! {
+            _ = drain_for_module_configuration.closed() => {
+                log::info!("Closed Sagittarius module configuration stream");
+            }
+            _ = reconnect => {}
         }
     });
 
@@ -319,19 +418,19 @@ pub async fn run(
         }
         _ = tokio::signal::ctrl_c() => {
             log::info!("Ctrl+C/Exit signal received, shutting down");
-            server_task.abort();
-            flow_task.abort();
-            module_configuration_task.abort();
-            test_execution_task.abort();
+            shutdown::drain_and_close(&drain, &mut server_task, &client, "dynamic").await;
             heartbeat_task.abort();
+            shutdown::await_closed("Sagittarius flow stream", &mut flow_task, SAGITTARIUS_CLOSE_TIMEOUT).await;
+            shutdown::await_closed("Sagittarius module configuration stream", &mut module_configuration_task, SAGITTARIUS_CLOSE_TIMEOUT).await;
+            shutdown::await_closed("Sagittarius execution stream", &mut test_execution_task, SAGITTARIUS_CLOSE_TIMEOUT).await;
         }
         _ = sigterm => {
             log::info!("SIGTERM received, shutting down");
-            server_task.abort();
-            flow_task.abort();
-            module_configuration_task.abort();
-            test_execution_task.abort();
+            shutdown::drain_and_close(&drain, &mut server_task, &client, "dynamic").await;
             heartbeat_task.abort();
+            shutdown::await_closed("Sagittarius flow stream", &mut flow_task, SAGITTARIUS_CLOSE_TIMEOUT).await;
+            shutdown::await_closed("Sagittarius module configuration stream", &mut module_configuration_task, SAGITTARIUS_CLOSE_TIMEOUT).await;
+            shutdown::await_closed("Sagittarius execution stream", &mut test_execution_task, SAGITTARIUS_CLOSE_TIMEOUT).await;
         }
     }
 
@@ -3,13 +3,22 @@
 //! [`AquilaConfig::is_static`].
 
 pub mod dynamic_mode;
+mod shutdown;
 pub mod static_mode;
 
-use crate::configuration::{
-    config::Config as AquilaConfig, service::ServiceConfiguration, state::AppReadiness,
+use crate::{
+    configuration::{
+        config::Config as AquilaConfig, service::ServiceConfiguration, state::AppReadiness,
+    },
+    server::EventKeys,
+    telemetry::errors,
 };
 use async_nats::jetstream::kv::Config;
-use std::sync::Arc;
+use std::{
+    path::PathBuf,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
 
 /// Connects to NATS, ensures the flow KV bucket exists, and starts the
 /// appropriate mode. Panics on any of those failures — none of them are
@@ -19,6 +28,7 @@ pub async fn run(
     config: AquilaConfig,
     app_readiness: AppReadiness,
     service_config: ServiceConfiguration,
+    service_config_path: Option<PathBuf>,
 ) {
     log::info!(
         "Bootstrapping startup mode={} nats_url={} nats_bucket={}",
@@ -38,6 +48,7 @@ pub async fn run(
                 "Aquila messaging dependency is ready dependency=nats url={}",
                 config.nats.url
             );
+            app_readiness.nats_ready.store(true, Ordering::SeqCst);
             client
         }
         Err(err) => {
@@ -71,6 +82,7 @@ pub async fn run(
                 "Aquila flow store is ready backend=nats_jetstream bucket={}",
                 config.nats.bucket
             );
+            app_readiness.kv_ready.store(true, Ordering::SeqCst);
             Arc::new(kv)
         }
         Err(err) => {
@@ -83,10 +95,105 @@ pub async fn run(
 
     if config.is_static() {
         log::info!("Selected Aquila startup mode mode=static source=local_flow_export");
-        static_mode::run(config, app_readiness, service_config, client, kv_store).await;
+        static_mode::run(
+            config,
+            app_readiness,
+            service_config,
+            service_config_path,
+            client,
+            kv_store,
+        )
+        .await;
         return;
     }
 
     log::info!("Selected Aquila startup mode mode=dynamic source=sagittarius");
     dynamic_mode::run(config, app_readiness, service_config, client, kv_store).await;
 }
+
+/// The configuration of a bucket whose entries never expire.
+fn bucket_config(bucket: &str) -> Config {
+    Config {
+        bucket: bucket.to_owned(),
+        ..Default::default()
+    }
+}
+
+/// The bucket dropping repeated action events, unless the dedupe window is
+/// disabled. Its entries expire once the window has passed.
+async fn open_event_keys(client: &async_nats::Client, config: &AquilaConfig) -> EventKeys {
+    let window = config.action_events.dedupe_window_secs;
+    if window == 0 {
+        return EventKeys::new(None);
+    }
+
+    EventKeys::new(
+        open_optional_bucket(
+            client,
+            Config {
+                max_age: Duration::from_secs(window),
+                ..bucket_config(&config.nats.event_key_bucket)
+            },
+        )
+        .await,
+    )
+}
+
+/// Opens one of the buckets Aquila can run without, creating it with
+/// `bucket_config` if needed, or updating it if its entries expire after a
+/// different age than `bucket_config` asks for: the one remembering which module each action
+/// last had accepted by Sagittarius, the one keeping each module's latest
+/// configuration, or the one keeping recent event idempotency keys. Without
+/// them, every action logon sends its module to Sagittarius, configurations
+/// pushed before a restart are gone, and repeated events aren't dropped.
+async fn open_optional_bucket(
+    client: &async_nats::Client,
+    bucket_config: Config,
+) -> Option<async_nats::jetstream::kv::Store> {
+    let jet_stream = async_nats::jetstream::new(client.clone());
+    let bucket = bucket_config.bucket.clone();
+    if let Ok(store) = jet_stream.get_key_value(&bucket).await {
+        let max_age = store.status().await.map(|status| status.max_age()).ok();
+        if max_age.is_none_or(|max_age| max_age == bucket_config.max_age) {
+            return Some(store);
+        }
+
+        let max_age_secs = bucket_config.max_age.as_secs();
+        return match jet_stream.update_key_value(bucket_config).await {
+            Ok(store) => {
+                log::info!(
+                    "Updated NATS key-value bucket expiry bucket={} max_age_secs={}",
+                    bucket,
+                    max_age_secs
+                );
+                Some(store)
+            }
+            Err(err) => {
+                // Still usable, its entries just expire after the old age.
+                errors::record(
+                    "flow_storage",
+                    "bucket.update",
+                    &err,
+                    format!("bucket={bucket} max_age_secs={max_age_secs}"),
+                );
+                Some(store)
+            }
+        };
+    }
+
+    match jet_stream.create_key_value(bucket_config).await {
+        Ok(store) => {
+            log::debug!("NATS key-value bucket is available bucket={}", bucket);
+            Some(store)
+        }
+        Err(err) => {
+            errors::record(
+                "flow_storage",
+                "bucket.open",
+                &err,
+                format!("bucket={bucket}"),
+            );
+            None
+        }
+    }
+}
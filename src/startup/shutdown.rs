@@ -0,0 +1,69 @@
+//! The graceful shutdown sequence both run modes go through on SIGTERM or
+//! Ctrl+C, instead of aborting the gRPC server mid-stream: drain action
+//! streams (see [`Drain`]), stop the server, then flush whatever is still
+//! buffered for NATS.
+
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use crate::{server::Drain, telemetry::errors};
+
+/// How long the gRPC server gets to finish its remaining connections once
+/// the drain coordinator is closed, before it's aborted outright.
+const SERVER_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Slack on top of the drain deadline for streams that hit it to send their
+/// final status and exit; each stream enforces the deadline itself.
+const STREAM_CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+/// Drains every action stream, stops the gRPC server and flushes NATS.
+/// Returns once all of that is done or has timed out; callers still own
+/// shutting down anything else they started.
+pub(super) async fn drain_and_close(
+    drain: &Drain,
+    server_task: &mut JoinHandle<()>,
+    client: &async_nats::Client,
+    mode: &'static str,
+) {
+    log::info!(
+        "Draining action streams active_streams={} timeout_secs={}",
+        drain.active_streams(),
+        drain.timeout().as_secs()
+    );
+    drain.start();
+
+    if tokio::time::timeout(drain.timeout() + STREAM_CLOSE_GRACE, drain.streams_closed())
+        .await
+        .is_err()
+    {
+        log::warn!(
+            "Action streams still open after drain deadline active_streams={}",
+            drain.active_streams()
+        );
+    }
+
+    drain.close();
+    match tokio::time::timeout(SERVER_CLOSE_TIMEOUT, &mut *server_task).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) if err.is_panic() => {}
+        Ok(Err(err)) => errors::record("task", "grpc.task", &err, format!("mode={mode}")),
+        Err(_) => {
+            log::warn!("gRPC server did not stop in time; aborting");
+            server_task.abort();
+        }
+    }
+
+    if let Err(err) = client.flush().await {
+        errors::record("messaging", "shutdown.flush", &err, format!("mode={mode}"));
+    }
+}
+
+/// Waits for a task that stops on its own once [`Drain::close`] is called,
+/// aborting it if it doesn't within `timeout`.
+pub(super) async fn await_closed(name: &str, task: &mut JoinHandle<()>, timeout: Duration) {
+    if tokio::time::timeout(timeout, &mut *task).await.is_err() {
+        log::warn!("{} did not stop in time; aborting", name);
+        task.abort();
+    }
+}
@@ -1,26 +1,38 @@
 //! Static mode wiring: load a fixed flow export from disk into the KV
 //! store once at startup, then serve the gRPC server with no ongoing
 //! Sagittarius dependency. Readiness is set unconditionally since there's
-//! nothing external left to wait on.
+//! nothing external left to wait on. The service configuration file is
+//! watched, so connected actions are sent their configuration again
+//! whenever it changes.
 
 use crate::{
+    admin::{self, AdminState},
     configuration::{config::Config, service::ServiceConfiguration, state::AppReadiness},
-    flow::get_flow_identifier,
-    server::static_server::AquilaStaticServer,
+    flow::{
+        dead_letters::DeadLetters, dispatch::ExecutionDispatcher, executions::ExecutionRegistry,
+        get_flow_identifier,
+    },
+    server::{Drain, static_server::AquilaStaticServer},
+    startup::{open_event_keys, shutdown},
     telemetry::{errors, metrics},
 };
 use async_nats::Client;
 use prost::Message;
 use serde_json::from_str;
-use std::{fs::File, io::Read, sync::Arc, sync::atomic::Ordering};
-use tucana::shared::Flows;
+use std::{fs::File, io::Read, path::PathBuf, sync::Arc, sync::atomic::Ordering, time::Duration};
+use tokio::sync::broadcast;
+use tucana::shared::{Flows, ModuleConfigurations};
+
+/// How often the service configuration file is checked for changes.
+const SERVICE_CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
 
 /// Loads the fallback flow export and serves the static gRPC server until a
-/// shutdown signal arrives.
+/// shutdown signal arrives, then drains action streams before exiting.
 pub async fn run(
     config: Config,
     app_readiness: AppReadiness,
     service_config: ServiceConfiguration,
+    service_config_path: Option<PathBuf>,
 
     client: Client,
     flow_store_client: Arc<async_nats::jetstream::kv::Store>,
@@ -41,9 +53,23 @@ pub async fn run(
     )
     .await;
 
+    let capacity = config.action_updates.channel_capacity();
     let (action_config_tx, _) =
-        tokio::sync::broadcast::channel::<tucana::shared::ModuleConfigurations>(64);
-    let (action_flow_tx, _) = tokio::sync::broadcast::channel::<crate::flow::FlowChange>(64);
+        tokio::sync::broadcast::channel::<tucana::shared::ModuleConfigurations>(capacity);
+    let (action_flow_tx, _) = tokio::sync::broadcast::channel::<crate::flow::FlowChange>(capacity);
+
+    if let Some(path) = service_config_path {
+        tokio::spawn(watch_service_configuration(
+            path,
+            service_config.clone(),
+            action_config_tx.clone(),
+        ));
+    }
+
+    let event_keys = open_event_keys(&client, &config).await;
+    let dispatcher = ExecutionDispatcher::open(&client, &config.execution_dispatch).await;
+    let dead_letters = DeadLetters::open(&client, &config.nats, dispatcher.clone()).await;
+    let drain = Drain::new(Duration::from_secs(config.shutdown.drain_timeout_secs));
 
     let server = AquilaStaticServer::new(
         &config,
@@ -53,6 +79,23 @@ pub async fn run(
         flow_store_client.clone(),
         action_config_tx.clone(),
         action_flow_tx.clone(),
+        event_keys,
+        dead_letters.clone(),
+        dispatcher,
+        ExecutionRegistry::new(),
+        drain.clone(),
+    );
+
+    admin::spawn(
+        &config,
+        AdminState {
+            server: server.diagnostics(),
+            kv: flow_store_client.as_ref().clone(),
+            sagittarius_streams: None,
+            dead_letters,
+            config: config.to_string(),
+        },
+        drain.clone(),
     );
 
     let mut server_task = tokio::spawn(async move {
@@ -84,17 +127,74 @@ pub async fn run(
         }
         _ = tokio::signal::ctrl_c() => {
             log::info!("Ctrl+C/Exit signal received, shutting down");
-            server_task.abort();
+            shutdown::drain_and_close(&drain, &mut server_task, &client, "static").await;
         }
         _ = sigterm => {
             log::info!("SIGTERM received, shutting down");
-            server_task.abort();
+            shutdown::drain_and_close(&drain, &mut server_task, &client, "static").await;
         }
     }
 
     log::info!("Aquila shutdown complete");
 }
 
+/// Re-reads the service configuration file at `path` every
+/// [`SERVICE_CONFIG_POLL_INTERVAL`] and sends every action configuration
+/// that changed to the connected actions through `action_config_tx`. Only
+/// action configurations are taken over; a file that can't be read or
+/// parsed is reported and otherwise ignored.
+async fn watch_service_configuration(
+    path: PathBuf,
+    service_config: ServiceConfiguration,
+    action_config_tx: broadcast::Sender<ModuleConfigurations>,
+) {
+    let mut last_read = std::fs::read_to_string(&path).ok();
+    let mut interval = tokio::time::interval(SERVICE_CONFIG_POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(error) => {
+                log::debug!(
+                    "Couldn't read service configuration file path={} error={}",
+                    path.display(),
+                    error
+                );
+                continue;
+            }
+        };
+        if last_read.as_deref() == Some(data.as_str()) {
+            continue;
+        }
+        let reloaded = ServiceConfiguration::from_json(&data);
+        last_read = Some(data);
+        let changed = match reloaded {
+            Ok(reloaded) => service_config.update_action_configurations(&reloaded),
+            Err(error) => {
+                errors::record_message(
+                    "configuration",
+                    "service_configuration.reload",
+                    error,
+                    format!("path={}", path.display()),
+                );
+                continue;
+            }
+        };
+
+        log::info!(
+            "Reloaded service configuration path={} changed_actions={}",
+            path.display(),
+            changed.len()
+        );
+        for configurations in changed {
+            // No receivers just means no action is connected right now.
+            let _ = action_config_tx.send(configurations);
+        }
+    }
+}
+
 /// Reads `path` as a JSON [`Flows`] export and stores each flow in the KV
 /// store. Panics on any read/parse failure, since static mode has no flows
 /// to serve without this file and continuing would just push the failure
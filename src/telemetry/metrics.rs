@@ -7,7 +7,7 @@ use std::sync::OnceLock;
 
 use opentelemetry::{
     KeyValue,
-    metrics::{Counter, Histogram, UpDownCounter},
+    metrics::{Counter, Gauge, Histogram, UpDownCounter},
 };
 
 static METRICS: OnceLock<Metrics> = OnceLock::new();
@@ -18,11 +18,22 @@ struct Metrics {
     active_actions: UpDownCounter<i64>,
     action_connection_duration: Histogram<f64>,
     action_events: Counter<u64>,
+    action_event_duplicates: Counter<u64>,
     action_executions: Counter<u64>,
     action_execution_duration: Histogram<f64>,
+    action_in_flight: UpDownCounter<i64>,
     action_results: Counter<u64>,
     action_config_updates: Counter<u64>,
+    action_configuration_version: Gauge<u64>,
+    action_update_lag: Counter<u64>,
     action_failures: Counter<u64>,
+    dead_letters: Counter<u64>,
+    cancelled_executions: Counter<u64>,
+    output_mismatches: Counter<u64>,
+    runtime_statuses: Gauge<u64>,
+    runtime_status_transitions: Counter<u64>,
+    runtime_heartbeat_age: Histogram<f64>,
+    runtime_heartbeat_interval: Histogram<f64>,
 }
 
 /// Registers every metric instrument against the global meter. Must be
@@ -40,16 +51,41 @@ pub fn initialize() {
             .with_unit("s")
             .build(),
         action_events: meter.u64_counter("aquila.action.events").build(),
+        action_event_duplicates: meter.u64_counter("aquila.action.events.duplicates").build(),
         action_executions: meter.u64_counter("aquila.action.executions").build(),
         action_execution_duration: meter
             .f64_histogram("aquila.action.execution.duration")
             .with_unit("s")
             .build(),
+        action_in_flight: meter
+            .i64_up_down_counter("aquila.action.executions.in_flight")
+            .build(),
         action_results: meter.u64_counter("aquila.action.results").build(),
         action_config_updates: meter
             .u64_counter("aquila.action.configuration_updates")
             .build(),
+        action_configuration_version: meter
+            .u64_gauge("aquila.action.configuration.acknowledged_version")
+            .build(),
+        action_update_lag: meter.u64_counter("aquila.action.updates.lagged").build(),
         action_failures: meter.u64_counter("aquila.action.failures").build(),
+        dead_letters: meter.u64_counter("aquila.executions.dead_letters").build(),
+        cancelled_executions: meter.u64_counter("aquila.executions.cancelled").build(),
+        output_mismatches: meter
+            .u64_counter("aquila.executions.output_mismatches")
+            .build(),
+        runtime_statuses: meter.u64_gauge("aquila.runtime.statuses").build(),
+        runtime_status_transitions: meter
+            .u64_counter("aquila.runtime.status.transitions")
+            .build(),
+        runtime_heartbeat_age: meter
+            .f64_histogram("aquila.runtime.heartbeat.age")
+            .with_unit("s")
+            .build(),
+        runtime_heartbeat_interval: meter
+            .f64_histogram("aquila.runtime.heartbeat.interval")
+            .with_unit("s")
+            .build(),
     });
 }
 
@@ -103,6 +139,16 @@ pub fn action_event(identifier: &str) {
     }
 }
 
+/// An action event dropped because it repeated a recent idempotency key.
+pub fn action_event_duplicate(identifier: &str) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.action_event_duplicates.add(
+            1,
+            &[KeyValue::new("action.identifier", identifier.to_owned())],
+        );
+    }
+}
+
 pub fn action_execution(identifier: &str, outcome: &'static str) {
     if let Some(metrics) = METRICS.get() {
         metrics.action_executions.add(
@@ -135,6 +181,16 @@ pub fn action_execution_duration(identifier: &str, seconds: f64) {
     }
 }
 
+/// Executions forwarded to `identifier` gained (`delta > 0`) or lost since
+/// the last report, summed over every connection of the action.
+pub fn action_in_flight(identifier: &str, delta: i64) {
+    if let Some(metrics) = METRICS.get() {
+        metrics
+            .action_in_flight
+            .add(delta, &action_attributes(identifier));
+    }
+}
+
 pub fn action_config_update(identifier: &str, outcome: &'static str) {
     if let Some(metrics) = METRICS.get() {
         metrics.action_config_updates.add(
@@ -147,6 +203,29 @@ pub fn action_config_update(identifier: &str, outcome: &'static str) {
     }
 }
 
+/// The latest configuration version an action acknowledged.
+pub fn action_configuration_version(identifier: &str, version: u64) {
+    if let Some(metrics) = METRICS.get() {
+        metrics
+            .action_configuration_version
+            .record(version, &action_attributes(identifier));
+    }
+}
+
+/// An action's forwarder fell behind the `updates` broadcast (`flow` or
+/// `configuration`) and had to be re-synced.
+pub fn action_update_lag(identifier: &str, updates: &'static str) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.action_update_lag.add(
+            1,
+            &[
+                KeyValue::new("action.identifier", identifier.to_owned()),
+                KeyValue::new("updates", updates),
+            ],
+        );
+    }
+}
+
 pub fn action_failure(identifier: &str, reason: &'static str) {
     if let Some(metrics) = METRICS.get() {
         metrics.action_failures.add(
@@ -158,3 +237,75 @@ pub fn action_failure(identifier: &str, reason: &'static str) {
         );
     }
 }
+
+/// An undeliverable execution recorded in, or re-driven from, the
+/// dead-letter stream.
+pub fn dead_letter(origin: &str, outcome: &'static str) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.dead_letters.add(
+            1,
+            &[
+                KeyValue::new("origin", origin.to_owned()),
+                KeyValue::new("outcome", outcome),
+            ],
+        );
+    }
+}
+
+/// An execution cancelled by the action or Sagittarius that asked for it.
+pub fn execution_cancelled(origin: &'static str) {
+    if let Some(metrics) = METRICS.get() {
+        metrics
+            .cancelled_executions
+            .add(1, &[KeyValue::new("origin", origin)]);
+    }
+}
+
+/// A successful result that didn't match its flow's output schema.
+pub fn output_mismatch(mode: &str) {
+    if let Some(metrics) = METRICS.get() {
+        metrics
+            .output_mismatches
+            .add(1, &[KeyValue::new("mode", mode.to_owned())]);
+    }
+}
+
+pub fn runtime_statuses(status: &'static str, count: u64) {
+    if let Some(metrics) = METRICS.get() {
+        metrics
+            .runtime_statuses
+            .record(count, &[KeyValue::new("status", status)]);
+    }
+}
+
+pub fn runtime_status_transition(identifier: &str, status: &'static str, cause: &'static str) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.runtime_status_transitions.add(
+            1,
+            &[
+                KeyValue::new("runtime.identifier", identifier.to_owned()),
+                KeyValue::new("status", status),
+                KeyValue::new("cause", cause),
+            ],
+        );
+    }
+}
+
+/// How long ago a tracked runtime's last heartbeat arrived, recorded for
+/// every runtime on each monitor tick. Not broken down by runtime, so one
+/// that left the registry doesn't leave a series behind.
+pub fn runtime_heartbeat_age(seconds: f64) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.runtime_heartbeat_age.record(seconds, &[]);
+    }
+}
+
+/// The time between a runtime's heartbeat and the one before it.
+pub fn runtime_heartbeat_interval(identifier: &str, seconds: f64) {
+    if let Some(metrics) = METRICS.get() {
+        metrics.runtime_heartbeat_interval.record(
+            seconds,
+            &[KeyValue::new("runtime.identifier", identifier.to_owned())],
+        );
+    }
+}
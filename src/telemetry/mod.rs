@@ -2,8 +2,10 @@
 //! from the shared `code0_flow` crate, re-exported here so the rest of the
 //! codebase depends on `crate::telemetry` rather than reaching into that
 //! crate directly. [`metrics`] is Aquila-specific: the OpenTelemetry
-//! instruments this service emits.
+//! instruments this service emits, and [`prometheus`] serves them for
+//! scraping when enabled.
 
 pub mod metrics;
+pub mod prometheus;
 
 pub use code0_flow::flow_telemetry::{OpenTelemetry, Telemetry, TelemetrySettings, errors};
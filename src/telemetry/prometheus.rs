@@ -0,0 +1,173 @@
+//! Prometheus scrape endpoint for the instruments in [`super::metrics`].
+//!
+//! The shared `code0_flow` telemetry setup only knows how to push metrics
+//! over OTLP, and its meter provider can't take a second reader once built.
+//! So when Prometheus is enabled, Aquila builds the meter provider itself
+//! with a Prometheus reader, plus the OTLP reader if
+//! `opentelemetry.metrics_endpoint` is also set, and `code0_flow` is told to
+//! leave metrics alone. Either way [`super::metrics::initialize`] registers
+//! the same instruments against whichever provider is global.
+
+use std::{error::Error, net::SocketAddr};
+
+use axum::{
+    Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::IntoResponse,
+    routing::get,
+};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    Resource,
+    metrics::{PeriodicReader, SdkMeterProvider},
+};
+use prometheus::{Encoder, Registry, TextEncoder};
+
+use crate::telemetry::{OpenTelemetry, errors, metrics};
+
+/// Owns the meter provider backing the Prometheus endpoint. Keep it alive
+/// for as long as metrics should be recorded and call [`Self::shutdown`] on
+/// exit so a configured OTLP reader gets its final export out.
+pub struct PrometheusMetrics {
+    registry: Registry,
+    provider: SdkMeterProvider,
+}
+
+impl PrometheusMetrics {
+    /// Builds the meter provider, installs it globally and registers Aquila's
+    /// instruments against it.
+    pub fn initialize(
+        config: &OpenTelemetry,
+        environment: &str,
+        service_version: &str,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let registry = Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()?;
+
+        let resource = Resource::builder()
+            .with_service_name(config.service_name.clone())
+            .with_attributes([
+                KeyValue::new("service.version", service_version.to_owned()),
+                KeyValue::new("deployment.environment.name", environment.to_owned()),
+            ])
+            .build();
+
+        let mut builder = SdkMeterProvider::builder()
+            .with_resource(resource)
+            .with_reader(exporter);
+
+        if config.enabled
+            && let Some(endpoint) = config.metrics_endpoint()
+        {
+            let otlp_exporter = opentelemetry_otlp::MetricExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint.to_owned())
+                .build()?;
+            builder = builder.with_reader(PeriodicReader::builder(otlp_exporter).build());
+        }
+
+        let provider = builder.build();
+        opentelemetry::global::set_meter_provider(provider.clone());
+        metrics::initialize();
+
+        Ok(Self { registry, provider })
+    }
+
+    /// Spawns the `/metrics` listener. It runs until the process exits, so
+    /// scrapes keep working while Aquila drains during shutdown.
+    pub fn serve(&self, address: SocketAddr) {
+        let registry = self.registry.clone();
+        let router = Router::new()
+            .route("/metrics", get(scrape))
+            .with_state(registry);
+
+        tokio::spawn(async move {
+            let listener = match tokio::net::TcpListener::bind(address).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    errors::record(
+                        "server",
+                        "prometheus.bind",
+                        &err,
+                        format!("address={address}"),
+                    );
+                    return;
+                }
+            };
+
+            log::info!("Prometheus metrics listening address={}/metrics", address);
+            if let Err(err) = axum::serve(listener, router).await {
+                errors::record(
+                    "server",
+                    "prometheus.serve",
+                    &err,
+                    format!("address={address}"),
+                );
+            }
+        });
+    }
+
+    /// Flushes and stops every reader on the provider.
+    pub fn shutdown(self) {
+        let _ = self.provider.shutdown();
+    }
+}
+
+/// Renders every registered metric in the Prometheus text format.
+fn encode(registry: &Registry) -> Result<String, prometheus::Error> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&registry.gather(), &mut buffer)?;
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+async fn scrape(State(registry): State<Registry>) -> impl IntoResponse {
+    match encode(&registry) {
+        Ok(body) => (
+            StatusCode::OK,
+            [(
+                header::CONTENT_TYPE,
+                TextEncoder::new().format_type().to_owned(),
+            )],
+            body,
+        ),
+        Err(err) => {
+            errors::record("telemetry", "prometheus.encode", &err, "");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                [(header::CONTENT_TYPE, "text/plain".to_owned())],
+                "failed to encode metrics".to_owned(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::metrics::MeterProvider;
+
+    use super::*;
+
+    #[test]
+    fn encode_renders_recorded_instruments() {
+        let registry = Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()
+            .expect("prometheus exporter");
+        let provider = SdkMeterProvider::builder().with_reader(exporter).build();
+
+        provider
+            .meter("aquila")
+            .u64_counter("aquila.flow.operations")
+            .build()
+            .add(2, &[KeyValue::new("operation", "insert")]);
+
+        let body = encode(&registry).expect("encoded metrics");
+        assert!(body.contains("aquila_flow_operations_total"));
+        assert!(body.contains("operation=\"insert\""));
+    }
+}
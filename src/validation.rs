@@ -1,5 +1,6 @@
 //! Validates incoming REST-flow execution requests against the JSON Schema
-//! stored in the flow's `input_schema` setting.
+//! stored in the flow's `input_schema` setting, and execution results against
+//! the flow's `output_schema`.
 //!
 //! Mirrors draco's REST adapter (`adapter/rest/src/validation.rs` and
 //! `route::extract_flow_setting_as_struct`), but here the outcome of a failed
@@ -15,6 +16,9 @@ use tucana::shared::{
     helper::value::to_json_value, value::Kind,
 };
 
+use crate::configuration::config::OutputValidationMode;
+use crate::telemetry::metrics;
+
 const REST_FLOW_TYPE: &str = "REST";
 const INPUT_SCHEMA_SETTING_ID: &str = "input_schema";
 
@@ -110,28 +114,140 @@ pub fn validate_body_against_schema(
         return Ok(());
     };
 
+    let body_value = body.cloned().unwrap_or(Value {
+        kind: Some(Kind::NullValue(0)),
+    });
+    check_schema(input_schema, body_value).map_err(|mismatch| match mismatch {
+        SchemaMismatch::InvalidSchema(msg) => BodyValidationError::InvalidSchema(msg),
+        SchemaMismatch::InvalidValue(msg) => BodyValidationError::InvalidBody(msg),
+        SchemaMismatch::Validation(msg) => BodyValidationError::Validation(msg),
+    })
+}
+
+#[derive(Debug)]
+pub enum OutputValidationError {
+    InvalidSchema(String),
+    InvalidOutput(String),
+    Validation(String),
+}
+
+impl std::fmt::Display for OutputValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSchema(msg) => write!(f, "flow output schema is invalid: {}", msg),
+            Self::InvalidOutput(msg) => write!(f, "result could not be validated: {}", msg),
+            Self::Validation(msg) => write!(f, "result failed output schema validation: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for OutputValidationError {}
+
+/// Validates the value of a successful `result` against the flow's `output_schema`. Error
+/// results, and flows without an `output_schema` (or with an empty one), pass unvalidated.
+pub fn validate_result_against_schema(
+    output_schema: Option<&Struct>,
+    result: &ExecutionResult,
+) -> Result<(), OutputValidationError> {
+    let Some(output_schema) = output_schema.filter(|schema| !schema.fields.is_empty()) else {
+        return Ok(());
+    };
+    let Some(execution_result::Result::Success(value)) = result.result.as_ref() else {
+        return Ok(());
+    };
+
+    check_schema(output_schema, value.clone()).map_err(|mismatch| match mismatch {
+        SchemaMismatch::InvalidSchema(msg) => OutputValidationError::InvalidSchema(msg),
+        SchemaMismatch::InvalidValue(msg) => OutputValidationError::InvalidOutput(msg),
+        SchemaMismatch::Validation(msg) => OutputValidationError::Validation(msg),
+    })
+}
+
+/// A result on its way to delivery, after output validation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidatedResult {
+    pub result: ExecutionResult,
+    /// Why the result doesn't match its flow's output schema, when it's
+    /// delivered anyway because the mode is `warn`.
+    pub output_mismatch: Option<String>,
+}
+
+/// Runs `result` through output validation in `mode` before it is delivered. A mismatch is
+/// logged and counted in every mode but `off`; `warn` delivers the result annotated with the
+/// mismatch, `enforce` replaces it with an `A-VALIDATION-000002` error, keeping its
+/// identifiers, timing and node results.
+pub fn apply_output_validation(
+    mode: OutputValidationMode,
+    output_schema: Option<&Struct>,
+    mut result: ExecutionResult,
+) -> ValidatedResult {
+    let error = match mode {
+        OutputValidationMode::Off => None,
+        _ => validate_result_against_schema(output_schema, &result).err(),
+    };
+    let Some(error) = error else {
+        return ValidatedResult {
+            result,
+            output_mismatch: None,
+        };
+    };
+
+    log::warn!(
+        "Execution result does not match the flow's output schema execution_id={} flow_id={} mode={} error={}",
+        result.execution_identifier,
+        result.flow_id,
+        mode,
+        error
+    );
+    metrics::output_mismatch(&mode.to_string());
+
+    if mode == OutputValidationMode::Enforce {
+        result.result = Some(execution_result::Result::Error(Error {
+            code: "A-VALIDATION-000002".to_string(),
+            category: "Internal".to_string(),
+            message: error.to_string(),
+            timestamp: epoch_millis_now(),
+            version: crate::version::runtime_version().to_string(),
+            ..Default::default()
+        }));
+        return ValidatedResult {
+            result,
+            output_mismatch: None,
+        };
+    }
+    ValidatedResult {
+        result,
+        output_mismatch: Some(error.to_string()),
+    }
+}
+
+/// Why a value didn't pass a schema, before it's mapped onto the caller's error type.
+enum SchemaMismatch {
+    InvalidSchema(String),
+    InvalidValue(String),
+    Validation(String),
+}
+
+/// Validates `value` against `schema` (a JSON Schema stored as a `shared.Struct`).
+fn check_schema(schema: &Struct, value: Value) -> Result<(), SchemaMismatch> {
     let schema_json = to_json_value(Value {
-        kind: Some(Kind::StructValue(input_schema.clone())),
+        kind: Some(Kind::StructValue(schema.clone())),
     });
     let raw = serde_json::to_string(&schema_json)
-        .map_err(|err| BodyValidationError::InvalidSchema(err.to_string()))?;
+        .map_err(|err| SchemaMismatch::InvalidSchema(err.to_string()))?;
     let schema = lupus::JsonSchema { raw };
 
-    let body_value = body.cloned().unwrap_or(Value {
-        kind: Some(Kind::NullValue(0)),
-    });
-    let body_json = to_json_value(body_value);
-    let data = json_value_to_data(body_json)?;
+    let data = json_value_to_data(to_json_value(value)).map_err(SchemaMismatch::InvalidValue)?;
 
     lupus::validation::validate_json_schema(&data, &schema)
-        .map_err(|err| BodyValidationError::Validation(err.to_string()))
+        .map_err(|err| SchemaMismatch::Validation(err.to_string()))
 }
 
 /// Mirrors lupus's internal JSON-to-`Data` conversion. We can't reuse `lupus::formats::json`
 /// directly here because the intermediate `tucana::shared::Value` type in this workspace and the
 /// one `lupus` depends on resolve to different (semver-incompatible 0.0.x) versions of the
 /// `tucana` crate, so we go through `serde_json::Value` instead, which both sides share.
-fn json_value_to_data(value: serde_json::Value) -> Result<Data, BodyValidationError> {
+fn json_value_to_data(value: serde_json::Value) -> Result<Data, String> {
     match value {
         serde_json::Value::Null => Ok(Data::Null),
         serde_json::Value::Bool(value) => Ok(Data::Bool(value)),
@@ -143,9 +259,7 @@ fn json_value_to_data(value: serde_json::Value) -> Result<Data, BodyValidationEr
             } else if let Some(value) = value.as_f64() {
                 Ok(Data::Number(Number::F64(value)))
             } else {
-                Err(BodyValidationError::InvalidBody(
-                    "unsupported JSON number".to_string(),
-                ))
+                Err("unsupported JSON number".to_string())
             }
         }
         serde_json::Value::String(value) => Ok(Data::String(value)),
@@ -321,4 +435,96 @@ mod tests {
             other => panic!("expected error result, got {:?}", other),
         }
     }
+
+    fn name_schema() -> Struct {
+        schema_struct(serde_json::json!({
+            "type": "object",
+            "required": ["name"],
+            "properties": { "name": { "type": "string" } }
+        }))
+    }
+
+    fn success_result(value: serde_json::Value) -> ExecutionResult {
+        ExecutionResult {
+            execution_identifier: "exec-1".to_string(),
+            flow_id: 42,
+            result: Some(execution_result::Result::Success(
+                tucana::shared::helper::value::from_json_value(value),
+            )),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matching_result_passes_output_validation() {
+        let schema = name_schema();
+        let result = success_result(serde_json::json!({ "name": "Ada" }));
+
+        assert!(validate_result_against_schema(Some(&schema), &result).is_ok());
+    }
+
+    #[test]
+    fn error_results_are_not_validated() {
+        let schema = name_schema();
+        let result = rejection_result(
+            "exec-1".to_string(),
+            42,
+            &BodyValidationError::Validation("bad".to_string()),
+        );
+
+        assert!(validate_result_against_schema(Some(&schema), &result).is_ok());
+    }
+
+    #[test]
+    fn warn_mode_delivers_mismatched_result_annotated() {
+        let schema = name_schema();
+        let result = success_result(serde_json::json!({ "name": 1 }));
+
+        let validated =
+            apply_output_validation(OutputValidationMode::Warn, Some(&schema), result.clone());
+
+        assert_eq!(validated.result, result);
+        let mismatch = validated
+            .output_mismatch
+            .expect("a mismatched result should be annotated");
+        assert!(mismatch.contains("output schema"));
+    }
+
+    #[test]
+    fn off_mode_neither_validates_nor_annotates() {
+        let schema = name_schema();
+        let result = success_result(serde_json::json!({ "name": 1 }));
+
+        let validated =
+            apply_output_validation(OutputValidationMode::Off, Some(&schema), result.clone());
+
+        assert_eq!(
+            validated,
+            ValidatedResult {
+                result,
+                output_mismatch: None,
+            }
+        );
+    }
+
+    #[test]
+    fn enforce_mode_turns_mismatched_result_into_error() {
+        let schema = name_schema();
+        let result = success_result(serde_json::json!({ "name": 1 }));
+
+        let validated =
+            apply_output_validation(OutputValidationMode::Enforce, Some(&schema), result);
+
+        assert_eq!(validated.output_mismatch, None);
+        let validated = validated.result;
+        assert_eq!(validated.execution_identifier, "exec-1");
+        assert_eq!(validated.flow_id, 42);
+        match validated.result {
+            Some(execution_result::Result::Error(err)) => {
+                assert_eq!(err.code, "A-VALIDATION-000002");
+                assert!(err.message.contains("output schema"));
+            }
+            other => panic!("expected error result, got {:?}", other),
+        }
+    }
 }